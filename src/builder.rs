@@ -0,0 +1,182 @@
+//! Builds sierra programs without llvm, with the same declarations and statements the lowering emits.
+//!
+//! ```
+//! use cairo_lang_sierra::extensions::core::{CoreLibfunc, CoreType};
+//! use cairo_lang_sierra::program_registry::ProgramRegistry;
+//! use llvm_to_sierra::builder::ProgramBuilder;
+//!
+//! let mut builder = ProgramBuilder::default();
+//! let felt = builder.add_type("felt252");
+//! let params = builder.add_function("add", &[felt.clone(), felt.clone()], &[felt.clone()]);
+//! let sum = builder.emit_add(&felt, &params[0], &params[1]);
+//! builder.emit_return(&[(felt, sum)]);
+//! let program = builder.build();
+//! assert_eq!(program.funcs.len(), 1);
+//! assert!(ProgramRegistry::<CoreType, CoreLibfunc>::new(&program).is_ok());
+//! ```
+
+use std::collections::HashSet;
+
+use cairo_lang_sierra::ids::{
+    ConcreteLibfuncId, ConcreteTypeId, FunctionId, GenericLibfuncId, GenericTypeId, VarId,
+};
+use cairo_lang_sierra::program::{
+    ConcreteLibfuncLongId, ConcreteTypeLongId, Function, FunctionSignature, GenBranchInfo,
+    GenBranchTarget, GenInvocation, GenStatement, GenericArg, LibfuncDeclaration, Param, Program,
+    StatementIdx, TypeDeclaration,
+};
+
+/// Sierra program under construction. Types and libfuncs are declared once, whatever the number of uses, and
+/// statements are appended to the body of the last added function.
+pub struct ProgramBuilder {
+    pub(crate) program: Program,
+    pub(crate) types: HashSet<String>,
+    pub(crate) libfuncs: HashSet<String>,
+    pub(crate) next_var: u64,
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        Self {
+            program: Program {
+                type_declarations: Vec::default(),
+                libfunc_declarations: Vec::default(),
+                statements: Vec::default(),
+                funcs: Vec::default(),
+            },
+            types: HashSet::default(),
+            libfuncs: HashSet::default(),
+            next_var: 0,
+        }
+    }
+}
+
+impl ProgramBuilder {
+    /// Declares the type without generic arguments `name` (`felt252`, `u32`) if it isn't declared yet and returns
+    /// its id.
+    pub fn add_type(&mut self, name: &str) -> ConcreteTypeId {
+        if self.types.insert(name.to_owned()) {
+            self.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(name),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string(name),
+                    generic_args: vec![],
+                },
+                declared_type_info: None,
+            });
+        }
+        ConcreteTypeId::from_string(name)
+    }
+
+    /// Adds the function `name` starting at the next statement and returns the variables of its params.
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        param_types: &[ConcreteTypeId],
+        ret_types: &[ConcreteTypeId],
+    ) -> Vec<VarId> {
+        let params = param_types
+            .iter()
+            .map(|ty| Param {
+                id: self.next_var(),
+                ty: ty.clone(),
+            })
+            .collect::<Vec<_>>();
+        let vars = params.iter().map(|param| param.id.clone()).collect();
+        self.program.funcs.push(Function {
+            id: FunctionId::from_string(name),
+            signature: FunctionSignature {
+                param_types: param_types.to_vec(),
+                ret_types: ret_types.to_vec(),
+            },
+            params,
+            entry_point: StatementIdx(self.program.statements.len()),
+        });
+        vars
+    }
+
+    /// Declares the `name<generic_args>` libfunc if it isn't declared yet and returns its id.
+    pub fn register_libfunc(
+        &mut self,
+        name: &str,
+        generic_args: Vec<GenericArg>,
+    ) -> ConcreteLibfuncId {
+        let id = if generic_args.is_empty() {
+            name.to_owned()
+        } else {
+            format!(
+                "{}<{}>",
+                name,
+                generic_args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        if self.libfuncs.insert(id.clone()) {
+            self.program.libfunc_declarations.push(LibfuncDeclaration {
+                id: ConcreteLibfuncId::from_string(&id),
+                long_id: ConcreteLibfuncLongId {
+                    generic_id: GenericLibfuncId::from_string(name),
+                    generic_args,
+                },
+            });
+        }
+        ConcreteLibfuncId::from_string(id)
+    }
+
+    /// Pushes the invocation of the libfunc `libfunc_id` that can't branch with `args` and returns its `results`
+    /// new variables.
+    pub fn push_invocation(
+        &mut self,
+        libfunc_id: ConcreteLibfuncId,
+        args: &[VarId],
+        results: usize,
+    ) -> Vec<VarId> {
+        let results = (0..results).map(|_| self.next_var()).collect::<Vec<_>>();
+        self.program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id,
+                args: args.to_vec(),
+                branches: vec![GenBranchInfo {
+                    target: GenBranchTarget::Fallthrough,
+                    results: results.clone(),
+                }],
+            }));
+        results
+    }
+
+    /// Pushes `<ty>_add` of `lhs` and `rhs` and returns the sum. Only `felt252` has an addition that can't
+    /// overflow, the integers would need the branches of `<ty>_overflowing_add`.
+    pub fn emit_add(&mut self, ty: &ConcreteTypeId, lhs: &VarId, rhs: &VarId) -> VarId {
+        let add = self.register_libfunc(&format!("{}_add", ty), vec![]);
+        self.push_invocation(add, &[lhs.clone(), rhs.clone()], 1)
+            .remove(0)
+    }
+
+    /// Stores the values (with their type) in temp vars with `store_temp` and returns them from the function.
+    pub fn emit_return(&mut self, values: &[(ConcreteTypeId, VarId)]) {
+        let stored = values
+            .iter()
+            .map(|(ty, var)| {
+                let store_temp =
+                    self.register_libfunc("store_temp", vec![GenericArg::Type(ty.clone())]);
+                self.push_invocation(store_temp, std::slice::from_ref(var), 1)
+                    .remove(0)
+            })
+            .collect();
+        self.program.statements.push(GenStatement::Return(stored));
+    }
+
+    /// The program built so far.
+    pub fn build(self) -> Program {
+        self.program
+    }
+
+    fn next_var(&mut self) -> VarId {
+        self.next_var += 1;
+        VarId::new(self.next_var - 1)
+    }
+}
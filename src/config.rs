@@ -0,0 +1,303 @@
+use std::path::{Path, PathBuf};
+
+/// Options controlling the compilation, parsed from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The llvm ir file to lower, `-` reads it from stdin.
+    pub input: Option<PathBuf>,
+    /// Prefix each statement with the llvm block and opcode it was lowered from.
+    pub annotate: bool,
+    /// Don't type check the generated program before printing it (`--no-verify`), faster but an invalid lowering is
+    /// printed instead of failing. Programs with unsupported placeholders are never checked.
+    pub no_verify: bool,
+    /// Emit an `unsupported_<opcode>` placeholder for each instruction that can't be lowered instead of skipping it
+    /// or failing. The output won't type check but keeps the structure of the function.
+    pub keep_unsupported_as_nop: bool,
+    /// Print the program without debug names, type infos and blank lines (`--compact`). The default (`--pretty`)
+    /// keeps everything.
+    pub compact: bool,
+    /// File (or directory) the output is written to instead of stdout (`--output <path>`), see
+    /// [`Config::output_path`].
+    pub output: Option<PathBuf>,
+    /// Print the variables without debug names (`--no-debug-names`). Otherwise each variable gets a name unique in
+    /// its function, anonymous llvm values are named after their id.
+    pub no_debug_names: bool,
+    /// What to print instead of the program (`--emit <kind>`).
+    pub emit: Option<Emit>,
+    /// Lower shifts by a non constant amount to a loop (`--dynamic-shifts`) instead of leaving them unsupported.
+    pub dynamic_shifts: bool,
+    /// Reduce the result of the arithmetic on integers narrower than their sierra type (`i3` is lowered to a `u8`)
+    /// to their llvm width (`--mask-narrow-ints`). Otherwise it only wraps at the bounds of the sierra type.
+    pub mask_narrow_ints: bool,
+    /// Emit an empty program for a module without any function (`--allow-empty`) instead of failing.
+    pub allow_empty: bool,
+    /// Fail instead of warning about the module targeting an unexpected triple (`--strict`).
+    pub strict: bool,
+    /// Number of threads lowering the functions (`--threads N`), the functions are lowered one after the other with
+    /// one (or zero) thread.
+    pub threads: usize,
+    /// Skip the functions that fail to lower (and their callers) instead of aborting (`--allow-partial`), a summary
+    /// of what was skipped is printed on stderr.
+    pub allow_partial: bool,
+    /// Functions that are external entry points of the contract (`--entry name[,name...]`), on top of the ones with
+    /// the `"starknet-external"` attribute.
+    pub entry_points: Vec<String>,
+    /// Print the functions of the module with their sierra signature and block count instead of lowering them
+    /// (`--list-functions`).
+    pub list_functions: bool,
+    /// Directory each function is written to as its own program (`<dir>/<function>.sierra`) instead of printing the
+    /// whole program (`--split-output <dir>`).
+    pub split_output: Option<PathBuf>,
+}
+
+/// Alternative outputs of `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The llvm source line of each statement as json (`debug-map`), needs the llvm ir to carry `!dbg` locations.
+    DebugMap,
+    /// How many times each libfunc is invoked, most used first (`sierra-stats`).
+    SierraStats,
+    /// The sierra variable of each llvm value of each function as json (`var-map`), the module is then lowered on a
+    /// single thread.
+    VarMap,
+    /// The entry points table of the contract class as json (`entry-points`), see [`Config::entry_points`].
+    EntryPoints,
+}
+
+impl Config {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--annotate" => config.annotate = true,
+                // Verifying is the default, the flag is kept for the existing scripts.
+                "--verify" => config.no_verify = false,
+                "--no-verify" => config.no_verify = true,
+                "--keep-unsupported-as-nop" => config.keep_unsupported_as_nop = true,
+                "--compact" => config.compact = true,
+                "--no-debug-names" => config.no_debug_names = true,
+                "--pretty" => config.compact = false,
+                "--allow-partial" => config.allow_partial = true,
+                "--allow-empty" => config.allow_empty = true,
+                "--list-functions" => config.list_functions = true,
+                "--strict" => config.strict = true,
+                "--dynamic-shifts" => config.dynamic_shifts = true,
+                "--mask-narrow-ints" => config.mask_narrow_ints = true,
+                "--output" => {
+                    config.output = Some(
+                        args.next()
+                            .map(PathBuf::from)
+                            .ok_or_else(|| "`--output` expects a path".to_owned())?,
+                    );
+                }
+                "--split-output" => {
+                    config.split_output = Some(
+                        args.next()
+                            .map(PathBuf::from)
+                            .ok_or_else(|| "`--split-output` expects a directory".to_owned())?,
+                    );
+                }
+                "--threads" => {
+                    config.threads = args
+                        .next()
+                        .and_then(|threads| threads.parse().ok())
+                        .ok_or_else(|| "`--threads` expects a number".to_owned())?;
+                }
+                "--entry" => {
+                    let names = args
+                        .next()
+                        .ok_or_else(|| "`--entry` expects function names".to_owned())?;
+                    config
+                        .entry_points
+                        .extend(names.split(',').map(str::to_owned));
+                }
+                "--emit" => match args.next().as_deref() {
+                    Some("debug-map") => config.emit = Some(Emit::DebugMap),
+                    Some("sierra-stats") => config.emit = Some(Emit::SierraStats),
+                    Some("entry-points") => config.emit = Some(Emit::EntryPoints),
+                    Some("var-map") => config.emit = Some(Emit::VarMap),
+                    Some(kind) => return Err(format!("unknown emit kind `{}`", kind)),
+                    None => return Err("`--emit` expects a value".to_owned()),
+                },
+                _ if arg == "-" || !arg.starts_with('-') => {
+                    if config.input.is_some() {
+                        return Err(format!(
+                            "unexpected argument `{}`, the input is already given",
+                            arg
+                        ));
+                    }
+                    config.input = Some(PathBuf::from(arg));
+                }
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Whether the output is the lowered program itself, not something `--emit`, `--annotate`, `--list-functions` or
+    /// `--split-output` derive from the lowering.
+    pub fn emits_program(&self) -> bool {
+        self.emit.is_none() && !self.annotate && !self.list_functions && self.split_output.is_none()
+    }
+
+    /// File the output of lowering `input` is written to, `None` to print it. A directory gets `<input-stem>.sierra`
+    /// in it and a path without extension gets the `.sierra` one. The json of `--emit debug-map` uses `.sierra.json`,
+    /// the ones of `--emit entry-points` and `--emit var-map` `.entry_points.json` and `.var_map.json` and the counts
+    /// of `--emit sierra-stats` `.sierra_stats.txt`.
+    pub fn output_path(&self, input: &Path) -> Option<PathBuf> {
+        let output = self.output.as_ref()?;
+        let extension = match self.emit {
+            Some(Emit::DebugMap) => "sierra.json",
+            Some(Emit::EntryPoints) => "entry_points.json",
+            Some(Emit::VarMap) => "var_map.json",
+            Some(Emit::SierraStats) => "sierra_stats.txt",
+            None => "sierra",
+        };
+        if output.is_dir() {
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            Some(output.join(format!("{}.{}", stem, extension)))
+        } else if output.extension().is_none() {
+            Some(output.with_extension(extension))
+        } else {
+            Some(output.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn output_extension_follows_the_emitted_kind() {
+        let input = Path::new("dir/fib.ll");
+        for (emit, extension) in [
+            (None, "sierra"),
+            (Some("debug-map"), "sierra.json"),
+            (Some("entry-points"), "entry_points.json"),
+            (Some("var-map"), "var_map.json"),
+            (Some("sierra-stats"), "sierra_stats.txt"),
+        ] {
+            let mut args = vec!["fib.ll", "--output", "out/fib"];
+            args.extend(emit.iter().flat_map(|emit| ["--emit", *emit]));
+            let config = parse(&args).unwrap();
+            assert_eq!(
+                config.output_path(input),
+                Some(PathBuf::from(format!("out/fib.{}", extension))),
+                "{:?}",
+                emit
+            );
+        }
+        let config = parse(&[
+            "fib.ll",
+            "--output",
+            "out/stats.log",
+            "--emit",
+            "sierra-stats",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.output_path(input),
+            Some(PathBuf::from("out/stats.log"))
+        );
+        assert_eq!(parse(&["fib.ll"]).unwrap().output_path(input), None);
+    }
+
+    #[test]
+    fn output_directory_gets_a_file_named_after_the_input() {
+        let dir = std::env::temp_dir();
+        let input = Path::new("dir/fib.ll");
+        let config = parse(&["fib.ll", "--output", dir.to_str().unwrap()]).unwrap();
+        assert_eq!(config.output_path(input), Some(dir.join("fib.sierra")));
+        let config = parse(&[
+            "fib.ll",
+            "--output",
+            dir.to_str().unwrap(),
+            "--emit",
+            "debug-map",
+        ])
+        .unwrap();
+        assert_eq!(config.output_path(input), Some(dir.join("fib.sierra.json")));
+    }
+
+    #[test]
+    fn emit_takes_a_known_kind() {
+        for (kind, emit) in [
+            ("debug-map", Emit::DebugMap),
+            ("sierra-stats", Emit::SierraStats),
+            ("entry-points", Emit::EntryPoints),
+            ("var-map", Emit::VarMap),
+        ] {
+            assert_eq!(parse(&["fib.ll", "--emit", kind]).unwrap().emit, Some(emit));
+        }
+        assert_eq!(parse(&["fib.ll"]).unwrap().emit, None);
+        assert_eq!(
+            parse(&["fib.ll", "--emit", "llvm"]).unwrap_err(),
+            "unknown emit kind `llvm`"
+        );
+        assert_eq!(
+            parse(&["fib.ll", "--emit"]).unwrap_err(),
+            "`--emit` expects a value"
+        );
+    }
+
+    #[test]
+    fn verification_is_on_unless_no_verify_comes_last() {
+        assert!(!parse(&["fib.ll"]).unwrap().no_verify);
+        assert!(parse(&["fib.ll", "--no-verify"]).unwrap().no_verify);
+        assert!(
+            !parse(&["fib.ll", "--no-verify", "--verify"])
+                .unwrap()
+                .no_verify
+        );
+        assert!(
+            parse(&["--verify", "fib.ll", "--no-verify"])
+                .unwrap()
+                .no_verify
+        );
+    }
+
+    #[test]
+    fn allow_partial_is_off_by_default() {
+        assert!(!parse(&["fib.ll"]).unwrap().allow_partial);
+        let config = parse(&["--allow-partial", "fib.ll"]).unwrap();
+        assert!(config.allow_partial);
+        assert_eq!(config.input, Some(PathBuf::from("fib.ll")));
+    }
+
+    #[test]
+    fn threads_expects_a_number() {
+        assert_eq!(parse(&["fib.ll", "--threads", "4"]).unwrap().threads, 4);
+        for args in [
+            &["fib.ll", "--threads", "four"][..],
+            &["fib.ll", "--threads"],
+        ] {
+            assert_eq!(parse(args).unwrap_err(), "`--threads` expects a number");
+        }
+    }
+
+    #[test]
+    fn dynamic_shifts_is_off_by_default() {
+        assert!(!parse(&["fib.ll"]).unwrap().dynamic_shifts);
+        assert!(
+            parse(&["fib.ll", "--dynamic-shifts"])
+                .unwrap()
+                .dynamic_shifts
+        );
+    }
+
+    #[test]
+    fn keep_unsupported_as_nop_is_off_by_default() {
+        assert!(!parse(&["fib.ll"]).unwrap().keep_unsupported_as_nop);
+        assert!(
+            parse(&["fib.ll", "--keep-unsupported-as-nop"])
+                .unwrap()
+                .keep_unsupported_as_nop
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::Display;
+
+use cairo_lang_sierra::program::Program;
+
+/// Describes the first divergence between `expected` and `actual`, section by section, along with the item preceding
+/// it for context. Ids are compared without their debug names. Returns `None` if both programs are the same.
+pub fn first_difference(expected: &Program, actual: &Program) -> Option<String> {
+    diff_section(
+        "type declaration",
+        &expected.type_declarations,
+        &actual.type_declarations,
+    )
+    .or_else(|| {
+        diff_section(
+            "libfunc declaration",
+            &expected.libfunc_declarations,
+            &actual.libfunc_declarations,
+        )
+    })
+    .or_else(|| diff_section("statement", &expected.statements, &actual.statements))
+    .or_else(|| diff_section("function", &expected.funcs, &actual.funcs))
+}
+
+/// Compares the items of a section in order, the lowering is deterministic so a different order is a divergence.
+fn diff_section<T: Display + PartialEq>(
+    section: &str,
+    expected: &[T],
+    actual: &[T],
+) -> Option<String> {
+    let idx =
+        (0..expected.len().max(actual.len())).find(|&idx| expected.get(idx) != actual.get(idx))?;
+    let show = |item: Option<&T>| item.map_or("<none>".to_owned(), |item| item.to_string());
+    let context = match idx.checked_sub(1) {
+        Some(prev) => format!("after `{}`", show(expected.get(prev))),
+        None => "at the start".to_owned(),
+    };
+    Some(format!(
+        "{} #{} differs {}:\n  expected: {}\n  actual:   {}",
+        section,
+        idx,
+        context,
+        show(expected.get(idx)),
+        show(actual.get(idx))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo_lang_sierra::program::GenStatement;
+    use cairo_lang_sierra::ProgramParser;
+
+    use super::*;
+
+    #[test]
+    fn reports_the_first_different_statement() {
+        let source =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/fib.sierra")).unwrap();
+        let expected = ProgramParser::new().parse(&source).unwrap();
+        assert_eq!(first_difference(&expected, &expected.clone()), None);
+        let mut actual = expected.clone();
+        actual.statements[29] = GenStatement::Return(vec![]);
+        let difference = first_difference(&expected, &actual).unwrap();
+        assert!(
+            difference.starts_with("statement #29 differs after"),
+            "{}",
+            difference
+        );
+        assert!(difference.ends_with("actual:   return()"), "{}", difference);
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use inkwell::values::InstructionOpcode;
+
+/// Where a lowering error happened: the llvm function and, if the ir carries `!dbg` locations, the source line of
+/// the instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub function: String,
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in `{}`", self.function)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors that can happen while lowering llvm ir to sierra.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoweringError {
+    /// The instruction has no sierra equivalent. Emitting nothing for it would produce silently wrong code.
+    UnsupportedOpcode {
+        opcode: InstructionOpcode,
+        instruction: String,
+        span: Span,
+    },
+    /// The block doesn't end with a statement that leaves it (jump, return or panic).
+    MissingTerminator { block: String, span: Span },
+    /// The generated program doesn't type check against the sierra core library.
+    InvalidProgram { reason: String },
+    /// A `ret` returns values whose sierra types differ from the return types of the function.
+    ReturnTypeMismatch {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    /// A `getelementptr` into a struct with an index that isn't a const, the field can't be known.
+    DynamicStructIndex { instruction: String, span: Span },
+    /// The module targets a triple whose pointer size or data layout the lowering doesn't expect (`--strict`).
+    UnexpectedTargetTriple { triple: String },
+    /// The input file couldn't be read or doesn't contain valid llvm ir.
+    Input { path: String, reason: String },
+    /// The output file couldn't be written.
+    Output { path: String, reason: String },
+    /// The module doesn't define any function, the program would be empty.
+    NoFunctions,
+    /// A call to a variadic function, the variadic arguments can't be passed to a sierra function.
+    VariadicCall { callee: String, span: Span },
+    /// An instruction uses a value that wasn't lowered, its instruction is dropped or comes later in the blocks.
+    MissingVariable {
+        value: String,
+        instruction: String,
+        span: Span,
+    },
+    /// A function given with `--entry` isn't defined by the module.
+    UnknownEntryPoint { name: String },
+    /// A constant that isn't a decimal integer (a hexadecimal float) can't be a sierra const.
+    UnsupportedConst {
+        value: String,
+        instruction: String,
+        span: Span,
+    },
+    /// An instruction consumes a value of a type that can't be duplicated (sierra arrays) which is used again later.
+    UncopyableValue {
+        value: String,
+        instruction: String,
+        span: Span,
+    },
+    /// A local is loaded before any store to it and its type (an array field) has no default value to read.
+    UninitializedLocal {
+        local: String,
+        instruction: String,
+        span: Span,
+    },
+    /// An instruction uses a struct built by `insertvalue` with a field that was never set.
+    UnsetStructField {
+        value: String,
+        instruction: String,
+        span: Span,
+    },
+    /// A thread lowering functions with `--threads` panicked.
+    ThreadPanicked { reason: String },
+}
+
+impl fmt::Display for LoweringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoweringError::UnsupportedOpcode {
+                opcode,
+                instruction,
+                span,
+            } => {
+                write!(
+                    f,
+                    "{}: unsupported opcode {:?} in `{}`",
+                    span,
+                    opcode,
+                    instruction.trim()
+                )
+            }
+            LoweringError::MissingTerminator { block, span } => {
+                write!(
+                    f,
+                    "{}: block `{}` doesn't end with a terminator",
+                    span, block
+                )
+            }
+            LoweringError::InvalidProgram { reason } => {
+                write!(f, "generated program is invalid: {}", reason)
+            }
+            LoweringError::ReturnTypeMismatch {
+                expected,
+                found,
+                span,
+            } => write!(
+                f,
+                "{}: returns ({}) but is declared to return ({})",
+                span, found, expected
+            ),
+            LoweringError::DynamicStructIndex { instruction, span } => write!(
+                f,
+                "{}: dynamic index into a struct in `{}`",
+                span,
+                instruction.trim()
+            ),
+            LoweringError::UnexpectedTargetTriple { triple } => {
+                write!(f, "unexpected target triple `{}`", triple)
+            }
+            LoweringError::Input { path, reason } => {
+                write!(f, "couldn't read `{}`: {}", path, reason)
+            }
+            LoweringError::Output { path, reason } => {
+                write!(f, "couldn't write `{}`: {}", path, reason)
+            }
+            LoweringError::NoFunctions => write!(f, "the module doesn't define any function"),
+            LoweringError::VariadicCall { callee, span } => write!(
+                f,
+                "{}: call to the variadic function `{}`, sierra functions take a fixed number of arguments",
+                span, callee
+            ),
+            LoweringError::MissingVariable {
+                value,
+                instruction,
+                span,
+            } => write!(
+                f,
+                "{}: `{}` uses `{}` which wasn't lowered",
+                span,
+                instruction.trim(),
+                value
+            ),
+            LoweringError::UnknownEntryPoint { name } => {
+                write!(f, "entry point `{}` isn't defined by the module", name)
+            }
+            LoweringError::UnsupportedConst {
+                value,
+                instruction,
+                span,
+            } => write!(
+                f,
+                "{}: `{}` uses the constant `{}` which isn't an integer",
+                span,
+                instruction.trim(),
+                value
+            ),
+            LoweringError::UncopyableValue {
+                value,
+                instruction,
+                span,
+            } => write!(
+                f,
+                "{}: `{}` consumes `{}` which is used again but can't be duplicated",
+                span,
+                instruction.trim(),
+                value
+            ),
+            LoweringError::UninitializedLocal {
+                local,
+                instruction,
+                span,
+            } => write!(
+                f,
+                "{}: `{}` loads `{}` before it's stored and its type has no default value",
+                span,
+                instruction.trim(),
+                local
+            ),
+            LoweringError::UnsetStructField {
+                value,
+                instruction,
+                span,
+            } => write!(
+                f,
+                "{}: `{}` uses the struct `{}` with a field that was never set",
+                span,
+                instruction.trim(),
+                value
+            ),
+            LoweringError::ThreadPanicked { reason } => {
+                write!(f, "a lowering thread panicked: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoweringError {}
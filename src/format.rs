@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use cairo_lang_sierra::ids::VarId;
+use cairo_lang_sierra::program::{GenStatement, GenericArg, Program, StatementIdx};
+use num_bigint::BigUint;
+use sha3::{Digest, Keccak256};
+use smol_str::SmolStr;
+
+/// Returns a copy of `program` without any debug name nor declared type info, ids are then printed as `[id]`.
+pub fn strip_debug_info(program: &Program) -> Program {
+    let mut program = program.clone();
+    let strip_arg = |arg: &mut GenericArg| match arg {
+        GenericArg::UserType(id) => id.debug_name = None,
+        GenericArg::Type(id) => id.debug_name = None,
+        GenericArg::UserFunc(id) => id.debug_name = None,
+        GenericArg::Libfunc(id) => id.debug_name = None,
+        GenericArg::Value(_) => (),
+    };
+    for declaration in &mut program.type_declarations {
+        declaration.id.debug_name = None;
+        declaration.declared_type_info = None;
+        declaration
+            .long_id
+            .generic_args
+            .iter_mut()
+            .for_each(strip_arg);
+    }
+    for declaration in &mut program.libfunc_declarations {
+        declaration.id.debug_name = None;
+        declaration
+            .long_id
+            .generic_args
+            .iter_mut()
+            .for_each(strip_arg);
+    }
+    for statement in &mut program.statements {
+        match statement {
+            GenStatement::Invocation(invocation) => {
+                invocation.libfunc_id.debug_name = None;
+                invocation
+                    .args
+                    .iter_mut()
+                    .for_each(|var| var.debug_name = None);
+                for branch in &mut invocation.branches {
+                    branch
+                        .results
+                        .iter_mut()
+                        .for_each(|var| var.debug_name = None);
+                }
+            }
+            GenStatement::Return(vars) => vars.iter_mut().for_each(|var| var.debug_name = None),
+        }
+    }
+    for func in &mut program.funcs {
+        func.id.debug_name = None;
+        func.signature
+            .param_types
+            .iter_mut()
+            .chain(func.signature.ret_types.iter_mut())
+            .for_each(|ty| ty.debug_name = None);
+        for param in &mut func.params {
+            param.id.debug_name = None;
+            param.ty.debug_name = None;
+        }
+    }
+    program
+}
+
+/// Minimal textual form of `program`: no debug info and no blank lines between the sections.
+pub fn compact_program(program: &Program) -> String {
+    strip_debug_info(program)
+        .to_string()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .fold(String::new(), |out, line| out + line + "\n")
+}
+
+/// Number of invocations of each generic libfunc in `program`, one `<count> <libfunc>` line each, most invoked first.
+pub fn libfunc_stats(program: &Program) -> String {
+    let generic_ids = program
+        .libfunc_declarations
+        .iter()
+        .map(|declaration| (&declaration.id, declaration.long_id.generic_id.to_string()))
+        .collect::<HashMap<_, _>>();
+    let mut counts = HashMap::<String, usize>::new();
+    for statement in &program.statements {
+        if let GenStatement::Invocation(invocation) = statement {
+            let generic_id = generic_ids
+                .get(&invocation.libfunc_id)
+                .cloned()
+                .unwrap_or_else(|| invocation.libfunc_id.to_string());
+            *counts.entry(generic_id).or_default() += 1;
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(lhs_id, lhs), (rhs_id, rhs)| rhs.cmp(lhs).then_with(|| lhs_id.cmp(rhs_id)));
+    counts
+        .into_iter()
+        .fold(String::new(), |out, (generic_id, count)| {
+            out + &format!("{:>6} {}\n", count, generic_id)
+        })
+}
+
+/// Calls `f` on every variable of `statement`.
+fn for_each_var(statement: &mut GenStatement<StatementIdx>, mut f: impl FnMut(&mut VarId)) {
+    match statement {
+        GenStatement::Invocation(invocation) => {
+            invocation.args.iter_mut().for_each(&mut f);
+            for branch in &mut invocation.branches {
+                branch.results.iter_mut().for_each(&mut f);
+            }
+        }
+        GenStatement::Return(vars) => vars.iter_mut().for_each(f),
+    }
+}
+
+/// Returns a copy of `program` where every variable has a debug name, unique in its function. Variables keep the
+/// name of their llvm value when no other variable of the function has it, anonymous ones are named `v<id>` and the
+/// others get their id as suffix (`x_4`). The name a variable first appears with is the one kept.
+pub fn name_variables(program: &Program) -> Program {
+    let mut program = program.clone();
+    let mut entry_points = program
+        .funcs
+        .iter()
+        .map(|func| func.entry_point.0)
+        .collect::<Vec<_>>();
+    entry_points.sort_unstable();
+    // The statements of a function go from its entry point to the next one.
+    let function_of = |statement: usize| entry_points.partition_point(|entry| *entry <= statement);
+    let mut first_names = HashMap::<usize, BTreeMap<u64, Option<SmolStr>>>::new();
+    for func in &program.funcs {
+        let names = first_names
+            .entry(function_of(func.entry_point.0))
+            .or_default();
+        for param in &func.params {
+            names
+                .entry(param.id.id)
+                .or_insert_with(|| param.id.debug_name.clone());
+        }
+    }
+    for (idx, statement) in program.statements.iter_mut().enumerate() {
+        let names = first_names.entry(function_of(idx)).or_default();
+        for_each_var(statement, |var| {
+            names
+                .entry(var.id)
+                .or_insert_with(|| var.debug_name.clone().filter(|name| !name.is_empty()));
+        });
+    }
+    let unique_names = first_names
+        .into_iter()
+        .map(|(function, names)| (function, unique_names(names)))
+        .collect::<HashMap<_, _>>();
+    for func in &mut program.funcs {
+        let names = &unique_names[&function_of(func.entry_point.0)];
+        for param in &mut func.params {
+            param.id.debug_name = Some(names[&param.id.id].clone());
+        }
+    }
+    for (idx, statement) in program.statements.iter_mut().enumerate() {
+        let names = &unique_names[&function_of(idx)];
+        for_each_var(statement, |var| {
+            var.debug_name = Some(names[&var.id].clone())
+        });
+    }
+    program
+}
+
+/// Gives a distinct name to each variable of a function from the name it appears with, see [`name_variables`].
+fn unique_names(names: BTreeMap<u64, Option<SmolStr>>) -> HashMap<u64, SmolStr> {
+    let mut holders = HashMap::<&SmolStr, usize>::new();
+    for name in names.values().flatten() {
+        *holders.entry(name).or_default() += 1;
+    }
+    let mut used = names
+        .values()
+        .flatten()
+        .filter(|name| holders[name] == 1)
+        .cloned()
+        .collect::<HashSet<_>>();
+    let mut unique = HashMap::new();
+    for (id, name) in &names {
+        let name = match name {
+            Some(name) if holders[name] == 1 => name.clone(),
+            _ => {
+                let mut candidate = match name {
+                    Some(name) => format!("{}_{}", name, id),
+                    None => format!("v{}", id),
+                };
+                while used.contains(candidate.as_str()) {
+                    candidate = format!("{}_{}", candidate, id);
+                }
+                let candidate = SmolStr::from(candidate);
+                used.insert(candidate.clone());
+                candidate
+            }
+        };
+        unique.insert(*id, name);
+    }
+    unique
+}
+
+/// Debug name of each variable of each function of `program`, by function name and variable id.
+pub fn variable_names(program: &Program) -> HashMap<String, HashMap<u64, SmolStr>> {
+    let mut entry_points = program
+        .funcs
+        .iter()
+        .map(|func| func.entry_point.0)
+        .collect::<Vec<_>>();
+    entry_points.sort_unstable();
+    let mut names = HashMap::<String, HashMap<u64, SmolStr>>::new();
+    for func in &program.funcs {
+        let Some(function) = &func.id.debug_name else {
+            continue;
+        };
+        let function_names = names.entry(function.to_string()).or_default();
+        let mut insert = |var: &mut VarId| {
+            if let Some(name) = &var.debug_name {
+                function_names.entry(var.id).or_insert_with(|| name.clone());
+            }
+        };
+        for param in &func.params {
+            insert(&mut param.id.clone());
+        }
+        let start = func.entry_point.0;
+        let end = entry_points
+            .iter()
+            .find(|entry| **entry > start)
+            .copied()
+            .unwrap_or(program.statements.len());
+        for statement in &program.statements[start..end] {
+            for_each_var(&mut statement.clone(), &mut insert);
+        }
+    }
+    names
+}
+
+/// Returns a copy of `program` without the debug names of its variables, they're printed as `[id]`.
+pub fn strip_variable_names(program: &Program) -> Program {
+    let mut program = program.clone();
+    for statement in &mut program.statements {
+        for_each_var(statement, |var| var.debug_name = None);
+    }
+    for func in &mut program.funcs {
+        func.params
+            .iter_mut()
+            .for_each(|param| param.id.debug_name = None);
+    }
+    program
+}
+
+/// Selector of the entry point `name` in a contract class: the keccak256 of the name truncated to 250 bits.
+pub fn entry_point_selector(name: &str) -> BigUint {
+    let hash = BigUint::from_bytes_be(&Keccak256::digest(name.as_bytes()));
+    hash & ((BigUint::from(1u8) << 250) - 1u8)
+}
+
+/// Json entry points table of a contract class with the `entry_points` functions of `program` as external ones,
+/// like `{"EXTERNAL": [{"selector": "0x1234", "function": "transfer", "entry_point": 12}]}`, sorted by selector.
+/// Entry points that aren't in the program (skipped with `--allow-partial`) are left out.
+pub fn entry_points_json(program: &Program, entry_points: &[String]) -> String {
+    let mut entries = entry_points
+        .iter()
+        .filter_map(|name| {
+            let function = program
+                .funcs
+                .iter()
+                .find(|function| function.id.debug_name.as_deref() == Some(name.as_str()))?;
+            Some((entry_point_selector(name), name, function.entry_point))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries.dedup();
+    let entries = entries
+        .into_iter()
+        .map(|(selector, name, entry_point)| {
+            format!(
+                "{{\"selector\": \"{:#x}\", \"function\": \"{}\", \"entry_point\": {}}}",
+                selector, name, entry_point.0
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{\"EXTERNAL\": [{}]}}", entries.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo_lang_sierra::ProgramParser;
+    use num_bigint::BigInt;
+
+    use super::*;
+
+    #[test]
+    fn stats_count_the_invocations_of_each_generic_libfunc() {
+        let program = ProgramParser::new()
+            .parse(
+                "type u32 = u32;\n\
+                 libfunc dup<u32> = dup<u32>;\n\
+                 libfunc drop<u32> = drop<u32>;\n\
+                 libfunc store_temp<u32> = store_temp<u32>;\n\
+                 dup<u32>([0]) -> ([0], [1]);\n\
+                 store_temp<u32>([0]) -> ([0]);\n\
+                 store_temp<u32>([1]) -> ([1]);\n\
+                 drop<u32>([1]) -> ();\n\
+                 return([0]);\n\
+                 f@0([0]: u32) -> (u32);\n",
+            )
+            .unwrap();
+        assert_eq!(
+            libfunc_stats(&program),
+            "     2 store_temp\n     1 drop\n     1 dup\n"
+        );
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(7)]),
+            Ok(vec![BigInt::from(7)])
+        );
+    }
+
+    #[test]
+    fn anonymous_and_clashing_variables_get_unique_names() {
+        let mut program = ProgramParser::new()
+            .parse(
+                "type u32 = u32;\n\
+                 libfunc dup<u32> = dup<u32>;\n\
+                 libfunc drop<u32> = drop<u32>;\n\
+                 dup<u32>([0]) -> ([1], [2]);\n\
+                 drop<u32>([2]) -> ();\n\
+                 return([1]);\n\
+                 f@0([0]: u32) -> (u32);\n",
+            )
+            .unwrap();
+        program.funcs[0].params[0].id.debug_name = Some("x".into());
+        let GenStatement::Invocation(dup) = &mut program.statements[0] else {
+            unreachable!();
+        };
+        dup.branches[0].results[0].debug_name = Some("x".into());
+        let named = name_variables(&program);
+        let names = &variable_names(&named)["f"];
+        assert_eq!(names[&0], "x_0");
+        assert_eq!(names[&1], "x_1");
+        assert_eq!(names[&2], "v2");
+        assert_eq!(name_variables(&program), named);
+        assert!(variable_names(&strip_variable_names(&named))
+            .values()
+            .all(|names| names.is_empty()));
+    }
+}
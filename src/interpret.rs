@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use cairo_lang_sierra::ids::{ConcreteLibfuncId, ConcreteTypeId, FunctionId, VarId};
+use cairo_lang_sierra::program::{
+    GenBranchTarget, GenStatement, GenericArg, Program, StatementIdx,
+};
+use num_bigint::BigInt;
+
+use crate::types::BUILTINS;
+
+/// Statements a run can execute before it's considered stuck in a loop.
+const MAX_STEPS: usize = 1_000_000;
+
+/// Value of a sierra variable while running a program.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(BigInt),
+    Enum(usize, Box<Value>),
+    Struct(Vec<Value>),
+    Array(Vec<Value>),
+    Builtin,
+    /// Local allocated by `alloc_local` and not stored yet.
+    Uninitialized,
+}
+
+/// Runs the function `name` of `program` with the integer arguments `args` and returns the integers it returns. The
+/// builtins are passed and returned on their own, `bool`s (any enum of units) are their variant index and structs are
+/// returned as their fields. Only the libfuncs the lowering emits for integers and control flow are interpreted, gas
+/// is never exhausted.
+pub fn run(program: &Program, name: &str, args: &[BigInt]) -> Result<Vec<BigInt>, String> {
+    run_with(program, name, args, false)
+}
+
+/// [`run`] that also fails when a function returns while variables are left unconsumed, sierra variables being
+/// linear. Values read on some paths only are still left on the others, this is for the lowerings that consume every
+/// variable on every path.
+pub fn run_linear(program: &Program, name: &str, args: &[BigInt]) -> Result<Vec<BigInt>, String> {
+    run_with(program, name, args, true)
+}
+
+fn run_with(
+    program: &Program,
+    name: &str,
+    args: &[BigInt],
+    linear: bool,
+) -> Result<Vec<BigInt>, String> {
+    let mut interpreter = Interpreter {
+        program,
+        steps: 0,
+        linear,
+    };
+    let function = FunctionId::from_string(name);
+    let mut args = args.iter().cloned();
+    let param_types = &program
+        .funcs
+        .iter()
+        .find(|func| func.id == function)
+        .ok_or_else(|| format!("no function `{}`", name))?
+        .signature
+        .param_types;
+    let values = param_types
+        .iter()
+        .map(|ty| match interpreter.generic_type(ty) {
+            builtin if BUILTINS.contains(&builtin.as_str()) => Ok(Value::Builtin),
+            _ => args
+                .next()
+                .map(Value::Int)
+                .ok_or_else(|| format!("missing argument of type `{}`", ty)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut returned = vec![];
+    for value in interpreter.call(&function, values)? {
+        returned_ints(value, &mut returned)?;
+    }
+    Ok(returned)
+}
+
+/// Appends the integers of the returned `value` to `ints`, the fields of a struct in order.
+fn returned_ints(value: Value, ints: &mut Vec<BigInt>) -> Result<(), String> {
+    match value {
+        Value::Builtin => (),
+        Value::Int(value) => ints.push(value),
+        Value::Enum(variant, _) => ints.push(BigInt::from(variant)),
+        Value::Struct(fields) => {
+            for field in fields {
+                returned_ints(field, ints)?;
+            }
+        }
+        value => return Err(format!("can't return {:?}", value)),
+    }
+    Ok(())
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    steps: usize,
+    /// Whether returning with variables left is an error.
+    linear: bool,
+}
+
+impl Interpreter<'_> {
+    fn call(&mut self, function: &FunctionId, args: Vec<Value>) -> Result<Vec<Value>, String> {
+        let function = self
+            .program
+            .funcs
+            .iter()
+            .find(|func| func.id == *function)
+            .ok_or_else(|| format!("no function `{}`", function))?;
+        let mut vars = function
+            .params
+            .iter()
+            .map(|param| param.id.clone())
+            .zip(args)
+            .collect::<HashMap<_, _>>();
+        let StatementIdx(mut pc) = function.entry_point;
+        loop {
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Err("too many steps".to_owned());
+            }
+            let statement = self
+                .program
+                .statements
+                .get(pc)
+                .ok_or_else(|| format!("no statement {}", pc))?;
+            let invocation = match statement {
+                GenStatement::Return(returned) => {
+                    let returned = take(&mut vars, returned)?;
+                    if self.linear && !vars.is_empty() {
+                        let mut left = vars.keys().map(|var| var.to_string()).collect::<Vec<_>>();
+                        left.sort();
+                        return Err(format!(
+                            "statement {}: variables left unconsumed: {}",
+                            pc,
+                            left.join(", ")
+                        ));
+                    }
+                    return Ok(returned);
+                }
+                GenStatement::Invocation(invocation) => invocation,
+            };
+            let args = take(&mut vars, &invocation.args)?;
+            let (branch, results) = self
+                .invoke(&invocation.libfunc_id, args)
+                .map_err(|err| format!("statement {} ({}): {}", pc, statement, err))?;
+            let branch = &invocation.branches[branch];
+            if branch.results.len() != results.len() {
+                return Err(format!("statement {} gives {} results", pc, results.len()));
+            }
+            vars.extend(branch.results.iter().cloned().zip(results));
+            pc = match branch.target {
+                GenBranchTarget::Fallthrough => pc + 1,
+                GenBranchTarget::Statement(StatementIdx(target)) => target,
+            };
+        }
+    }
+
+    /// Runs the libfunc `id` on `args`, returns the index of the branch it takes and its results.
+    fn invoke(
+        &mut self,
+        id: &ConcreteLibfuncId,
+        args: Vec<Value>,
+    ) -> Result<(usize, Vec<Value>), String> {
+        let declaration = self
+            .program
+            .libfunc_declarations
+            .iter()
+            .find(|declaration| declaration.id == *id)
+            .ok_or_else(|| format!("undeclared libfunc `{}`", id))?;
+        let generic = declaration.long_id.generic_id.to_string();
+        let generic_args = &declaration.long_id.generic_args;
+        let type_arg = |idx: usize| match generic_args.get(idx) {
+            Some(GenericArg::Type(ty)) => Ok(ty.clone()),
+            _ => Err(format!("`{}` has no type argument {}", id, idx)),
+        };
+        let ints = args
+            .iter()
+            .filter_map(|arg| match arg {
+                Value::Int(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (ty, op) = generic.split_once('_').unwrap_or((&generic, ""));
+        let modulus = ty
+            .strip_prefix('u')
+            .and_then(|bits| bits.parse::<u32>().ok())
+            .map(|bits| BigInt::from(1) << bits);
+        let result = match (generic.as_str(), op, &modulus) {
+            ("store_temp" | "rename" | "branch_align" | "disable_ap_tracking" | "jump", ..) => {
+                (0, args)
+            }
+            ("upcast" | "unwrap_non_zero", ..) => (0, args),
+            ("alloc_local", ..) => (0, vec![Value::Uninitialized]),
+            ("finalize_locals", ..) => (0, vec![]),
+            ("store_local", ..) => (0, vec![args[1].clone()]),
+            ("dup", ..) => (0, vec![args[0].clone(), args[0].clone()]),
+            ("drop", ..) => (0, vec![]),
+            ("withdraw_gas", ..) => (0, args),
+            ("function_call", ..) => {
+                let Some(GenericArg::UserFunc(function)) = generic_args.first() else {
+                    return Err("function_call without a function".to_owned());
+                };
+                (0, self.call(function, args)?)
+            }
+            ("enum_init", ..) => {
+                let Some(GenericArg::Value(variant)) = generic_args.get(1) else {
+                    return Err("enum_init without a variant".to_owned());
+                };
+                let variant = usize::try_from(variant).map_err(|err| err.to_string())?;
+                (0, vec![Value::Enum(variant, Box::new(args[0].clone()))])
+            }
+            ("enum_match", ..) => match &args[0] {
+                Value::Enum(variant, inner) => (*variant, vec![(**inner).clone()]),
+                value => return Err(format!("enum_match on {:?}", value)),
+            },
+            ("enum_from_bounded_int", ..) => {
+                let variant = usize::try_from(&ints[0]).map_err(|err| err.to_string())?;
+                (
+                    0,
+                    vec![Value::Enum(variant, Box::new(Value::Struct(vec![])))],
+                )
+            }
+            ("const_as_immediate", ..) => (0, vec![Value::Int(self.const_value(&type_arg(0)?)?)]),
+            ("struct_construct", ..) => (0, vec![Value::Struct(args)]),
+            ("array_new", ..) => (0, vec![Value::Array(vec![])]),
+            ("array_append", ..) => match args.as_slice() {
+                [Value::Array(values), value] => {
+                    let mut values = values.clone();
+                    values.push(value.clone());
+                    (0, vec![Value::Array(values)])
+                }
+                values => return Err(format!("array_append to {:?}", values)),
+            },
+            // The printed values aren't kept, debug prints have no effect on the run.
+            ("print", ..) => (0, vec![]),
+            ("struct_deconstruct", ..) => match &args[0] {
+                Value::Struct(fields) => (0, fields.clone()),
+                value => return Err(format!("struct_deconstruct of {:?}", value)),
+            },
+            ("downcast", ..) => {
+                let max = self.max_value(&type_arg(1)?)?;
+                if ints[0] <= max {
+                    (0, vec![Value::Builtin, Value::Int(ints[0].clone())])
+                } else {
+                    (1, vec![Value::Builtin])
+                }
+            }
+            ("bitwise", ..) => {
+                let [a, b] = [&ints[0], &ints[1]];
+                (
+                    0,
+                    vec![
+                        Value::Builtin,
+                        Value::Int(a & b),
+                        Value::Int(a ^ b),
+                        Value::Int(a | b),
+                    ],
+                )
+            }
+            ("bool_and_impl" | "bool_or_impl" | "bool_xor_impl", ..) => {
+                match (&args[0], &args[1]) {
+                    (Value::Enum(lhs, _), Value::Enum(rhs, _)) => {
+                        let value = match generic.as_str() {
+                            "bool_and_impl" => lhs & rhs,
+                            "bool_or_impl" => lhs | rhs,
+                            _ => lhs ^ rhs,
+                        };
+                        (0, vec![Value::Enum(value, Box::new(Value::Struct(vec![])))])
+                    }
+                    values => return Err(format!("{} of {:?}", generic, values)),
+                }
+            }
+            ("felt252_add" | "felt252_sub" | "felt252_mul", ..) => {
+                let prime = (BigInt::from(1) << 251) + (BigInt::from(17) << 192) + 1;
+                let value = match op {
+                    "add" => &ints[0] + &ints[1],
+                    "sub" => &ints[0] - &ints[1],
+                    _ => &ints[0] * &ints[1],
+                };
+                (0, vec![Value::Int(((value % &prime) + &prime) % &prime)])
+            }
+            (_, "to_felt252", _) => (0, vec![Value::Int(ints[0].clone())]),
+            (_, "try_from_felt252", Some(modulus)) if ints[0] < *modulus => {
+                (0, vec![Value::Builtin, Value::Int(ints[0].clone())])
+            }
+            (_, "try_from_felt252", _) => (1, vec![Value::Builtin]),
+            (_, "const", _) => {
+                let Some(GenericArg::Value(value)) = generic_args.first() else {
+                    return Err(format!("`{}` without a value", id));
+                };
+                (0, vec![Value::Int(value.clone())])
+            }
+            (_, "overflowing_add" | "overflowing_sub", Some(modulus)) => {
+                let value = if op == "overflowing_add" {
+                    &ints[0] + &ints[1]
+                } else {
+                    &ints[0] - &ints[1]
+                };
+                let wrapped = ((&value % modulus) + modulus) % modulus;
+                let branch = usize::from(wrapped != value);
+                (branch, vec![Value::Builtin, Value::Int(wrapped)])
+            }
+            (_, "eq", _) => (usize::from(ints[0] == ints[1]), vec![]),
+            (_, "lt", _) => (usize::from(ints[0] < ints[1]), vec![Value::Builtin]),
+            (_, "le", _) => (usize::from(ints[0] <= ints[1]), vec![Value::Builtin]),
+            (_, "is_zero", _) if ints[0] == BigInt::from(0) => (0, vec![]),
+            (_, "is_zero", _) => (1, vec![Value::Int(ints[0].clone())]),
+            (_, "safe_divmod", _) => (
+                0,
+                vec![
+                    Value::Builtin,
+                    Value::Int(&ints[0] / &ints[1]),
+                    Value::Int(&ints[0] % &ints[1]),
+                ],
+            ),
+            (_, "wide_mul", _) => (0, vec![Value::Int(&ints[0] * &ints[1])]),
+            _ => return Err(format!("can't run `{}`", id)),
+        };
+        Ok(result)
+    }
+
+    /// Name of the generic type of the declared type `ty` (`u32`, `BoundedInt`).
+    fn generic_type(&self, ty: &ConcreteTypeId) -> String {
+        self.program
+            .type_declarations
+            .iter()
+            .find(|declaration| declaration.id == *ty)
+            .map_or_else(
+                || ty.to_string(),
+                |declaration| declaration.long_id.generic_id.to_string(),
+            )
+    }
+
+    /// Value of the `Const` type `ty`, through the `Const` of a `NonZero` wrapping it.
+    fn const_value(&self, ty: &ConcreteTypeId) -> Result<BigInt, String> {
+        let declaration = self
+            .program
+            .type_declarations
+            .iter()
+            .find(|declaration| declaration.id == *ty)
+            .ok_or_else(|| format!("undeclared type `{}`", ty))?;
+        match declaration.long_id.generic_args.last() {
+            Some(GenericArg::Value(value)) => Ok(value.clone()),
+            Some(GenericArg::Type(inner)) => self.const_value(inner),
+            _ => Err(format!("`{}` isn't a const type", ty)),
+        }
+    }
+
+    /// Largest value of the integer type `ty`, an unsigned integer or a `BoundedInt`.
+    fn max_value(&self, ty: &ConcreteTypeId) -> Result<BigInt, String> {
+        let declaration = self
+            .program
+            .type_declarations
+            .iter()
+            .find(|declaration| declaration.id == *ty)
+            .ok_or_else(|| format!("undeclared type `{}`", ty))?;
+        let generic = declaration.long_id.generic_id.to_string();
+        if let Some(GenericArg::Value(max)) = declaration.long_id.generic_args.get(1) {
+            return Ok(max.clone());
+        }
+        generic
+            .strip_prefix('u')
+            .and_then(|bits| bits.parse::<u32>().ok())
+            .map(|bits| (BigInt::from(1) << bits) - 1)
+            .ok_or_else(|| format!("`{}` isn't an integer type", ty))
+    }
+}
+
+/// Removes the values of `vars` from `values`, sierra variables are consumed when used.
+fn take(values: &mut HashMap<VarId, Value>, vars: &[VarId]) -> Result<Vec<Value>, String> {
+    vars.iter()
+        .map(|var| {
+            values
+                .remove(var)
+                .ok_or_else(|| format!("variable {} isn't set", var))
+        })
+        .collect()
+}
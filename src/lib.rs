@@ -0,0 +1,1782 @@
+//! Lowers llvm ir to sierra: [`compile`] gives the sierra program of an llvm file. The [`builder`] module builds
+//! sierra programs without llvm.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use builder::ProgramBuilder;
+use cairo_lang_sierra::{
+    ids::{ConcreteTypeId, GenericTypeId, VarId},
+    program::{
+        ConcreteTypeLongId, GenBranchTarget, GenStatement, Program, StatementIdx, TypeDeclaration,
+    },
+};
+use config::{Config, Emit};
+use errors::LoweringError;
+use inkwell::attributes::AttributeLoc;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{
+    AnyValue, AsValueRef, BasicValueEnum, FunctionValue, InstructionOpcode, InstructionValue,
+};
+use inkwell::{basic_block::BasicBlock, context::Context, module::Module, values::PhiValue};
+use smol_str::SmolStr;
+use types::{SierraType, BUILTINS};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(u32);
+
+#[derive(Default)]
+struct SierraBuilder<'ctx> {
+    /// Program being built, with the types and libfuncs it declares and the id of the next variable.
+    sierra: ProgramBuilder,
+    variables: HashMap<BasicValueEnum<'ctx>, VarId>,
+    block_remapping: HashMap<BasicBlock<'ctx>, StatementIdx>,
+    /// Blocks the branches of a statement (by index) lead to, set once every block is lowered. `None` keeps the target
+    /// of the branch.
+    block_targets: HashMap<usize, Vec<Option<BasicBlock<'ctx>>>>,
+    jump_to_phi: HashMap<BasicBlock<'ctx>, HashSet<(VarId, String, BasicValueEnum<'ctx>)>>,
+    /// Selects that were lowered together with the comparison that feeds them.
+    fused_selects: HashSet<InstructionValue<'ctx>>,
+    /// `urem`s lowered with the `udiv` of the same operands (and the other way around, `srem` with `sdiv` as well),
+    /// `safe_divmod` gives both.
+    fused_divmods: HashSet<InstructionValue<'ctx>>,
+    /// `extractvalue`s of the result of a signed overflow intrinsic, bound to the fields when the intrinsic is lowered.
+    overflow_extracts: HashSet<InstructionValue<'ctx>>,
+    /// Comparisons and boolean ops whose result is never used, directly or through other dead ones.
+    dead_booleans: HashSet<InstructionValue<'ctx>>,
+    /// `sub`s only compared to zero (`icmp eq (sub a, b), 0`), the comparison is lowered as `a == b` instead.
+    zero_compared_subs: HashSet<InstructionValue<'ctx>>,
+    /// Llvm block name and opcode each statement was lowered from.
+    statement_origins: Vec<(String, InstructionOpcode)>,
+    /// Llvm source line (from the `!dbg` location) of the instruction each statement was lowered from.
+    statement_lines: Vec<Option<u32>>,
+    /// Llvm values whose variable is deferred (not stored yet) and has to be stored before being passed to libfuncs
+    /// that don't accept deferred values.
+    deferred_values: HashSet<BasicValueEnum<'ctx>>,
+    /// Variables stored in a temp slot by a `store_temp`, they don't have to be stored again.
+    temp_vars: HashSet<VarId>,
+    /// Llvm values that are lowered to the same variable as another value (zero offset geps).
+    aliases: HashMap<BasicValueEnum<'ctx>, BasicValueEnum<'ctx>>,
+    /// Number of uses left for each llvm value, used to know when a value has to be copied before being consumed.
+    remaining_uses: HashMap<BasicValueEnum<'ctx>, usize>,
+    /// Variable of the `RangeCheck` builtin once a libfunc needed it. Every libfunc using it rebinds the same
+    /// variable so all the branches agree on it when they merge.
+    range_check: Option<VarId>,
+    /// Variable of the `Bitwise` builtin once a libfunc needed it, rebound the same way as the range check.
+    bitwise: Option<VarId>,
+    /// Variable of the `GasBuiltin` builtin once a libfunc needed it, rebound the same way as the range check.
+    gas_builtin: Option<VarId>,
+    /// Allocas lowered to sierra locals: the sierra type of the slot, the variable of the local (uninitialized until
+    /// the first store) and whether it was stored yet.
+    locals: HashMap<BasicValueEnum<'ctx>, (String, VarId, bool)>,
+    /// Geps to a field of a struct local: the local and the index of the field at each level of nesting.
+    field_geps: HashMap<BasicValueEnum<'ctx>, (BasicValueEnum<'ctx>, Vec<u32>)>,
+    /// Initializer of each constant global, by the pointer to the global.
+    const_globals: HashMap<BasicValueEnum<'ctx>, BasicValueEnum<'ctx>>,
+    /// Geps into a constant global table with const indices and the element they point to.
+    table_elements: HashMap<BasicValueEnum<'ctx>, BasicValueEnum<'ctx>>,
+    /// Consts read several times by the instructions of the block being lowered and the number of those reads left
+    /// (see [`SierraBuilder::find_shared_consts`]).
+    shared_const_uses: HashMap<BasicValueEnum<'ctx>, usize>,
+    /// Variable and sierra type of each shared const once it's materialized, its readers get copies until the last.
+    shared_const_vars: HashMap<BasicValueEnum<'ctx>, (VarId, String)>,
+    /// Shared consts the instruction being lowered reads.
+    current_shared_consts: Vec<BasicValueEnum<'ctx>>,
+    /// Builtins each function takes and returns (see [`function_builtins`]), `None` until the first lowering of the
+    /// module that finds them.
+    function_builtins: Option<FunctionBuiltins>,
+    /// Don't print the warnings, the module is lowered again after the lowering that finds the builtins.
+    quiet: bool,
+    /// Calls (as `(caller, callee)`) that can lead back to the caller, they withdraw gas.
+    recursive_calls: HashSet<(String, String)>,
+    /// Name of the function being lowered, to know which one failed to lower.
+    current_function: Option<String>,
+    /// Value used by the instruction being lowered that has no variable, it wasn't lowered (or not yet).
+    missing_value: Option<String>,
+    /// Constant used by the instruction being lowered that isn't an integer.
+    unsupported_const: Option<String>,
+    /// Value the instruction being lowered consumes while it's used again later, its type can't be duplicated.
+    uncopyable_value: Option<String>,
+    /// Struct the instruction being lowered uses while one of its fields was never set.
+    unset_struct: Option<String>,
+    /// `insertvalue` into a nested aggregate building a struct the instruction being lowered uses.
+    unsupported_insertvalue: Option<String>,
+    /// Local the instruction being lowered loads before any store to it while its type has no default value.
+    uninitialized_local: Option<String>,
+    /// Source line of the instruction being lowered, if the ir carries `!dbg` locations.
+    current_line: Option<u32>,
+}
+pub mod builder;
+pub mod config;
+#[cfg(test)]
+pub mod diff;
+pub mod errors;
+pub mod format;
+#[cfg(test)]
+pub mod interpret;
+pub mod types;
+pub mod utils;
+pub mod validate;
+
+impl<'ctx> SierraBuilder<'ctx> {
+    pub fn next_var(&mut self) -> u32 {
+        self.sierra.next_var += 1;
+        (self.sierra.next_var - 1) as u32
+    }
+    /// Insert type in type declaration if needed
+    pub fn insert_type(&mut self, ty: String) {
+        let ty = SierraType::from_llvm(&ty);
+        if ty == SierraType::Bool {
+            return self.insert_bool_type();
+        }
+        let type_info = ty.declared_type_info();
+        let ty = ty.to_string();
+        if self.sierra.types.insert(ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(ty.clone()),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string(ty.clone()),
+                    generic_args: vec![],
+                },
+                declared_type_info: Some(type_info),
+            })
+        }
+    }
+
+    /// Insert function parameters (insert type + creates sierra variables)
+    pub fn insert_param(&mut self, param: BasicValueEnum<'ctx>) {
+        self.declare_llvm_type(param.get_type());
+        let next_var = self.next_var();
+        self.variables.insert(
+            param,
+            VarId {
+                id: next_var as u64,
+                debug_name: Some(SmolStr::from(param.get_name().to_str().unwrap())),
+            },
+        );
+    }
+
+    /// Read an llvm file (`-` for stdin) and generate fully unfunctionnal sierra.
+    pub fn compile(input: &Path, config: &Config) -> Result<Program, LoweringError> {
+        let source = read_input(input)?;
+        Self::compile_source(&source, input, config)
+    }
+
+    /// Lowers the llvm ir `source` read from `input` like [`Self::compile`].
+    pub fn compile_source(
+        source: &[u8],
+        input: &Path,
+        config: &Config,
+    ) -> Result<Program, LoweringError> {
+        // Initialize LLVM context
+        let context = Context::create();
+
+        // Parse the LLVM IR
+        let module = parse_module(&context, source, input)?;
+        check_target_triple(&module, config)?;
+        let (builder, _) = SierraBuilder::lower(&context, &module, (source, input), config)?;
+        Ok(builder.sierra.program)
+    }
+
+    /// Lowers `input` like [`Self::compile`] but writes what `--emit`, `--annotate`, `--list-functions` or
+    /// `--split-output` ask for instead of returning the program.
+    pub fn emit(input: &Path, config: &Config) -> Result<(), LoweringError> {
+        let source = read_input(input)?;
+        let context = Context::create();
+        let module = parse_module(&context, &source, input)?;
+        check_target_triple(&module, config)?;
+        if config.list_functions {
+            print!("{}", function_listing(&module));
+            return Ok(());
+        }
+        let entry_points = entry_points(&module, config)?;
+        let (builder, skipped) = SierraBuilder::lower(&context, &module, (&source, input), config)?;
+        if let Some(dir) = &config.split_output {
+            return SierraBuilder::write_split_output(&context, &module, config, &skipped, dir);
+        }
+        let output = if config.emit == Some(Emit::DebugMap) {
+            format!("{}\n", builder.debug_map_json())
+        } else if config.emit == Some(Emit::SierraStats) {
+            format::libfunc_stats(&builder.sierra.program)
+        } else if config.emit == Some(Emit::VarMap) {
+            format!("{}\n", builder.var_map_json(&module))
+        } else if config.emit == Some(Emit::EntryPoints) {
+            format!(
+                "{}\n",
+                format::entry_points_json(&builder.sierra.program, &entry_points)
+            )
+        } else if config.annotate {
+            builder.annotated_program()
+        } else if config.compact {
+            format::compact_program(&builder.sierra.program)
+        } else {
+            format!("{}\n", builder.sierra.program)
+        };
+        write_output(input, config, &output)
+    }
+
+    /// Lowers `module` parsed from `source`, validates and names the variables of the program. Returns the builder
+    /// with the names of the functions skipped by `--allow-partial`.
+    fn lower(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        (source, input): (&[u8], &Path),
+        config: &Config,
+    ) -> Result<(SierraBuilder<'ctx>, HashSet<String>), LoweringError> {
+        // With `--allow-partial` a function that fails to lower is skipped, along with the functions calling it, and
+        // the module is lowered again without them.
+        let mut skipped: Vec<(String, String)> = vec![];
+        let mut builder = loop {
+            let skipped_names = skipped.iter().map(|(name, _)| name.clone()).collect();
+            // The variables of the values are only known by the builder that lowered them.
+            let lowered = if config.threads > 1 && config.emit != Some(Emit::VarMap) {
+                SierraBuilder::lower_in_parallel(
+                    context,
+                    module,
+                    (source, input),
+                    config,
+                    &skipped_names,
+                    config.threads,
+                )
+            } else {
+                let mut builder = SierraBuilder::default();
+                match SierraBuilder::lower_module(
+                    &mut builder,
+                    context,
+                    module,
+                    config,
+                    &skipped_names,
+                    None,
+                ) {
+                    Ok(()) => Ok(builder),
+                    Err(err) => Err((builder.current_function, err)),
+                }
+            };
+            match lowered {
+                Ok(builder) => break builder,
+                Err((Some(function), err)) if config.allow_partial => {
+                    skipped.push((function, err.to_string()));
+                    skip_callers(module, &mut skipped);
+                }
+                Err((_, err)) => return Err(err),
+            }
+        };
+        for (function, reason) in &skipped {
+            eprintln!("warning: skipped function `{}`: {}", function, reason);
+        }
+        if !skipped.is_empty() {
+            eprintln!("warning: {} function(s) skipped", skipped.len());
+        }
+        if !config.no_verify && !config.keep_unsupported_as_nop {
+            validate::validate_program(&builder.sierra.program)
+                .map_err(|reason| LoweringError::InvalidProgram { reason })?;
+        }
+        builder.sierra.program = if config.no_debug_names {
+            format::strip_variable_names(&builder.sierra.program)
+        } else {
+            format::name_variables(&builder.sierra.program)
+        };
+        Ok((builder, skipped.into_iter().map(|(name, _)| name).collect()))
+    }
+
+    /// Writes each function of `module` (except the `skipped` ones) lowered on its own to `<dir>/<function>.sierra`.
+    /// The programs only contain the function, its calls to the other functions can't be resolved.
+    fn write_split_output(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        config: &Config,
+        skipped: &HashSet<String>,
+        dir: &Path,
+    ) -> Result<(), LoweringError> {
+        let output_error = |path: &Path, err: std::io::Error| LoweringError::Output {
+            path: path.display().to_string(),
+            reason: err.to_string(),
+        };
+        std::fs::create_dir_all(dir).map_err(|err| output_error(dir, err))?;
+        let function_builtins =
+            SierraBuilder::find_function_builtins(context, module, config, skipped)
+                .map_err(|(_, err)| err)?;
+        for function in module.get_functions() {
+            let name = function.get_name().to_str().unwrap().to_owned();
+            if function.count_basic_blocks() == 0 || skipped.contains(&name) {
+                continue;
+            }
+            let mut builder = SierraBuilder {
+                function_builtins: Some(function_builtins.clone()),
+                ..SierraBuilder::default()
+            };
+            let bodies = HashSet::from([name.clone()]);
+            SierraBuilder::lower_module(
+                &mut builder,
+                context,
+                module,
+                config,
+                skipped,
+                Some(&bodies),
+            )?;
+            let program = if config.no_debug_names {
+                format::strip_variable_names(&builder.sierra.program)
+            } else {
+                format::name_variables(&builder.sierra.program)
+            };
+            let output = if config.compact {
+                format::compact_program(&program)
+            } else {
+                format!("{}\n", program)
+            };
+            let path = dir.join(format!("{}.sierra", name));
+            std::fs::write(&path, output).map_err(|err| output_error(&path, err))?;
+        }
+        Ok(())
+    }
+
+    /// Lowers every function of `module` except the `skipped` ones. With `bodies`, only the bodies of these functions
+    /// are lowered and the program only contains them. On error, [`Self::current_function`] is the function that
+    /// failed to lower if the error comes from one.
+    fn lower_module(
+        builder: &mut SierraBuilder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        config: &Config,
+        skipped: &HashSet<String>,
+        bodies: Option<&HashSet<String>>,
+    ) -> Result<(), LoweringError> {
+        if builder.function_builtins.is_none() {
+            let builtins = SierraBuilder::find_function_builtins(context, module, config, skipped)
+                .map_err(|(function, err)| {
+                    builder.current_function = function;
+                    err
+                })?;
+            builder.function_builtins = Some(builtins);
+        }
+        SierraBuilder::lower_bodies(builder, context, module, config, skipped, bodies)
+    }
+
+    /// Finds the builtins each function takes with a first lowering of the whole module: the builtins a function
+    /// passes to its callees are only known once their bodies are lowered. See [`function_builtins`].
+    fn find_function_builtins(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        config: &Config,
+        skipped: &HashSet<String>,
+    ) -> Result<FunctionBuiltins, (Option<String>, LoweringError)> {
+        let mut first_pass = SierraBuilder {
+            function_builtins: Some(HashMap::new()),
+            quiet: true,
+            ..SierraBuilder::default()
+        };
+        match SierraBuilder::lower_bodies(&mut first_pass, context, module, config, skipped, None) {
+            Ok(()) => Ok(function_builtins(module, &first_pass.sierra.program)),
+            Err(err) => Err((first_pass.current_function, err)),
+        }
+    }
+
+    /// Lowers the bodies like [`Self::lower_module`], once the builtins of the functions are known.
+    fn lower_bodies(
+        builder: &mut SierraBuilder<'ctx>,
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        config: &Config,
+        skipped: &HashSet<String>,
+        bodies: Option<&HashSet<String>>,
+    ) -> Result<(), LoweringError> {
+        // Collect all the basic blocks where a jump leads to a phi instruction to store the value in a tempvar before jumping
+        // phi basically merges branches to allow let a = if cond { some_val } else { some_other_val}
+        // Variables are numbered per function: its params, its phis then the rest of its body.
+        let mut first_body_vars = HashMap::new();
+        for function in module.get_functions() {
+            let mut first_var_id = function.count_params();
+            for basic_block in function.get_basic_block_iter() {
+                for instr in basic_block.get_instructions() {
+                    builder.count_uses(instr);
+                    if let InstructionOpcode::Phi = instr.get_opcode() {
+                        // Every edge stores its incoming value in the same variable, the one of the phi.
+                        let var_id = VarId {
+                            id: first_var_id as u64,
+                            debug_name: instr
+                                .get_name()
+                                .map(|name| SmolStr::from(name.to_str().unwrap())),
+                        };
+                        first_var_id += 1;
+                        if let Ok(basic_value_enum) = instr.as_any_value_enum().try_into() {
+                            builder.variables.insert(basic_value_enum, var_id.clone());
+                        }
+                        let phi_ty = BasicTypeEnum::try_from(instr.get_type())
+                            .map(|ty| SierraType::from_llvm_type(ty).to_string())
+                            .unwrap();
+                        unsafe {
+                            // Get the 2 basic blocks that contain the jump instruction that jump here
+                            PhiValue::new(instr.as_value_ref())
+                                .get_incomings()
+                                .for_each(|inc| {
+                                    // Append the set if it already exists (case where multiple jumps in the same BB land to this phi instruction)
+                                    // else just create it. Several edges from the same block carry the same value
+                                    // and are stored once.
+                                    builder.jump_to_phi.entry(inc.1).or_default().insert((
+                                        var_id.clone(),
+                                        phi_ty.clone(),
+                                        inc.0,
+                                    ));
+                                })
+                        }
+                    };
+                }
+            }
+
+            first_body_vars.insert(function, first_var_id);
+        }
+        builder.find_dead_booleans(module);
+        builder.find_zero_compared_subs(module);
+
+        // Iterate over functions and basic blocks
+        let dbg_kind_id = context.get_kind_id("dbg");
+        // Declarations (like `printf`) have no body to lower.
+        let functions = module
+            .get_functions()
+            .filter(|function| function.count_basic_blocks() > 0)
+            .filter(|function| !skipped.contains(function.get_name().to_str().unwrap()))
+            .collect::<Vec<_>>();
+        if functions.is_empty() && !config.allow_empty {
+            return Err(LoweringError::NoFunctions);
+        }
+        for global in module.get_globals() {
+            if let Some(initializer) = global.get_initializer().filter(|_| global.is_constant()) {
+                builder
+                    .const_globals
+                    .insert(global.as_pointer_value().into(), initializer);
+            }
+        }
+        builder.recursive_calls = recursive_calls(&functions);
+        // Declare every function up front so calls resolve whatever the order of the functions in the module.
+        for function in &functions {
+            builder.declare_function(*function);
+        }
+
+        for (func_idx, function) in functions.into_iter().enumerate() {
+            let name = function.get_name().to_str().unwrap();
+            if bodies.is_some_and(|bodies| !bodies.contains(name)) {
+                continue;
+            }
+            builder.current_function = Some(name.to_owned());
+            builder.sierra.next_var = u64::from(first_body_vars[&function]);
+            builder.start_function(func_idx);
+
+            for basic_block in function.get_basic_blocks() {
+                let block_start = StatementIdx(builder.sierra.program.statements.len());
+                builder.block_remapping.insert(basic_block, block_start);
+                builder.find_shared_consts(basic_block);
+                let block_name = basic_block.get_name().to_str().unwrap().to_owned();
+                for instr in basic_block.get_instructions() {
+                    builder.current_line = debug_line(instr, dbg_kind_id);
+                    builder.current_shared_consts = builder.shared_const_operands(instr);
+                    match instr.get_opcode() {
+                        // Nothing reads their result, they'd only leave an unused `bool` behind.
+                        _ if builder.dead_booleans.contains(&instr) => (),
+                        InstructionOpcode::ICmp => {
+                            // `select (icmp a, b), x, y` (min/max idioms) branches directly on the comparison
+                            if let Some(select) = builder.fusable_select(instr) {
+                                builder.build_select_on_comparison(instr, select);
+                                builder.fused_selects.insert(select);
+                            } else if utils::is_lowerable_comparison(instr) {
+                                builder.build_comparison(instr);
+                            } else if config.keep_unsupported_as_nop {
+                                // Signed comparisons of narrow integers have no sierra counterpart.
+                                builder.build_unsupported_placeholder(instr);
+                            } else {
+                                return Err(LoweringError::UnsupportedOpcode {
+                                    opcode: instr.get_opcode(),
+                                    instruction: instr.print_to_string().to_string(),
+                                    span: builder.current_span(),
+                                });
+                            }
+                        }
+                        InstructionOpcode::Add => {
+                            builder.build_overflowing_op(instr, "add", config.mask_narrow_ints);
+                        }
+                        InstructionOpcode::Mul
+                            if SierraType::from_llvm(
+                                &instr.get_type().print_to_string().to_string(),
+                            )
+                            .wide_mul_type()
+                            .is_some() =>
+                        {
+                            builder.build_wrapping_mul(instr);
+                        }
+                        // Lowered along with the comparison reading it.
+                        InstructionOpcode::Sub if builder.zero_compared_subs.contains(&instr) => (),
+                        InstructionOpcode::Sub => {
+                            builder.build_overflowing_op(instr, "sub", config.mask_narrow_ints);
+                        }
+                        InstructionOpcode::Shl
+                        | InstructionOpcode::LShr
+                        | InstructionOpcode::AShr
+                            if utils::is_lowerable_const_shift(instr) =>
+                        {
+                            builder.build_const_shift(instr);
+                        }
+                        InstructionOpcode::Shl
+                            if config.dynamic_shifts
+                                && !instr
+                                    .get_operand(1)
+                                    .unwrap()
+                                    .left()
+                                    .unwrap()
+                                    .into_int_value()
+                                    .is_constant_int() =>
+                        {
+                            builder.build_shift_loop(instr);
+                        }
+                        InstructionOpcode::Select
+                            if instr
+                                .get_operand(0)
+                                .unwrap()
+                                .left()
+                                .unwrap()
+                                .into_int_value()
+                                .is_constant_int() =>
+                        {
+                            builder.build_const_select(instr);
+                        }
+                        InstructionOpcode::Select if builder.fused_selects.contains(&instr) => {
+                            // Already lowered along with its comparison.
+                        }
+                        InstructionOpcode::Select
+                            if instr.get_operand(0).unwrap().left().unwrap().is_int_value() =>
+                        {
+                            builder.build_select(instr);
+                        }
+                        InstructionOpcode::Br => {
+                            builder.store_values_used_later(basic_block);
+                            builder.store_phi_values(basic_block);
+                            // When the next block starts right after there is no need to jump.
+                            if !falls_through(basic_block) {
+                                if instr.get_num_operands() == 1 {
+                                    builder.build_jump(instr);
+                                } else {
+                                    builder.build_bool_branch(instr);
+                                }
+                            }
+                        }
+                        InstructionOpcode::Unreachable => builder.build_unreachable(instr),
+                        InstructionOpcode::Switch => {
+                            builder.store_values_used_later(basic_block);
+                            builder.store_phi_values(basic_block);
+                            builder.build_switch(instr);
+                        }
+                        InstructionOpcode::And | InstructionOpcode::Or | InstructionOpcode::Xor
+                            if instr.get_type().print_to_string().to_string() == "i1" =>
+                        {
+                            let op = match instr.get_opcode() {
+                                InstructionOpcode::And => "and",
+                                InstructionOpcode::Or => "or",
+                                _ => "xor",
+                            };
+                            builder.build_bool_op(instr, &format!("bool_{}_impl", op));
+                        }
+                        InstructionOpcode::And if builder.low_bits_mask(instr).is_some() => {
+                            builder.build_low_bits_mask(instr);
+                        }
+                        InstructionOpcode::And | InstructionOpcode::Or | InstructionOpcode::Xor
+                            if instr.get_type().is_int_type() =>
+                        {
+                            builder.build_bitwise_op(instr);
+                        }
+                        InstructionOpcode::UDiv
+                        | InstructionOpcode::URem
+                        | InstructionOpcode::SDiv
+                        | InstructionOpcode::SRem
+                            if builder.fused_divmods.contains(&instr) => {}
+                        InstructionOpcode::UDiv | InstructionOpcode::URem => {
+                            builder.build_divmod(instr);
+                        }
+                        InstructionOpcode::SDiv | InstructionOpcode::SRem
+                            if utils::is_lowerable_signed_division(instr) =>
+                        {
+                            builder.build_signed_divmod(instr);
+                        }
+                        // Sign extending needs the sign bit of both integers to be the one of their sierra type.
+                        InstructionOpcode::ZExt | InstructionOpcode::SExt
+                            if utils::is_lowerable_extension(instr) =>
+                        {
+                            builder.build_int_extension(instr);
+                        }
+                        // Truncating to `i1` would give a bool, which isn't an integer in sierra.
+                        InstructionOpcode::Trunc
+                            if instr.get_type().into_int_type().get_bit_width() > 1 =>
+                        {
+                            builder.build_trunc(instr);
+                        }
+                        InstructionOpcode::Alloca if builder.is_local_alloca(instr) => {
+                            builder.build_alloc_local(instr);
+                        }
+                        InstructionOpcode::Store
+                            if builder
+                                .locals
+                                .contains_key(&instr.get_operand(1).unwrap().left().unwrap()) =>
+                        {
+                            builder.build_store_local(instr);
+                        }
+                        InstructionOpcode::Load
+                            if builder
+                                .field_geps
+                                .contains_key(&instr.get_operand(0).unwrap().left().unwrap()) =>
+                        {
+                            builder.build_load_field(instr);
+                        }
+                        InstructionOpcode::Load
+                            if builder
+                                .loaded_const(instr.get_operand(0).unwrap().left().unwrap())
+                                .is_some() =>
+                        {
+                            builder.build_const_load(instr);
+                        }
+                        InstructionOpcode::Load
+                            if builder
+                                .locals
+                                .contains_key(&instr.get_operand(0).unwrap().left().unwrap()) =>
+                        {
+                            builder.build_load_local(instr);
+                        }
+                        InstructionOpcode::Call if is_variadic_call(module, instr) => {
+                            return Err(LoweringError::VariadicCall {
+                                callee: callee_name(instr).unwrap(),
+                                span: builder.current_span(),
+                            });
+                        }
+                        InstructionOpcode::Call
+                            if is_signed_overflow_intrinsic(instr)
+                                && utils::is_lowerable_signed_overflow(instr) =>
+                        {
+                            builder.build_signed_overflow(instr)
+                        }
+                        // The other signed overflow intrinsics have no sierra counterpart, they aren't calls to lower.
+                        InstructionOpcode::Call if !is_signed_overflow_intrinsic(instr) => {
+                            builder.build_call(instr)
+                        }
+                        InstructionOpcode::ExtractValue
+                            if builder.overflow_extracts.contains(&instr) => {}
+                        // The element of a const global table at a const index is loaded as a const.
+                        InstructionOpcode::GetElementPtr
+                            if builder.const_table_element(instr).is_some() =>
+                        {
+                            let element = builder.const_table_element(instr).unwrap();
+                            let result =
+                                BasicValueEnum::try_from(instr.as_any_value_enum()).unwrap();
+                            builder.table_elements.insert(result, element);
+                        }
+                        InstructionOpcode::GetElementPtr
+                            if builder
+                                .locals
+                                .contains_key(&instr.get_operand(0).unwrap().left().unwrap()) =>
+                        {
+                            builder.build_field_gep(instr)?;
+                        }
+                        // `gep ptr %p, i32 0` is just `%p`
+                        InstructionOpcode::GetElementPtr if builder.is_zero_offset_gep(instr) => {
+                            let base = instr.get_operand(0).unwrap().left().unwrap();
+                            let result =
+                                BasicValueEnum::try_from(instr.as_any_value_enum()).unwrap();
+                            builder.alias_value(result, base);
+                        }
+                        // Aggregates are built from their fields by the instruction consuming them.
+                        InstructionOpcode::InsertValue => (),
+                        // Phis are lowered by the stores emitted before the jumps leading to them. Their variable is
+                        // bound before any block is lowered so the instructions of a loop header read it as well.
+                        InstructionOpcode::Phi => (),
+                        InstructionOpcode::Return => builder.build_return(instr)?,
+                        // Anything else (SIMD vectors, floats) has no sierra mapping, emitting nothing
+                        // for it would produce silently wrong code.
+                        _ if config.keep_unsupported_as_nop => {
+                            builder.build_unsupported_placeholder(instr)
+                        }
+                        _ => {
+                            return Err(LoweringError::UnsupportedOpcode {
+                                opcode: instr.get_opcode(),
+                                instruction: instr.print_to_string().to_string(),
+                                span: builder.current_span(),
+                            });
+                        }
+                    }
+                    builder.drop_if_unused(instr);
+                    if let Some(value) = builder.missing_value.take() {
+                        return Err(LoweringError::MissingVariable {
+                            value,
+                            instruction: instr.print_to_string().to_string(),
+                            span: builder.current_span(),
+                        });
+                    }
+                    if let Some(value) = builder.unsupported_const.take() {
+                        return Err(LoweringError::UnsupportedConst {
+                            value,
+                            instruction: instr.print_to_string().to_string(),
+                            span: builder.current_span(),
+                        });
+                    }
+                    if let Some(value) = builder.uncopyable_value.take() {
+                        return Err(LoweringError::UncopyableValue {
+                            value,
+                            instruction: instr.print_to_string().to_string(),
+                            span: builder.current_span(),
+                        });
+                    }
+                    if let Some(insertvalue) = builder.unsupported_insertvalue.take() {
+                        return Err(LoweringError::UnsupportedOpcode {
+                            opcode: InstructionOpcode::InsertValue,
+                            instruction: insertvalue,
+                            span: builder.current_span(),
+                        });
+                    }
+                    if let Some(local) = builder.uninitialized_local.take() {
+                        return Err(LoweringError::UninitializedLocal {
+                            local,
+                            instruction: instr.print_to_string().to_string(),
+                            span: builder.current_span(),
+                        });
+                    }
+                    if let Some(value) = builder.unset_struct.take() {
+                        return Err(LoweringError::UnsetStructField {
+                            value,
+                            instruction: instr.print_to_string().to_string(),
+                            span: builder.current_span(),
+                        });
+                    }
+                    let lowered =
+                        builder.sierra.program.statements.len() - builder.statement_origins.len();
+                    builder.statement_origins.extend(std::iter::repeat_n(
+                        (block_name.clone(), instr.get_opcode()),
+                        lowered,
+                    ));
+                    builder
+                        .statement_lines
+                        .extend(std::iter::repeat_n(builder.current_line, lowered));
+                }
+                // Sierra requires every path to terminate, a block whose terminator wasn't lowered is invalid.
+                if !builder.ends_with_terminator(block_start.0) && !falls_through(basic_block) {
+                    return Err(LoweringError::MissingTerminator {
+                        block: block_name,
+                        span: builder.current_span(),
+                    });
+                }
+            }
+            builder.add_builtins_to_signature(func_idx);
+        }
+        builder.current_function = None;
+        builder.current_line = None;
+        builder.sierra.program.statements = builder
+            .sierra
+            .program
+            .statements
+            .iter()
+            .enumerate()
+            .map(|(idx, statement)| {
+                // Jumps to blocks get their target now, the ones inside of a block (fused selects) already have it.
+                let mut statement = statement.clone();
+                if let (Some(blocks), GenStatement::Invocation(invocation)) =
+                    (builder.block_targets.get(&idx), &mut statement)
+                {
+                    for (branch, block) in invocation.branches.iter_mut().zip(blocks) {
+                        if let Some(block) = block {
+                            branch.target =
+                                GenBranchTarget::Statement(builder.block_remapping[block]);
+                        }
+                    }
+                }
+                statement
+            })
+            .collect::<Vec<_>>();
+        if let Some(bodies) = bodies {
+            builder
+                .sierra
+                .program
+                .funcs
+                .retain(|func| bodies.contains(&func.id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Lowers the functions of the module on `threads` threads, each one lowering the bodies of consecutive
+    /// functions. The first chunk is lowered on the calling thread with the parsed module, the other threads parse
+    /// the module again in their own context since a context can't be shared between threads. The programs are then
+    /// merged in the order of the functions so the output is the same as when lowering them all at once. On error,
+    /// returns the function that failed to lower if the error comes from one.
+    fn lower_in_parallel(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        (source, input): (&[u8], &Path),
+        config: &Config,
+        skipped: &HashSet<String>,
+        threads: usize,
+    ) -> Result<Self, (Option<String>, LoweringError)> {
+        let function_builtins =
+            SierraBuilder::find_function_builtins(context, module, config, skipped)?;
+        let functions = module
+            .get_functions()
+            .filter(|function| function.count_basic_blocks() > 0)
+            .map(|function| function.get_name().to_str().unwrap().to_owned())
+            .filter(|name| !skipped.contains(name))
+            .collect::<Vec<_>>();
+        let chunks = functions
+            .chunks(functions.len().div_ceil(threads).max(1))
+            .map(|chunk| chunk.iter().cloned().collect::<HashSet<_>>())
+            .collect::<Vec<_>>();
+        let Some((first, others)) = chunks.split_first() else {
+            return Ok(SierraBuilder::default());
+        };
+        let lowered = std::thread::scope(|scope| {
+            let handles = others
+                .iter()
+                .map(|bodies| {
+                    let function_builtins = function_builtins.clone();
+                    scope.spawn(move || {
+                        let context = Context::create();
+                        let module =
+                            parse_module(&context, source, input).map_err(|err| (None, err))?;
+                        SierraBuilder::lower_chunk(
+                            &context,
+                            &module,
+                            config,
+                            skipped,
+                            bodies,
+                            function_builtins,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            let first = SierraBuilder::lower_chunk(
+                context,
+                module,
+                config,
+                skipped,
+                first,
+                function_builtins.clone(),
+            );
+            std::iter::once(first)
+                .chain(handles.into_iter().map(|handle| {
+                    handle.join().unwrap_or_else(|panic| {
+                        let reason = panic
+                            .downcast_ref::<&str>()
+                            .map(|reason| reason.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+                        Err((None, LoweringError::ThreadPanicked { reason }))
+                    })
+                }))
+                .collect::<Vec<_>>()
+        });
+        let mut builder = SierraBuilder::default();
+        for chunk in lowered {
+            let (program, statement_origins, statement_lines) = chunk?;
+            let offset = builder.sierra.program.statements.len();
+            for declaration in program.type_declarations {
+                if builder.sierra.types.insert(declaration.id.to_string()) {
+                    builder.sierra.program.type_declarations.push(declaration);
+                }
+            }
+            for declaration in program.libfunc_declarations {
+                if builder.sierra.libfuncs.insert(declaration.id.to_string()) {
+                    builder
+                        .sierra
+                        .program
+                        .libfunc_declarations
+                        .push(declaration);
+                }
+            }
+            builder
+                .sierra
+                .program
+                .statements
+                .extend(program.statements.into_iter().map(|mut statement| {
+                    if let GenStatement::Invocation(invocation) = &mut statement {
+                        for branch in &mut invocation.branches {
+                            if let GenBranchTarget::Statement(target) = &mut branch.target {
+                                target.0 += offset;
+                            }
+                        }
+                    }
+                    statement
+                }));
+            builder
+                .sierra
+                .program
+                .funcs
+                .extend(program.funcs.into_iter().map(|mut func| {
+                    func.entry_point.0 += offset;
+                    func
+                }));
+            builder.statement_origins.extend(statement_origins);
+            builder.statement_lines.extend(statement_lines);
+        }
+        Ok(builder)
+    }
+
+    /// Lowers the bodies of the functions in `bodies` with the builtins of every function found beforehand, see
+    /// [`SierraBuilder::lower_in_parallel`].
+    fn lower_chunk(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        config: &Config,
+        skipped: &HashSet<String>,
+        bodies: &HashSet<String>,
+        function_builtins: FunctionBuiltins,
+    ) -> Result<LoweredChunk, (Option<String>, LoweringError)> {
+        let mut builder = SierraBuilder {
+            function_builtins: Some(function_builtins),
+            ..SierraBuilder::default()
+        };
+        match SierraBuilder::lower_module(
+            &mut builder,
+            context,
+            module,
+            config,
+            skipped,
+            Some(bodies),
+        ) {
+            Ok(()) => Ok((
+                builder.sierra.program,
+                builder.statement_origins,
+                builder.statement_lines,
+            )),
+            Err(err) => Err((builder.current_function, err)),
+        }
+    }
+}
+
+/// Architectures of the target triples the lowering expects, pointers are lowered to 64 bits addresses.
+const EXPECTED_ARCHS: [&str; 4] = ["x86_64", "aarch64", "arm64", "riscv64"];
+
+/// Warns (or fails with `--strict`) when `module` targets a triple the lowering doesn't expect, the pointer size and
+/// the data layout the ir was generated for may not match the lowering. Modules without a triple are accepted.
+fn check_target_triple(module: &Module, config: &Config) -> Result<(), LoweringError> {
+    let triple = module.get_triple();
+    let triple = triple.as_str().to_string_lossy();
+    let arch = triple.split('-').next().unwrap_or_default();
+    if triple.is_empty() || EXPECTED_ARCHS.contains(&arch) {
+        return Ok(());
+    }
+    if config.strict {
+        return Err(LoweringError::UnexpectedTargetTriple {
+            triple: triple.into_owned(),
+        });
+    }
+    eprintln!(
+        "warning: unexpected target triple `{}`, pointers are lowered as 64 bits addresses",
+        triple
+    );
+    Ok(())
+}
+
+/// Writes `output` to the file `--output`/`--emit` ask for, named after `input` (`stdin` when it's `-`), or prints it.
+pub fn write_output(input: &Path, config: &Config, output: &str) -> Result<(), LoweringError> {
+    let input = if input == Path::new("-") {
+        Path::new("stdin")
+    } else {
+        input
+    };
+    match config.output_path(input) {
+        Some(path) => std::fs::write(&path, output).map_err(|err| LoweringError::Output {
+            path: path.display().to_string(),
+            reason: err.to_string(),
+        }),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+/// Reads the llvm ir in the file `input`, or in stdin if it's `-`. The text is kept so each lowering thread can parse
+/// it in its own context.
+fn read_input(input: &Path) -> Result<Vec<u8>, LoweringError> {
+    let read = if input == Path::new("-") {
+        let mut source = vec![];
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut source).map(|_| source)
+    } else {
+        std::fs::read(input)
+    };
+    read.map_err(|err| LoweringError::Input {
+        path: input.display().to_string(),
+        reason: err.to_string(),
+    })
+}
+
+/// Parses the llvm ir `source` read from `input` in `context`.
+fn parse_module<'ctx>(
+    context: &'ctx Context,
+    source: &[u8],
+    input: &Path,
+) -> Result<Module<'ctx>, LoweringError> {
+    let name = input.display().to_string();
+    context
+        .create_module_from_ir(MemoryBuffer::create_from_memory_range_copy(source, &name))
+        .map_err(|err| LoweringError::Input {
+            path: name,
+            reason: err.to_string(),
+        })
+}
+
+/// Adds the functions calling one of the `skipped` functions to them, they can't be emitted without their callee.
+fn skip_callers(module: &Module, skipped: &mut Vec<(String, String)>) {
+    loop {
+        let caller = module.get_functions().find_map(|function| {
+            let name = function.get_name().to_str().unwrap();
+            if skipped.iter().any(|(skipped, _)| skipped == name) {
+                return None;
+            }
+            callees(function)
+                .find(|callee| skipped.iter().any(|(skipped, _)| skipped == callee))
+                .map(|callee| {
+                    (
+                        name.to_owned(),
+                        format!("calls skipped function `{}`", callee),
+                    )
+                })
+        });
+        match caller {
+            Some(caller) => skipped.push(caller),
+            None => return,
+        }
+    }
+}
+
+/// One line per function defined by `module` with the sierra types of its params and return value and its number of
+/// blocks, like `fib(u32, u32) -> u32 [3 blocks]`.
+fn function_listing(module: &Module) -> String {
+    module
+        .get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .map(|function| {
+            let params = function
+                .get_param_iter()
+                .map(|param| SierraType::from_llvm_type(param.get_type()).to_string())
+                .collect::<Vec<_>>();
+            let ret = function
+                .get_type()
+                .get_return_type()
+                .map_or("()".to_owned(), |ty| {
+                    SierraType::from_llvm_type(ty).to_string()
+                });
+            format!(
+                "{}({}) -> {} [{} blocks]\n",
+                function.get_name().to_str().unwrap(),
+                params.join(", "),
+                ret,
+                function.count_basic_blocks()
+            )
+        })
+        .collect()
+}
+
+/// Functions that are external entry points of the contract: the ones given with `--entry` and the ones with the
+/// `"starknet-external"` function attribute, in module order.
+fn entry_points(module: &Module, config: &Config) -> Result<Vec<String>, LoweringError> {
+    if let Some(name) = config
+        .entry_points
+        .iter()
+        .find(|name| module.get_function(name).is_none())
+    {
+        return Err(LoweringError::UnknownEntryPoint { name: name.clone() });
+    }
+    Ok(module
+        .get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .filter(|function| {
+            function
+                .get_string_attribute(AttributeLoc::Function, "starknet-external")
+                .is_some()
+                || config
+                    .entry_points
+                    .iter()
+                    .any(|name| function.get_name().to_str() == Ok(name))
+        })
+        .map(|function| function.get_name().to_str().unwrap().to_owned())
+        .collect())
+}
+
+/// Names of the functions called by `function`, once per call.
+fn callees<'ctx>(function: FunctionValue<'ctx>) -> impl Iterator<Item = String> + 'ctx {
+    function
+        .get_basic_block_iter()
+        .flat_map(|block| block.get_instructions())
+        .filter(|instr| instr.get_opcode() == InstructionOpcode::Call)
+        .filter_map(callee_name)
+}
+
+/// Builtins each function takes and returns, in the order of [`SierraBuilder::used_builtins`]: the ones its body uses,
+/// read from the signatures of `first_pass` (a lowering of the whole module), and the ones of the functions it calls
+/// since it passes them along.
+fn function_builtins(module: &Module, first_pass: &Program) -> FunctionBuiltins {
+    let mut builtins = first_pass
+        .funcs
+        .iter()
+        .map(|func| {
+            let used = func
+                .signature
+                .param_types
+                .iter()
+                .filter_map(|ty| {
+                    BUILTINS
+                        .into_iter()
+                        .find(|builtin| ty.to_string() == *builtin)
+                })
+                .collect::<HashSet<_>>();
+            (func.id.to_string(), used)
+        })
+        .collect::<HashMap<_, _>>();
+    let call_graph = module
+        .get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .map(|function| {
+            let name = function.get_name().to_str().unwrap().to_owned();
+            (name, callees(function).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    // Builtins go up the call graph until every caller has the builtins of its callees.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (caller, callees) in &call_graph {
+            for callee in callees {
+                let callee_builtins = builtins.get(callee).cloned().unwrap_or_default();
+                let caller_builtins = builtins.entry(caller.clone()).or_default();
+                for builtin in callee_builtins {
+                    changed |= caller_builtins.insert(builtin);
+                }
+            }
+        }
+    }
+    builtins
+        .into_iter()
+        .map(|(name, used)| {
+            let ordered = BUILTINS
+                .into_iter()
+                .filter(|builtin| used.contains(builtin))
+                .collect();
+            (name, ordered)
+        })
+        .collect()
+}
+
+/// Builtins of each function by name, see [`function_builtins`].
+type FunctionBuiltins = HashMap<String, Vec<&'static str>>;
+
+/// Program lowered by one thread with the origin and the source line of each statement, see
+/// [`SierraBuilder::lower_in_parallel`].
+type LoweredChunk = (Program, Vec<(String, InstructionOpcode)>, Vec<Option<u32>>);
+
+/// Name of the function called by `call`, `None` for indirect calls.
+fn callee_name(call: InstructionValue) -> Option<String> {
+    let callee = call.get_operand(call.get_num_operands() - 1)?.left()?;
+    callee.is_pointer_value().then(|| {
+        callee
+            .into_pointer_value()
+            .get_name()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    })
+}
+
+/// Whether `call` calls a variadic function other than `printf` (lowered to debug prints), sierra calls have a fixed
+/// number of arguments.
+fn is_variadic_call(module: &Module, call: InstructionValue) -> bool {
+    callee_name(call)
+        .filter(|callee| callee != "printf")
+        .and_then(|callee| module.get_function(&callee))
+        .is_some_and(|callee| callee.get_type().is_var_arg())
+}
+
+/// Whether `call` calls one of the signed overflow intrinsics (`llvm.sadd.with.overflow.i32`, ...). Integers are
+/// lowered to the unsigned sierra types whatever their signedness so the signed overflowing libfuncs (`i32_*`) can't
+/// take them, they're lowered by [`SierraBuilder::build_signed_overflow`] instead.
+fn is_signed_overflow_intrinsic(call: InstructionValue) -> bool {
+    callee_name(call).is_some_and(|callee| {
+        ["llvm.sadd.", "llvm.ssub.", "llvm.smul."]
+            .iter()
+            .any(|prefix| callee.starts_with(prefix) && callee.contains(".with.overflow."))
+    })
+}
+
+/// Calls (as `(caller, callee)`) through which `caller` can end up calling itself again, directly or through other
+/// functions (`is_even` calling `is_odd` calling `is_even`).
+fn recursive_calls(functions: &[FunctionValue]) -> HashSet<(String, String)> {
+    let call_graph = functions
+        .iter()
+        .map(|function| {
+            let name = function.get_name().to_str().unwrap().to_owned();
+            (name, callees(*function).collect::<HashSet<_>>())
+        })
+        .collect::<HashMap<_, _>>();
+    let reaches = |from: &String, to: &String| {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(function) = stack.pop() {
+            if function == to {
+                return true;
+            }
+            if visited.insert(function) {
+                stack.extend(call_graph.get(function).into_iter().flatten());
+            }
+        }
+        false
+    };
+    call_graph
+        .iter()
+        .flat_map(|(caller, callees)| callees.iter().map(move |callee| (caller, callee)))
+        .filter(|(caller, callee)| reaches(callee, caller))
+        .map(|(caller, callee)| (caller.clone(), callee.clone()))
+        .collect()
+}
+
+/// Whether `block` ends with an unconditional branch to the block laid out right after it, in which case its
+/// statements fall through to the ones of the next block.
+fn falls_through(block: BasicBlock) -> bool {
+    block.get_terminator().is_some_and(|terminator| {
+        terminator.get_opcode() == InstructionOpcode::Br
+            && terminator.get_num_operands() == 1
+            && terminator.get_operand(0).unwrap().right() == block.get_next_basic_block()
+    })
+}
+
+/// Line of the `!dbg` location attached to `instr`, if any.
+fn debug_line(instr: InstructionValue, dbg_kind_id: u32) -> Option<u32> {
+    let location = instr
+        .get_metadata(dbg_kind_id)?
+        .print_to_string()
+        .to_string();
+    // `!DILocation(line: 12, column: 5, scope: !7)`
+    let line = location.split("line: ").nth(1)?;
+    line[..line.find(|c: char| !c.is_ascii_digit())?]
+        .parse()
+        .ok()
+}
+
+/// Reads the llvm file `input` (`-` for stdin) and lowers it to a sierra program.
+pub fn compile(input: &Path, config: &Config) -> Result<Program, LoweringError> {
+    SierraBuilder::compile(input, config)
+}
+
+/// Lowers `input` like [`compile`] but writes what `--emit`, `--annotate`, `--list-functions` or `--split-output` ask
+/// for instead of returning the program.
+pub fn emit(input: &Path, config: &Config) -> Result<(), LoweringError> {
+    SierraBuilder::emit(input, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cairo_lang_sierra::ProgramParser;
+    use num_bigint::BigInt;
+
+    use super::*;
+
+    /// Path of the file `name` at the root of the repository.
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join(name)
+    }
+
+    fn fib(program: &Program, a: u32, b: u32, n: u32) -> Vec<BigInt> {
+        interpret::run(program, "fib", &[a, b, n].map(BigInt::from)).unwrap()
+    }
+
+    #[test]
+    fn reference_fib_validates_and_runs() {
+        let path = fixture("fib.sierra");
+        validate::validate_sierra_file(path.to_str().unwrap()).unwrap();
+        let program = ProgramParser::new()
+            .parse(&std::fs::read_to_string(path).unwrap())
+            .unwrap();
+        assert_eq!(fib(&program, 0, 1, 10), [BigInt::from(89)]);
+    }
+
+    #[test]
+    fn fib_lowers_to_a_valid_program_computing_fib() {
+        // The program is type checked by the lowering.
+        let program = compile(&fixture("fib.ll"), &Config::default()).unwrap();
+        validate::validate_program(&program).unwrap();
+        assert_eq!(fib(&program, 0, 1, 0), [BigInt::from(1)]);
+        assert_eq!(fib(&program, 0, 1, 1), [BigInt::from(1)]);
+        assert_eq!(fib(&program, 0, 1, 10), [BigInt::from(89)]);
+    }
+
+    #[test]
+    fn compile_returns_the_lowered_program() {
+        let program = compile(&fixture("fib.ll"), &Config::default()).unwrap();
+        assert!(program
+            .type_declarations
+            .iter()
+            .any(|declaration| declaration.id.to_string() == "u32"));
+        assert_eq!(program.funcs.len(), 1);
+        assert_eq!(program.funcs[0].id.to_string(), "fib");
+        assert!(program
+            .statements
+            .iter()
+            .any(|statement| matches!(statement, GenStatement::Return(_))));
+    }
+
+    #[test]
+    fn compile_reports_an_unreadable_input() {
+        let err = compile(&fixture("missing.ll"), &Config::default()).unwrap_err();
+        assert!(matches!(err, LoweringError::Input { .. }));
+    }
+
+    #[test]
+    fn lowering_is_deterministic() {
+        let sequential = compile(&fixture("fib.ll"), &Config::default()).unwrap();
+        for threads in [2, 4] {
+            let config = Config {
+                threads,
+                ..Config::default()
+            };
+            let program = compile(&fixture("fib.ll"), &config).unwrap();
+            if let Some(difference) = diff::first_difference(&sequential, &program) {
+                panic!(
+                    "lowering with {} thread(s) differs: {}",
+                    threads, difference
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lowering_on_more_or_fewer_threads_than_functions_is_deterministic() {
+        let source = b"
+define i32 @double(i32 %a) {
+  %r = add i32 %a, %a
+  ret i32 %r
+}
+
+define i32 @square(i32 %a) {
+  %r = mul i32 %a, %a
+  ret i32 %r
+}
+
+define i32 @sum(i32 %a, i32 %b) {
+  %r = add i32 %a, %b
+  ret i32 %r
+}
+
+define i32 @double_square(i32 %a) {
+  %s = call i32 @square(i32 %a)
+  %r = call i32 @double(i32 %s)
+  ret i32 %r
+}
+
+define i32 @sum_of_squares(i32 %a, i32 %b) {
+  %x = call i32 @square(i32 %a)
+  %y = call i32 @square(i32 %b)
+  %r = call i32 @sum(i32 %x, i32 %y)
+  ret i32 %r
+}
+";
+        let input = Path::new("functions.ll");
+        let sequential = SierraBuilder::compile_source(source, input, &Config::default()).unwrap();
+        for threads in [2, 3, 4, 8] {
+            let config = Config {
+                threads,
+                ..Config::default()
+            };
+            let program = SierraBuilder::compile_source(source, input, &config).unwrap();
+            if let Some(difference) = diff::first_difference(&sequential, &program) {
+                panic!(
+                    "lowering with {} thread(s) differs: {}",
+                    threads, difference
+                );
+            }
+            assert_eq!(
+                interpret::run(&program, "sum_of_squares", &[3, 4].map(BigInt::from)).unwrap(),
+                vec![BigInt::from(25)]
+            );
+            assert_eq!(
+                interpret::run(&program, "double_square", &[5].map(BigInt::from)).unwrap(),
+                vec![BigInt::from(50)]
+            );
+        }
+    }
+
+    #[test]
+    fn annotated_statements_name_their_block_and_opcode() {
+        let source = b"
+define i32 @f(i32 %a) {
+entry:
+  %c = icmp ult i32 %a, 10
+  br i1 %c, label %double, label %done
+
+double:
+  %d = add i32 %a, %a
+  br label %done
+
+done:
+  %r = phi i32 [ %a, %entry ], [ %d, %double ]
+  ret i32 %r
+}
+";
+        let input = Path::new("annotated.ll");
+        let config = Config {
+            annotate: true,
+            ..Config::default()
+        };
+        let context = Context::create();
+        let module = parse_module(&context, source, input).unwrap();
+        let (builder, _) =
+            SierraBuilder::lower(&context, &module, (source, input), &config).unwrap();
+        let annotated = builder.annotated_program();
+        for label in ["[entry ICmp] ", "[double Add] ", "[done Return] "] {
+            assert!(
+                annotated.contains(label),
+                "no `{}` in:\n{}",
+                label,
+                annotated
+            );
+        }
+        let run = |a: u32| interpret::run(&builder.sierra.program, "f", &[BigInt::from(a)]);
+        assert_eq!(run(4), Ok(vec![BigInt::from(8)]));
+        assert_eq!(run(12), Ok(vec![BigInt::from(12)]));
+    }
+
+    #[test]
+    fn lowered_fib_agrees_with_the_reference() {
+        let reference = ProgramParser::new()
+            .parse(&std::fs::read_to_string(fixture("fib.sierra")).unwrap())
+            .unwrap();
+        let program = compile(&fixture("fib.ll"), &Config::default()).unwrap();
+        for (a, b) in [(0, 1), (2, 1), (5, 3)] {
+            for n in 0..20 {
+                assert_eq!(
+                    fib(&program, a, b, n),
+                    fib(&reference, a, b, n),
+                    "fib({}, {}, {})",
+                    a,
+                    b,
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compact_output_is_smaller_and_runs_the_same() {
+        let program = compile(&fixture("fib.ll"), &Config::default()).unwrap();
+        let pretty = format!("{}\n", program);
+        let compact = format::compact_program(&program);
+        assert!(compact.len() < pretty.len());
+        let reparsed = ProgramParser::new().parse(&compact).unwrap();
+        for n in [0, 1, 10] {
+            assert_eq!(fib(&reparsed, 0, 1, n), fib(&program, 0, 1, n));
+        }
+    }
+
+    #[test]
+    fn statements_map_to_the_line_of_their_instruction() {
+        let source = br#"
+define i32 @f(i32 %a, i32 %b) !dbg !5 {
+  %s = add i32 %a, %b, !dbg !8
+  %m = mul i32 %s, %s, !dbg !9
+  ret i32 %m, !dbg !10
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "clang", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "f.c", directory: "/tmp")
+!2 = !{i32 2, !"Debug Info Version", i32 3}
+!5 = distinct !DISubprogram(name: "f", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{}
+!8 = !DILocation(line: 2, column: 3, scope: !5)
+!9 = !DILocation(line: 3, column: 3, scope: !5)
+!10 = !DILocation(line: 4, column: 3, scope: !5)
+"#;
+        let input = Path::new("debug.ll");
+        let config = Config {
+            emit: Some(Emit::DebugMap),
+            ..Config::default()
+        };
+        let context = Context::create();
+        let module = parse_module(&context, source, input).unwrap();
+        let (builder, _) =
+            SierraBuilder::lower(&context, &module, (source, input), &config).unwrap();
+        for ((_, opcode), line) in builder
+            .statement_origins
+            .iter()
+            .zip(&builder.statement_lines)
+        {
+            let expected = match opcode {
+                InstructionOpcode::Add => 2,
+                InstructionOpcode::Mul => 3,
+                _ => 4,
+            };
+            assert_eq!(*line, Some(expected), "{:?}", opcode);
+        }
+        assert!(builder.debug_map_json().contains("\"line\": 3"));
+        let run = |a: u32, b: u32| {
+            interpret::run(&builder.sierra.program, "f", &[a, b].map(BigInt::from))
+        };
+        assert_eq!(run(2, 3), Ok(vec![BigInt::from(25)]));
+    }
+
+    #[test]
+    fn module_without_functions_is_reported_unless_allowed() {
+        let source = b"
+@answer = global i32 42
+
+declare i32 @external(i32)
+";
+        let input = Path::new("empty.ll");
+        let err = SierraBuilder::compile_source(source, input, &Config::default()).unwrap_err();
+        assert!(matches!(err, LoweringError::NoFunctions));
+        let config = Config {
+            allow_empty: true,
+            ..Config::default()
+        };
+        let program = SierraBuilder::compile_source(source, input, &config).unwrap();
+        assert!(program.funcs.is_empty());
+        assert!(program.statements.is_empty());
+        validate::validate_program(&program).unwrap();
+        assert!(interpret::run(&program, "external", &[BigInt::from(1)]).is_err());
+    }
+
+    #[test]
+    fn unsupported_opcode_is_reported_with_its_function_and_line() {
+        let source = br#"
+define i32 @f(i32 %x) {
+  %r = add i32 %x, 1
+  ret i32 %r
+}
+
+define i32 @g(i32 %x) !dbg !5 {
+  %f = uitofp i32 %x to float, !dbg !8
+  %r = fptoui float %f to i32, !dbg !8
+  ret i32 %r, !dbg !8
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "clang", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "g.c", directory: "/tmp")
+!2 = !{i32 2, !"Debug Info Version", i32 3}
+!5 = distinct !DISubprogram(name: "g", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{}
+!8 = !DILocation(line: 7, column: 3, scope: !5)
+"#;
+        let input = Path::new("span.ll");
+        let err = SierraBuilder::compile_source(source, input, &Config::default()).unwrap_err();
+        let LoweringError::UnsupportedOpcode { opcode, span, .. } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(*opcode, InstructionOpcode::UIToFP);
+        assert_eq!(
+            *span,
+            errors::Span {
+                function: "g".to_owned(),
+                line: Some(7)
+            }
+        );
+        assert!(err.to_string().starts_with("in `g` at line 7: "), "{}", err);
+        let config = Config {
+            allow_partial: true,
+            ..Config::default()
+        };
+        let program = SierraBuilder::compile_source(source, input, &config).unwrap();
+        assert_eq!(
+            interpret::run(&program, "f", &[BigInt::from(1)]),
+            Ok(vec![BigInt::from(2)])
+        );
+    }
+
+    #[test]
+    fn invalid_program_fails_unless_verification_is_disabled() {
+        let source = b"define float @f(float %x) {\n  ret float %x\n}\n";
+        let input = Path::new("float.ll");
+        let err = SierraBuilder::compile_source(source, input, &Config::default()).unwrap_err();
+        assert!(
+            matches!(err, LoweringError::InvalidProgram { .. }),
+            "unexpected error: {}",
+            err
+        );
+        let config = Config {
+            no_verify: true,
+            ..Config::default()
+        };
+        let program = SierraBuilder::compile_source(source, input, &config).unwrap();
+        assert!(program
+            .type_declarations
+            .iter()
+            .any(|declaration| declaration.id.to_string() == "float"));
+    }
+
+    #[test]
+    fn unexpected_target_triple_fails_only_in_strict_mode() {
+        let source = r#"target triple = "wasm32-unknown-unknown"
+define i32 @f(i32 %x) {
+  ret i32 %x
+}
+"#;
+        let input = Path::new("wasm.ll");
+        let program =
+            SierraBuilder::compile_source(source.as_bytes(), input, &Config::default()).unwrap();
+        assert_eq!(
+            interpret::run(&program, "f", &[BigInt::from(4)]),
+            Ok(vec![BigInt::from(4)])
+        );
+        let config = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let err = SierraBuilder::compile_source(source.as_bytes(), input, &config).unwrap_err();
+        let LoweringError::UnexpectedTargetTriple { triple } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(triple, "wasm32-unknown-unknown");
+        let source = source.replace("wasm32-unknown-unknown", "x86_64-unknown-linux-gnu");
+        SierraBuilder::compile_source(source.as_bytes(), input, &config).unwrap();
+    }
+
+    #[test]
+    fn external_functions_and_entry_flags_fill_the_entry_points_table() {
+        let source = br#"
+define i32 @helper(i32 %x) {
+  ret i32 %x
+}
+
+define i32 @transfer(i32 %x) #0 {
+  %r = call i32 @helper(i32 %x)
+  ret i32 %r
+}
+
+define i32 @view(i32 %x) {
+  ret i32 %x
+}
+
+attributes #0 = { "starknet-external" }
+"#;
+        let input = Path::new("contract.ll");
+        let context = Context::create();
+        let module = parse_module(&context, source, input).unwrap();
+        let config = Config {
+            entry_points: vec!["view".to_owned()],
+            ..Config::default()
+        };
+        let entries = entry_points(&module, &config).unwrap();
+        assert_eq!(entries, ["transfer", "view"]);
+        let program = SierraBuilder::compile_source(source, input, &config).unwrap();
+        let json = format::entry_points_json(&program, &entries);
+        let transfer = program
+            .funcs
+            .iter()
+            .find(|function| function.id.to_string() == "transfer")
+            .unwrap();
+        assert!(json.contains(&format!(
+            "{{\"selector\": \"{:#x}\", \"function\": \"transfer\", \"entry_point\": {}}}",
+            format::entry_point_selector("transfer"),
+            transfer.entry_point.0
+        )));
+        assert!(json.contains("\"function\": \"view\""));
+        assert!(!json.contains("helper"));
+        let config = Config {
+            entry_points: vec!["missing".to_owned()],
+            ..Config::default()
+        };
+        let err = entry_points(&module, &config).unwrap_err();
+        assert!(matches!(err, LoweringError::UnknownEntryPoint { name } if name == "missing"));
+    }
+
+    #[test]
+    fn var_map_has_the_variable_of_each_value() {
+        let source = b"define i32 @f(i32 %n) {\n  %0 = add i32 %n, 1\n  %m = mul i32 %0, 2\n  ret i32 %m\n}\n";
+        let input = Path::new("var_map.ll");
+        let context = Context::create();
+        let module = parse_module(&context, source, input).unwrap();
+        let config = Config {
+            emit: Some(Emit::VarMap),
+            ..Config::default()
+        };
+        let (builder, _) =
+            SierraBuilder::lower(&context, &module, (source, input), &config).unwrap();
+        let json = builder.var_map_json(&module);
+        let prefix = "{\"f\": {\"%n\": {\"id\": 0, \"debug_name\": \"n\"}, \"%0\": {\"id\": ";
+        assert!(json.starts_with(prefix), "{}", json);
+        // Anonymous values are named after their variable.
+        let id = json[prefix.len()..].split(',').next().unwrap();
+        assert!(json.contains(&format!(
+            "\"%0\": {{\"id\": {}, \"debug_name\": \"v{}\"}}",
+            id, id
+        )));
+        assert!(json.contains("\"debug_name\": \"m\"}}}"), "{}", json);
+    }
+
+    #[test]
+    fn listing_has_the_signature_and_block_count_of_each_function() {
+        let source = b"declare i32 @ext(i32)\n\
+            define i64 @f(i32 %x, i1 %c) {\n\
+            entry:\n  br i1 %c, label %a, label %b\n\
+            a:\n  ret i64 1\n\
+            b:\n  ret i64 2\n\
+            }\n\
+            define void @g(ptr %p) {\n  ret void\n}\n";
+        let context = Context::create();
+        let module = parse_module(&context, source, Path::new("listing.ll")).unwrap();
+        assert_eq!(
+            function_listing(&module),
+            "f(u32, bool) -> u64 [3 blocks]\ng(u64) -> () [1 blocks]\n"
+        );
+    }
+
+    #[test]
+    fn split_output_writes_a_file_per_function() {
+        let source = b"define i32 @f(i32 %x) {\n  %r = add i32 %x, 1\n  ret i32 %r\n}\n\
+            define i32 @g(i32 %x) {\n  %r = mul i32 %x, 2\n  ret i32 %r\n}\n";
+        let context = Context::create();
+        let module = parse_module(&context, source, Path::new("split.ll")).unwrap();
+        let dir = std::env::temp_dir().join("llvm_to_sierra_split_output");
+        SierraBuilder::write_split_output(
+            &context,
+            &module,
+            &Config::default(),
+            &HashSet::new(),
+            &dir,
+        )
+        .unwrap();
+        for (name, arg, result) in [("f", 4, 5), ("g", 4, 8)] {
+            let text = std::fs::read_to_string(dir.join(format!("{}.sierra", name))).unwrap();
+            let program = ProgramParser::new().parse(&text).unwrap();
+            assert_eq!(program.funcs.len(), 1);
+            assert_eq!(program.funcs[0].id.to_string(), name);
+            assert_eq!(
+                interpret::run(&program, name, &[BigInt::from(arg)]),
+                Ok(vec![BigInt::from(result)])
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_file_is_taken_from_the_arguments() {
+        let dir = std::env::temp_dir().join("llvm_to_sierra_input");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("double.ll");
+        std::fs::write(
+            &path,
+            "define i32 @double(i32 %x) {\n  %r = mul i32 %x, 2\n  ret i32 %r\n}\n",
+        )
+        .unwrap();
+        let config = Config::from_args([path.display().to_string()]).unwrap();
+        assert_eq!(config.input.as_deref(), Some(path.as_path()));
+        let program = compile(&path, &config).unwrap();
+        assert_eq!(
+            interpret::run(&program, "double", &[BigInt::from(21)]),
+            Ok(vec![BigInt::from(42)])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        let err = compile(&path, &config).unwrap_err();
+        assert!(
+            matches!(&err, LoweringError::Input { path: input, .. } if *input == path.display().to_string())
+        );
+        assert_eq!(Config::from_args(Vec::new()).unwrap().input, None);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+
+use cairo_lang_sierra::ids::ConcreteTypeId;
+use cairo_lang_sierra::program::DeclaredTypeInfo;
+use inkwell::types::BasicTypeEnum;
+use num_bigint::BigInt;
+
+/// Sierra type of the pointers, they're lowered to their address (64 bits as in the usual llvm data layouts).
+pub const POINTER_TY: SierraType = SierraType::U64;
+
+/// Sierra builtins the lowering passes to the functions using them, in the order functions take them.
+pub const BUILTINS: [&str; 3] = ["RangeCheck", "Bitwise", "GasBuiltin"];
+
+/// Sierra type a value is lowered to. Displayed as the name of its declaration (`u32`, `Tuple<u32, bool>`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SierraType {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    Felt252,
+    Bool,
+    /// `Struct<ut@Tuple, fields...>`, llvm structs are lowered to tuples.
+    Struct(Vec<SierraType>),
+    /// Any other type (builtins, arrays...), by name.
+    Other(String),
+}
+
+impl SierraType {
+    /// Maps an llvm type (`i32`) to its sierra type. LLVM integers don't carry a sign so they're represented as
+    /// unsigned values, except for `i1` which is a sierra `bool`. Integers of other widths (`i3`, `i33` from
+    /// bitfields) are widened to the next unsigned type, the ones wider than 128 bits are `felt252`. Pointers are mapped
+    /// to [`POINTER_TY`]. Other types are parsed as sierra type names.
+    pub fn from_llvm(llvm_ty: &str) -> Self {
+        let llvm_ty = llvm_ty.trim_matches('"');
+        match llvm_ty {
+            "i1" => SierraType::Bool,
+            "ptr" => POINTER_TY,
+            _ => match llvm_ty
+                .strip_prefix('i')
+                .and_then(|bits| bits.parse::<u32>().ok())
+            {
+                Some(bits) if bits <= 8 => SierraType::U8,
+                Some(bits) if bits <= 16 => SierraType::U16,
+                Some(bits) if bits <= 32 => SierraType::U32,
+                Some(bits) if bits <= 64 => SierraType::U64,
+                Some(bits) if bits <= 128 => SierraType::U128,
+                Some(_) => SierraType::Felt252,
+                None => SierraType::from_name(llvm_ty),
+            },
+        }
+    }
+
+    /// Maps an llvm type to its sierra type like [`Self::from_llvm`], structs (named ones like `%struct.Foo` as well)
+    /// are mapped to tuples of their fields instead of being parsed from their name.
+    pub fn from_llvm_type(llvm_ty: BasicTypeEnum) -> Self {
+        match llvm_ty {
+            BasicTypeEnum::StructType(struct_ty) => SierraType::Struct(
+                struct_ty
+                    .get_field_types()
+                    .into_iter()
+                    .map(SierraType::from_llvm_type)
+                    .collect(),
+            ),
+            _ => SierraType::from_llvm(&llvm_ty.print_to_string().to_string()),
+        }
+    }
+
+    /// Parses the name of a sierra type as displayed by this type.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "u8" => SierraType::U8,
+            "u16" => SierraType::U16,
+            "u32" => SierraType::U32,
+            "u64" => SierraType::U64,
+            "u128" => SierraType::U128,
+            "u256" => SierraType::U256,
+            "felt252" => SierraType::Felt252,
+            "bool" => SierraType::Bool,
+            _ => SierraType::Other(name.to_owned()),
+        }
+    }
+
+    /// Bit width of the unsigned integer types.
+    pub fn bit_width(&self) -> Option<u32> {
+        match self {
+            SierraType::U8 => Some(8),
+            SierraType::U16 => Some(16),
+            SierraType::U32 => Some(32),
+            SierraType::U64 => Some(64),
+            SierraType::U128 => Some(128),
+            SierraType::U256 => Some(256),
+            _ => None,
+        }
+    }
+
+    /// Type of the full product of two integers of this type, given by `<ty>_wide_mul`. `u128` has no wide product.
+    pub fn wide_mul_type(&self) -> Option<SierraType> {
+        match self {
+            SierraType::U8 => Some(SierraType::U16),
+            SierraType::U16 => Some(SierraType::U32),
+            SierraType::U32 => Some(SierraType::U64),
+            SierraType::U64 => Some(SierraType::U128),
+            _ => None,
+        }
+    }
+
+    /// Type info of the declaration of this type. The builtins can't be dropped nor duplicated, they're threaded
+    /// through every function using them, and llvm arrays become sierra arrays which can't be duplicated. Structs
+    /// get theirs from their fields (see [`struct_type_info`]).
+    pub fn declared_type_info(&self) -> DeclaredTypeInfo {
+        if let SierraType::Struct(fields) = self {
+            return struct_type_info(fields.iter().map(SierraType::declared_type_info));
+        }
+        let (droppable, duplicatable) = match self {
+            SierraType::Other(name) if BUILTINS.contains(&name.as_str()) => (false, false),
+            SierraType::Other(name) if name.starts_with('[') => (true, false),
+            _ => (true, true),
+        };
+        DeclaredTypeInfo {
+            storable: true,
+            droppable,
+            duplicatable,
+            zero_sized: false,
+        }
+    }
+
+    pub fn concrete_id(&self) -> ConcreteTypeId {
+        ConcreteTypeId::from_string(self.to_string())
+    }
+
+    /// Name of the libfunc implementing `op` on this type (`u32_eq`, `u8_overflowing_add`).
+    pub fn libfunc_name(&self, op: &str) -> String {
+        format!("{}_{}", self, op)
+    }
+}
+
+impl fmt::Display for SierraType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SierraType::U8 => write!(f, "u8"),
+            SierraType::U16 => write!(f, "u16"),
+            SierraType::U32 => write!(f, "u32"),
+            SierraType::U64 => write!(f, "u64"),
+            SierraType::U128 => write!(f, "u128"),
+            SierraType::U256 => write!(f, "u256"),
+            SierraType::Felt252 => write!(f, "felt252"),
+            SierraType::Bool => write!(f, "bool"),
+            SierraType::Struct(fields) => write!(
+                f,
+                "Tuple<{}>",
+                fields
+                    .iter()
+                    .map(|field| field.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            SierraType::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Type info of a struct with fields of type info `fields`: it can be stored, dropped or duplicated if all of its
+/// fields can and it's zero sized if they all are (the empty tuple is the unit type).
+pub fn struct_type_info(fields: impl IntoIterator<Item = DeclaredTypeInfo>) -> DeclaredTypeInfo {
+    fields.into_iter().fold(
+        DeclaredTypeInfo {
+            storable: true,
+            droppable: true,
+            duplicatable: true,
+            zero_sized: true,
+        },
+        |info, field| DeclaredTypeInfo {
+            storable: info.storable && field.storable,
+            droppable: info.droppable && field.droppable,
+            duplicatable: info.duplicatable && field.duplicatable,
+            zero_sized: info.zero_sized && field.zero_sized,
+        },
+    )
+}
+
+/// Name of the sierra type of the llvm type `llvm_ty`, see [`SierraType::from_llvm`].
+pub fn llvm_int_ty_to_sierra(llvm_ty: &str) -> String {
+    SierraType::from_llvm(llvm_ty).to_string()
+}
+
+/// Converts the printed value of an llvm const to the value of the sierra const of type `sierra_ty`. LLVM prints
+/// constants as signed so the negative values of unsigned types are wrapped (`i128 -1` is the `u128` max) and the ones
+/// of `felt252` are reduced modulo the field prime (`-1` is `P - 1`), as are the `i256` ones it can't hold. Returns
+/// `None` for a value that isn't a decimal integer, like the hexadecimal floats (`0x3FF0000000000000`).
+pub fn sierra_const_value(sierra_ty: &SierraType, llvm_value: &str) -> Option<BigInt> {
+    let value = match llvm_value {
+        "true" => BigInt::from(1),
+        "false" => BigInt::from(0),
+        _ => llvm_value.parse::<BigInt>().ok()?,
+    };
+    let value = match sierra_ty.bit_width() {
+        Some(bit_width) if value < BigInt::from(0) => value + (BigInt::from(1) << bit_width),
+        None if *sierra_ty == SierraType::Felt252 => {
+            let prime: BigInt =
+                (BigInt::from(1) << 251) + BigInt::from(17) * (BigInt::from(1) << 192) + 1;
+            (value % &prime + &prime) % prime
+        }
+        _ => value,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn llvm_integers_map_to_core_types() {
+        for (llvm_ty, sierra_ty) in [
+            ("i1", "bool"),
+            ("i3", "u8"),
+            ("i8", "u8"),
+            ("i16", "u16"),
+            ("i32", "u32"),
+            ("i33", "u64"),
+            ("i64", "u64"),
+            ("i128", "u128"),
+            ("i129", "felt252"),
+            ("i256", "felt252"),
+            ("ptr", "u64"),
+        ] {
+            assert_eq!(llvm_int_ty_to_sierra(llvm_ty), sierra_ty, "{}", llvm_ty);
+        }
+        assert_eq!(SierraType::from_llvm("\"i32\""), SierraType::U32);
+    }
+
+    #[test]
+    fn struct_type_info_comes_from_the_fields() {
+        let builtin = SierraType::Other("RangeCheck".to_owned()).declared_type_info();
+        assert!(!builtin.droppable && !builtin.duplicatable);
+        let plain =
+            SierraType::Struct(vec![SierraType::U32, SierraType::Bool]).declared_type_info();
+        assert!(plain.droppable && plain.duplicatable && !plain.zero_sized);
+        let array = SierraType::Other("[4 x i32]".to_owned());
+        let with_array = SierraType::Struct(vec![SierraType::U32, array]).declared_type_info();
+        assert!(with_array.droppable && !with_array.duplicatable);
+        let unit = SierraType::Struct(vec![]).declared_type_info();
+        assert!(unit.zero_sized && unit.duplicatable);
+        let nested_unit = SierraType::Struct(vec![SierraType::Struct(vec![])]).declared_type_info();
+        assert!(nested_unit.zero_sized);
+    }
+
+    #[test]
+    fn negative_consts_of_unsigned_types_wrap() {
+        assert_eq!(
+            sierra_const_value(&SierraType::U32, "-1"),
+            Some(BigInt::from(u32::MAX))
+        );
+        assert_eq!(
+            sierra_const_value(&SierraType::U128, "-1"),
+            Some(BigInt::from(u128::MAX))
+        );
+        assert_eq!(
+            sierra_const_value(&SierraType::Bool, "true"),
+            Some(BigInt::from(1))
+        );
+    }
+
+    #[test]
+    fn consts_above_i128_max_are_kept() {
+        let value = "340282366920938463463374607431768211457";
+        assert_eq!(
+            sierra_const_value(&SierraType::Felt252, value),
+            value.parse::<BigInt>().ok()
+        );
+    }
+
+    #[test]
+    fn non_decimal_consts_are_rejected() {
+        assert_eq!(
+            sierra_const_value(&SierraType::U64, "0x3FF0000000000000"),
+            None
+        );
+        assert_eq!(sierra_const_value(&SierraType::Felt252, "1.5"), None);
+    }
+
+    #[test]
+    fn felt252_consts_are_reduced_modulo_the_prime() {
+        let prime: BigInt =
+            (BigInt::from(1) << 251) + BigInt::from(17) * (BigInt::from(1) << 192) + 1;
+        let felt = SierraType::Felt252;
+        assert_eq!(sierra_const_value(&felt, "-1"), Some(&prime - 1));
+        assert_eq!(
+            sierra_const_value(&felt, &prime.to_string()),
+            Some(BigInt::from(0))
+        );
+        let max_i256: BigInt = (BigInt::from(1) << 255) - 1;
+        assert!(sierra_const_value(&felt, &max_i256.to_string()).unwrap() < prime);
+    }
+
+    #[test]
+    fn names_parse_back_and_prefix_the_libfuncs() {
+        for ty in [
+            SierraType::U8,
+            SierraType::U16,
+            SierraType::U32,
+            SierraType::U64,
+            SierraType::U128,
+            SierraType::U256,
+            SierraType::Felt252,
+            SierraType::Bool,
+            SierraType::Other("RangeCheck".to_owned()),
+        ] {
+            assert_eq!(SierraType::from_name(&ty.to_string()), ty);
+            assert_eq!(
+                ty.concrete_id(),
+                ConcreteTypeId::from_string(ty.to_string())
+            );
+        }
+        assert_eq!(
+            SierraType::U8.libfunc_name("overflowing_add"),
+            "u8_overflowing_add"
+        );
+        assert_eq!(SierraType::Felt252.libfunc_name("sub"), "felt252_sub");
+        let tuple = SierraType::Struct(vec![SierraType::U32, SierraType::Bool]);
+        assert_eq!(tuple.to_string(), "Tuple<u32, bool>");
+        assert_eq!(SierraType::from_llvm("i64").libfunc_name("eq"), "u64_eq");
+    }
+}
@@ -1,17 +1,32 @@
+use std::collections::{HashMap, HashSet};
 
 use cairo_lang_sierra::{
-    ids::{ConcreteLibfuncId, ConcreteTypeId, GenericLibfuncId, VarId},
+    ids::{
+        ConcreteLibfuncId, ConcreteTypeId, FunctionId, GenericLibfuncId, GenericTypeId, UserTypeId,
+        VarId,
+    },
     program::{
-        ConcreteLibfuncLongId, GenBranchInfo,
-        GenBranchTarget, GenInvocation, GenStatement, GenericArg, LibfuncDeclaration,
-        StatementIdx,
+        ConcreteLibfuncLongId, ConcreteTypeLongId, DeclaredTypeInfo, Function, FunctionSignature,
+        GenBranchInfo, GenBranchTarget, GenInvocation, GenStatement, GenericArg,
+        LibfuncDeclaration, Param, StatementIdx, TypeDeclaration,
     },
 };
-use inkwell::values::{AnyValue, BasicValueEnum};
-use inkwell::{values::InstructionValue};
+use inkwell::basic_block::BasicBlock;
+use inkwell::module::Module;
+use inkwell::types::{BasicTypeEnum, IntType};
+use inkwell::values::{
+    AnyValue, AsValueRef, BasicValue, BasicValueEnum, FunctionValue, GlobalValue, IntValue,
+    PhiValue,
+};
+use inkwell::values::{InstructionOpcode, InstructionValue};
+use inkwell::IntPredicate;
 use num_bigint::BigInt;
 use smol_str::SmolStr;
 
+use crate::errors::{LoweringError, Span};
+use crate::types::{
+    llvm_int_ty_to_sierra, sierra_const_value, struct_type_info, SierraType, BUILTINS, POINTER_TY,
+};
 use crate::SierraBuilder;
 
 impl<'ctx> SierraBuilder<'ctx> {
@@ -29,144 +44,6701 @@ impl<'ctx> SierraBuilder<'ctx> {
                 results: results.into(),
             }],
         });
-        self.program.statements.push(statement);
+        self.sierra.program.statements.push(statement);
     }
 
+    /// Stores `args` in the temp vars `results` with `store_temp<ty>`, declaring it if needed.
     pub fn push_store_temp_statement(
         &mut self,
-        libfunc_id: ConcreteLibfuncId,
         ty: String,
         args: &[cairo_lang_sierra::ids::VarId],
         results: &[cairo_lang_sierra::ids::VarId],
     ) {
-        let func = LibfuncDeclaration {
-            id: ConcreteLibfuncId::from_string("store_temp"),
-            long_id: ConcreteLibfuncLongId {
-                generic_id: GenericLibfuncId::from_string("store_temp"),
-                generic_args: vec![GenericArg::Type(ConcreteTypeId::from_string(ty.clone()))],
-            },
+        let libfunc_id = self.register_libfunc(
+            "store_temp",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        self.temp_vars.extend(results.iter().cloned());
+        self.push_simple_basic_statement(libfunc_id, args, results);
+    }
+
+    /// Stores the deferred values defined in `block` that are read by other blocks before `block` branches away. A
+    /// deferred value is an expression on the frame and the branch can move it, the reading block needs it in a
+    /// temp slot like the values of the other blocks. Phis get their own copy in [`Self::store_phi_values`].
+    pub fn store_values_used_later(&mut self, block: BasicBlock<'ctx>) {
+        let function = block.get_parent().unwrap();
+        let used_later = function
+            .get_basic_block_iter()
+            .filter(|other| *other != block)
+            .flat_map(|other| other.get_instructions())
+            .filter(|instr| instr.get_opcode() != InstructionOpcode::Phi)
+            .flat_map(|instr| {
+                (0..instr.get_num_operands()).filter_map(move |i| instr.get_operand(i)?.left())
+            })
+            .filter(|operand| {
+                operand
+                    .as_instruction_value()
+                    .is_some_and(|instr| instr.get_parent() == Some(block))
+            })
+            .collect::<HashSet<_>>();
+        for val in block
+            .get_instructions()
+            .filter_map(|instr| BasicValueEnum::try_from(instr.as_any_value_enum()).ok())
+        {
+            if !used_later.contains(&val) || !self.deferred_values.remove(&val) {
+                continue;
+            }
+            let var = self.variables[&self.aliases.get(&val).copied().unwrap_or(val)].clone();
+            self.push_store_temp_statement(
+                SierraType::from_llvm_type(val.get_type()).to_string(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+        }
+    }
+
+    /// Stores the values the phis of the successors of `block` receive from it in the variables of the phis, right
+    /// before `block` branches to them.
+    pub fn store_phi_values(&mut self, block: BasicBlock<'ctx>) {
+        let phis = self.jump_to_phi.get(&block).cloned().unwrap_or_default();
+        let mut stores = phis
+            .iter()
+            .map(|(var_id, ty, var)| {
+                self.insert_type(ty.clone());
+                self.add_const_if_const(*var, ty.clone());
+                (var_id.clone(), ty.clone(), self.use_var(*var))
+            })
+            .collect::<Vec<_>>();
+        // The phis of a loop header can be read by its own back edge (`%a = phi [%b, %loop]`,
+        // `%b = phi [%a, %loop]`). All of them are read before any is overwritten, a source
+        // that is the variable of another phi is copied first.
+        for idx in 0..stores.len() {
+            let (var_id, ty, source) = stores[idx].clone();
+            let overwritten = stores
+                .iter()
+                .any(|(other, _, _)| *other == source && *other != var_id);
+            if overwritten {
+                let copy = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: source.debug_name.clone(),
+                };
+                self.push_store_temp_statement(ty, &[source], std::slice::from_ref(&copy));
+                stores[idx].2 = copy;
+            }
+        }
+        for (var_id, ty, source) in stores {
+            // A phi that keeps its value on this edge (`%i = phi [%i, %loop]`) is already
+            // stored in its variable.
+            if source == var_id && self.temp_vars.contains(&source) {
+                continue;
+            }
+            self.push_store_temp_statement(ty, &[source], std::slice::from_ref(&var_id))
+        }
+    }
+
+    /// Lowers `switch` on an integer. Dense cases (at least 3, covering at least half of their range) are dispatched
+    /// with a single `enum_match` on an enum with a variant per value of the range, the index (`value - min`) is
+    /// converted to it through a `BoundedInt` and the values out of the range go to the default block:
+    /// ```text
+    /// u32_overflowing_sub(rc, value, min) -> (rc, index)
+    /// downcast<u32, BoundedInt<0, 3>>(rc, index) { fallthrough(rc, bounded) default(rc) }
+    /// enum_from_bounded_int<SwitchCases<4>>(bounded) -> (cases)
+    /// enum_match<SwitchCases<4>>(cases) { case0() case1() case2() case3() }
+    /// ```
+    /// Sparse cases are compared one after the other with `<ty>_eq`.
+    pub fn build_switch(&mut self, switch: InstructionValue<'ctx>) {
+        let value = switch.get_operand(0).unwrap().left().unwrap();
+        let default = switch.get_operand(1).unwrap().right().unwrap();
+        let cases = (2..switch.get_num_operands())
+            .step_by(2)
+            .map(|i| {
+                let case = switch.get_operand(i).unwrap().left().unwrap();
+                let case = zero_extended_const(case.into_int_value());
+                (case, switch.get_operand(i + 1).unwrap().right().unwrap())
+            })
+            .collect::<Vec<_>>();
+        let ty = self.declare_llvm_type(value.get_type());
+        self.add_const_if_const(value, ty.clone());
+        let deferred =
+            self.deferred_values.contains(&value) || value.into_int_value().is_constant_int();
+        let var = self.use_var(value);
+        if deferred {
+            self.push_store_temp_statement(
+                ty.clone(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+        }
+        let values = cases
+            .iter()
+            .map(|(case, _)| case.clone())
+            .collect::<Vec<_>>();
+        let table = switch_table_range(&values)
+            .filter(|_| SierraType::from_name(&ty).bit_width().is_some());
+        if let Some((min, len)) = table {
+            let targets = (0..len)
+                .map(|offset| {
+                    let case = &min + offset;
+                    cases
+                        .iter()
+                        .find(|(value, _)| *value == case)
+                        .map_or(default, |(_, block)| *block)
+                })
+                .collect::<Vec<_>>();
+            self.build_switch_table(&ty, var, min, targets, default);
+        } else {
+            self.build_switch_chain(&ty, var, &cases, default);
+        }
+    }
+
+    /// Dispatches `var` to `targets[var - min]` with an `enum_match`, see [`Self::build_switch`].
+    fn build_switch_table(
+        &mut self,
+        ty: &str,
+        var: VarId,
+        min: BigInt,
+        targets: Vec<BasicBlock<'ctx>>,
+        default: BasicBlock<'ctx>,
+    ) {
+        let sierra_ty = SierraType::from_name(ty);
+        let range_check = self.range_check_var();
+        let index = if min == BigInt::from(0) {
+            var
+        } else {
+            let min_var = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            self.push_const_statement(ty.to_owned(), min.to_string(), min_var.clone());
+            self.push_store_temp_statement(
+                ty.to_owned(),
+                std::slice::from_ref(&min_var),
+                std::slice::from_ref(&min_var),
+            );
+            let index = VarId {
+                id: self.next_var() as u64,
+                debug_name: Some(SmolStr::from("switch_index")),
+            };
+            // Values below `min` wrap to values above the range and go to the default block.
+            let overflowing_sub =
+                self.register_libfunc(&sierra_ty.libfunc_name("overflowing_sub"), vec![]);
+            self.push_merged_branches(
+                overflowing_sub,
+                vec![range_check.clone(), var, min_var],
+                vec![range_check.clone(), index.clone()],
+            );
+            index
+        };
+        let bounded_ty = self.insert_bounded_int_type(targets.len() as u64 - 1);
+        let cases_ty = self.insert_switch_cases_type(targets.len());
+        let downcast = self.register_libfunc(
+            "downcast",
+            vec![
+                GenericArg::Type(ConcreteTypeId::from_string(ty)),
+                GenericArg::Type(ConcreteTypeId::from_string(&bounded_ty)),
+            ],
+        );
+        let bounded = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: downcast,
+                args: vec![range_check.clone(), index],
+                branches: vec![
+                    GenBranchInfo {
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check.clone(), bounded.clone()],
+                    },
+                    GenBranchInfo {
+                        target: GenBranchTarget::Statement(StatementIdx(usize::MAX)),
+                        results: vec![range_check],
+                    },
+                ],
+            }));
+        self.push_branch_stubs(vec![None, Some(default)]);
+        let enum_from_bounded_int = self.register_libfunc(
+            "enum_from_bounded_int",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&cases_ty))],
+        );
+        let cases = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
         };
-        if self.libfuncs.insert("store_temp".to_owned()) {
-            self.program.libfunc_declarations.push(func);
+        self.push_simple_basic_statement(
+            enum_from_bounded_int,
+            &[bounded],
+            std::slice::from_ref(&cases),
+        );
+        self.push_store_temp_statement(
+            cases_ty.clone(),
+            std::slice::from_ref(&cases),
+            std::slice::from_ref(&cases),
+        );
+        let enum_match = self.register_libfunc(
+            "enum_match",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&cases_ty))],
+        );
+        let branches = targets
+            .iter()
+            .map(|_| GenBranchInfo {
+                target: GenBranchTarget::Statement(StatementIdx(usize::MAX)),
+                results: vec![VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                }],
+            })
+            .collect();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: enum_match,
+                args: vec![cases],
+                branches,
+            }));
+        self.push_branch_stubs(targets.into_iter().map(Some).collect());
+    }
+
+    /// Compares `var` to each case with `<ty>_eq`, branching to the block of the first equal one, then jumps to
+    /// `default`. The cases but the last compare a copy of `var`, their branch to the case block drops it:
+    /// ```text
+    /// dup<u32>(x) -> (x, copy)
+    /// u32_const<1>() -> (case)
+    /// u32_eq(copy, case) { fallthrough() 5() }
+    /// branch_align() -> ()
+    /// jump() { 8() }
+    /// branch_align() -> ()
+    /// drop<u32>(x) -> ()
+    /// jump() { case_block() }
+    /// u32_const<10>() -> (case)
+    /// u32_eq(x, case) { fallthrough() 11() }
+    /// ```
+    fn build_switch_chain(
+        &mut self,
+        ty: &str,
+        var: VarId,
+        cases: &[(BigInt, BasicBlock<'ctx>)],
+        default: BasicBlock<'ctx>,
+    ) {
+        let sierra_ty = SierraType::from_name(ty);
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        let drop = self.register_libfunc(
+            "drop",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let eq = self.register_libfunc(&sierra_ty.libfunc_name("eq"), vec![]);
+        for (idx, (case, block)) in cases.iter().enumerate() {
+            let last = idx == cases.len() - 1;
+            let compared = if last {
+                var.clone()
+            } else {
+                let copy = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: var.debug_name.clone(),
+                };
+                self.push_simple_basic_statement(
+                    dup.clone(),
+                    std::slice::from_ref(&var),
+                    &[var.clone(), copy.clone()],
+                );
+                copy
+            };
+            let case_var = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            self.push_const_statement(ty.to_owned(), case.to_string(), case_var.clone());
+            let eq_idx = self.sierra.program.statements.len();
+            self.sierra
+                .program
+                .statements
+                .push(GenStatement::Invocation(GenInvocation {
+                    libfunc_id: eq.clone(),
+                    args: vec![compared, case_var],
+                    branches: vec![
+                        GenBranchInfo {
+                            target: GenBranchTarget::Fallthrough,
+                            results: vec![],
+                        },
+                        GenBranchInfo {
+                            target: GenBranchTarget::Statement(StatementIdx(usize::MAX)),
+                            results: vec![],
+                        },
+                    ],
+                }));
+            if last {
+                self.push_branch_stubs(vec![None, Some(*block)]);
+                continue;
+            }
+            self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+            let next_case = self.sierra.program.statements.len();
+            self.push_jump(usize::MAX);
+            self.set_branch_target(eq_idx, 1, self.sierra.program.statements.len());
+            self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+            self.push_simple_basic_statement(drop.clone(), std::slice::from_ref(&var), &[]);
+            self.block_targets
+                .insert(self.sierra.program.statements.len(), vec![Some(*block)]);
+            self.push_jump(usize::MAX);
+            self.set_branch_target(next_case, 0, self.sierra.program.statements.len());
         }
-        let statement = GenStatement::Invocation(GenInvocation {
-            libfunc_id,
-            args: args.into(),
-            branches: vec![GenBranchInfo {
-                target: GenBranchTarget::Fallthrough,
-                results: results.into(),
-            }],
+        // Without cases nothing consumed the value.
+        if cases.is_empty() {
+            self.push_simple_basic_statement(drop, &[var], &[]);
+        }
+        self.block_targets
+            .insert(self.sierra.program.statements.len(), vec![Some(default)]);
+        self.push_jump(usize::MAX);
+    }
+
+    /// Sets the target of the `branch`th branch of the statement at `idx` to the statement `target`, for the
+    /// branches pushed before their target.
+    fn set_branch_target(&mut self, idx: usize, branch: usize, target: usize) {
+        if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[idx] {
+            invocation.branches[branch].target = GenBranchTarget::Statement(StatementIdx(target));
+        }
+    }
+
+    /// Pushes the `enum_match<bool>` consuming the condition of the conditional branch `br`, its branches lead to the
+    /// false block then the true one.
+    pub fn build_bool_branch(&mut self, br: InstructionValue<'ctx>) {
+        let cond = br.get_operand(0).unwrap().left().unwrap();
+        self.insert_type("bool".to_owned());
+        let cond = self.use_var(cond);
+        let enum_match = self.register_libfunc(
+            "enum_match",
+            vec![GenericArg::Type(ConcreteTypeId::from_string("bool"))],
+        );
+        let branches = (0..2)
+            .map(|_| GenBranchInfo {
+                target: GenBranchTarget::Statement(StatementIdx(usize::MAX)),
+                results: vec![VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                }],
+            })
+            .collect();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: enum_match,
+                args: vec![cond],
+                branches,
+            }));
+        // `br i1 %cond, label %true, label %false` has its operands reversed.
+        let blocks = [2, 1].map(|i| br.get_operand(i).unwrap().right());
+        self.push_branch_stubs(blocks.into());
+    }
+
+    /// Lowers an unconditional `br label %dest` that doesn't fall through to a `jump`, its target is set once `dest` is
+    /// lowered.
+    pub fn build_jump(&mut self, br: InstructionValue<'ctx>) {
+        let dest = br.get_operand(0).unwrap().right().unwrap();
+        self.block_targets
+            .insert(self.sierra.program.statements.len(), vec![Some(dest)]);
+        self.push_jump(usize::MAX);
+    }
+
+    /// Sends each branch of the branching invocation that was just pushed to its block through a `branch_align` and a
+    /// `jump`. The targets of a libfunc with several branches have to be a `branch_align` that no other branch leads
+    /// to and can't be before it, while blocks are reached by several branches and loops jump back to their header.
+    /// The first branch is the fallthrough one, a `None` block continues with the statements pushed after the stubs.
+    pub fn push_branch_stubs(&mut self, blocks: Vec<Option<BasicBlock<'ctx>>>) {
+        let idx = self.sierra.program.statements.len() - 1;
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let mut continuing = vec![];
+        for (branch, block) in blocks.iter().enumerate() {
+            let stub = self.sierra.program.statements.len();
+            if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[idx] {
+                invocation.branches[branch].target = if branch == 0 {
+                    GenBranchTarget::Fallthrough
+                } else {
+                    GenBranchTarget::Statement(StatementIdx(stub))
+                };
+            }
+            self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+            match block {
+                Some(block) => {
+                    self.block_targets
+                        .insert(self.sierra.program.statements.len(), vec![Some(*block)]);
+                    self.push_jump(usize::MAX);
+                }
+                // The last stub falls through to what follows.
+                None if branch == blocks.len() - 1 => (),
+                None => {
+                    continuing.push(self.sierra.program.statements.len());
+                    self.push_jump(usize::MAX);
+                }
+            }
+        }
+        let end = self.sierra.program.statements.len();
+        for jump in continuing {
+            if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[jump]
+            {
+                invocation.branches[0].target = GenBranchTarget::Statement(StatementIdx(end));
+            }
+        }
+    }
+
+    /// Lowers `icmp` to a `bool`. The sierra comparisons branch (see [`branching_comparison`]), each branch stores
+    /// its `bool` in the result and they merge right after:
+    /// ```text
+    /// u32_lt(rc, a, b) { fallthrough(rc) 6(rc) }
+    /// branch_align() -> ()
+    /// struct_construct<Unit>() -> (unit)
+    /// enum_init<bool, 0>(unit) -> (result)
+    /// store_temp<bool>(result) -> (result)
+    /// jump() { 10() }
+    /// branch_align() -> ()
+    /// struct_construct<Unit>() -> (unit)
+    /// enum_init<bool, 1>(unit) -> (result)
+    /// store_temp<bool>(result) -> (result)
+    /// ```
+    /// Signed predicates compare the operands biased by `2^(N-1)` (see [`Self::push_sign_bias`]) with the unsigned
+    /// comparison.
+    pub fn build_comparison(&mut self, icmp: InstructionValue<'ctx>) {
+        let predicate = icmp.get_icmp_predicate().unwrap();
+        let signed_as = unsigned_predicate(predicate);
+        let (cond, swap_operands, negate) =
+            branching_comparison(signed_as.unwrap_or(predicate)).unwrap();
+        let (mut lhs, mut rhs) = self.comparison_operands(icmp);
+        if swap_operands {
+            std::mem::swap(&mut lhs, &mut rhs);
+        }
+        let sierra_ty = SierraType::from_llvm(&lhs.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        self.insert_type("bool".to_owned());
+        // The comparisons don't accept deferred values as their first operand and `lt`/`le` take no const at all.
+        let args = [lhs, rhs].map(|val| self.use_stored_var(val, &ty));
+        let args = if signed_as.is_some() {
+            args.map(|var| self.push_sign_bias(&sierra_ty, var))
+        } else {
+            args
+        };
+        // Only `eq` doesn't need the range check.
+        let range_check = (cond != "eq").then(|| self.range_check_var());
+        let result = self.bind_result_var(icmp);
+        let cmp_libfunc = self.register_libfunc(&sierra_ty.libfunc_name(cond), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let cmp_idx = self.sierra.program.statements.len();
+        let branches = [(); 2].map(|_| GenBranchInfo {
+            // The target of the true branch is set once the false one is pushed.
+            target: GenBranchTarget::Fallthrough,
+            results: range_check.clone().into_iter().collect(),
         });
-        self.program.statements.push(statement);
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: cmp_libfunc,
+                args: range_check.clone().into_iter().chain(args).collect(),
+                branches: branches.into(),
+            }));
+        // The false branch falls through, the true one jumps.
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.push_bool_const(negate, result.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&result),
+            std::slice::from_ref(&result),
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let true_branch = self.sierra.program.statements.len();
+        self.set_branch_target(cmp_idx, 1, true_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_bool_const(!negate, result.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&result),
+            std::slice::from_ref(&result),
+        );
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
     }
 
-    pub fn build_jump_basic_statement(
-        &self,
-        libfunc_id: ConcreteLibfuncId,
-        dest1: u32,
-        dest2: u32,
-    ) -> GenStatement<StatementIdx> {
-        GenStatement::Invocation(GenInvocation {
+    /// Adds `2^(N-1)` to the `N` bits integer `var`, wrapping with `u<N>_overflowing_add`. It maps the two's
+    /// complement values in order: `-2^(N-1)` to `0`, `-1` to `2^(N-1) - 1`, `0` to `2^(N-1)` and `2^(N-1) - 1` to
+    /// `2^N - 1`, so the signed comparisons are the unsigned ones of the biased values.
+    fn push_sign_bias(&mut self, sierra_ty: &SierraType, var: VarId) -> VarId {
+        let ty = sierra_ty.to_string();
+        let bit_width = sierra_ty
+            .bit_width()
+            .expect("Signed comparisons are only lowered for integers");
+        let bias = self.push_stored_const(&ty, BigInt::from(1) << (bit_width - 1));
+        let biased = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let range_check = self.range_check_var();
+        let libfunc_id = self.register_libfunc(&sierra_ty.libfunc_name("overflowing_add"), vec![]);
+        self.push_merged_branches(
             libfunc_id,
-            args: Vec::new(),
-            branches: vec![
-                GenBranchInfo {
-                    target: GenBranchTarget::Statement(StatementIdx(dest1 as usize)),
-                    results: Vec::new(),
-                },
-                GenBranchInfo {
-                    target: GenBranchTarget::Statement(StatementIdx(dest2 as usize)),
-                    results: Vec::new(),
-                },
-            ],
-        })
+            vec![range_check.clone(), var, bias],
+            vec![range_check, biased.clone()],
+        );
+        biased
     }
-    pub fn build_binary_int_func(
-        &mut self,
-        instr: InstructionValue<'ctx>,
-        concrete_id: ConcreteLibfuncId,
-    ) {
-        // Get the 2 operands ex: in `let _ = a == b;` we get a and b
-        let first_val = unsafe { instr.get_operand_unchecked(0).unwrap().left().unwrap() };
-        let scnd_val = unsafe { instr.get_operand_unchecked(1).unwrap().left().unwrap() };
-        // get their types
-        let mut first_ty = first_val.get_type().to_string();
-        let mut scnd_ty = scnd_val.get_type().to_string();
-        // removes the quotes
-        first_ty.retain(|c| c != '"');
-        scnd_ty.retain(|c| c != '"');
-
-        // sanity check
-        assert_eq!(first_ty, scnd_ty, "Comparison should have the same types");
-        // Insert the types in sierra program (only need one as they're equal)
-        self.insert_type(first_ty.clone());
-        // Get the condition
-        // Create the const function if one of the operands is a const. Add it to the declaration and statements
-        self.add_const_if_const(first_val, first_ty.clone());
-        self.add_const_if_const(scnd_val, scnd_ty.clone());
-        // Args of the comparison function
-        let args = [
-            self.variables.get(&first_val).unwrap().clone(),
-            self.variables.get(&scnd_val).unwrap().clone(),
-        ];
-        // result variable of the comparison
-        let mut result_var_id = VarId {
+
+    /// Pushes the const `value` of type `ty` and stores it, for the libfuncs that don't accept deferred values.
+    fn push_stored_const(&mut self, ty: &str, value: BigInt) -> VarId {
+        let var = VarId {
             id: self.next_var() as u64,
             debug_name: None,
         };
-        if let Ok(basic_value_enum) = instr.as_any_value_enum().try_into() {
-            self.variables
-                .insert(basic_value_enum, result_var_id.clone());
-            let res_name = basic_value_enum.get_name().to_str().unwrap();
-            result_var_id.debug_name = (!res_name.is_empty()).then_some(SmolStr::from(res_name));
+        self.push_const_statement(ty.to_owned(), value.to_string(), var.clone());
+        self.push_store_temp_statement(
+            ty.to_owned(),
+            std::slice::from_ref(&var),
+            std::slice::from_ref(&var),
+        );
+        var
+    }
+
+    /// Operands compared by `icmp`, the ones of the `sub` it compares to zero if they're lowered together.
+    fn comparison_operands(
+        &self,
+        icmp: InstructionValue<'ctx>,
+    ) -> (BasicValueEnum<'ctx>, BasicValueEnum<'ctx>) {
+        // `(a - b) == 0` is `a == b`, whether the sub wraps or not.
+        let instr = self.zero_compared_sub(icmp).unwrap_or(icmp);
+        (
+            instr.get_operand(0).unwrap().left().unwrap(),
+            instr.get_operand(1).unwrap().left().unwrap(),
+        )
+    }
+
+    /// Lowers `llvm.sadd.with.overflow.iN` (`ssub`, `smul`) to the wrapping operation on the unsigned representation
+    /// and an overflow flag. The flag is computed on the signed values as felts, `a - 2^(N-1)` for the biased operand
+    /// `a` (see [`Self::push_sign_bias`]): the result fits in `N` bits if it's in `[-2^(N-1), 2^(N-1))`, that is if
+    /// it converts to a `u<N>` once `2^(N-1)` is added back. The `extractvalue`s of the returned struct read the
+    /// wrapped value and the flag.
+    pub fn build_signed_overflow(&mut self, call: InstructionValue<'ctx>) {
+        let callee = crate::callee_name(call).unwrap();
+        // `llvm.sadd.with.overflow.i32` is the `sadd` of two `i32`s.
+        let op = &callee["llvm.s".len().."llvm.sadd".len()];
+        let operands = [0, 1].map(|i| call.get_operand(i).unwrap().left().unwrap());
+        let sierra_ty = SierraType::from_llvm(&operands[0].get_type().to_string());
+        let ty = sierra_ty.to_string();
+        let felt = SierraType::Felt252.to_string();
+        self.insert_type(ty.clone());
+        self.insert_type(felt.clone());
+        self.insert_type("bool".to_owned());
+        let args = operands.map(|val| self.use_stored_var(val, &ty));
+        let copies = args.clone().map(|var| self.push_dup(&ty, var));
+        let bit_width = operands[0].into_int_value().get_type().get_bit_width();
+        let result = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        if op == "mul" {
+            self.push_wrapping_mul(&sierra_ty, args, bit_width, result.clone());
+        } else {
+            let range_check = self.range_check_var();
+            let libfunc_id = self.register_libfunc(
+                &sierra_ty.libfunc_name(&format!("overflowing_{}", op)),
+                vec![],
+            );
+            let [lhs, rhs] = args;
+            self.push_merged_branches(
+                libfunc_id,
+                vec![range_check.clone(), lhs, rhs],
+                vec![range_check, result.clone()],
+            );
         }
-        // Insert the function call in the statements and declaration
-        self.push_simple_basic_statement(concrete_id, &args, &[result_var_id]);
+
+        let bias = BigInt::from(1) << (bit_width - 1);
+        let to_felt = self.register_libfunc(&sierra_ty.libfunc_name("to_felt252"), vec![]);
+        let [lhs, rhs] = copies.map(|var| {
+            let biased = self.push_sign_bias(&sierra_ty, var);
+            let unsigned = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            self.push_simple_basic_statement(
+                to_felt.clone(),
+                &[biased],
+                std::slice::from_ref(&unsigned),
+            );
+            let bias = self.push_stored_const(&felt, bias.clone());
+            self.push_felt_op("sub", unsigned, bias)
+        });
+        let signed_result = self.push_felt_op(op, lhs, rhs);
+        let bias = self.push_stored_const(&felt, bias);
+        let biased_result = self.push_felt_op("add", signed_result, bias);
+
+        let overflow = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let range_check = self.range_check_var();
+        let fitting = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let try_from = self.register_libfunc(&sierra_ty.libfunc_name("try_from_felt252"), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let try_from_idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: try_from,
+                args: vec![range_check.clone(), biased_result],
+                branches: vec![
+                    GenBranchInfo {
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check.clone(), fitting.clone()],
+                    },
+                    GenBranchInfo {
+                        // Set once the fitting branch is pushed.
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check],
+                    },
+                ],
+            }));
+        let drop = self.register_libfunc(
+            "drop",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.push_simple_basic_statement(drop, &[fitting], &[]);
+        self.push_bool_const(false, overflow.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&overflow),
+            std::slice::from_ref(&overflow),
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let overflow_branch = self.sierra.program.statements.len();
+        self.set_branch_target(try_from_idx, 1, overflow_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_bool_const(true, overflow.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&overflow),
+            std::slice::from_ref(&overflow),
+        );
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
+        self.bind_extracted_fields(call, vec![(result, ty), (overflow, "bool".to_owned())]);
     }
 
-    /// Adds a const function if the int value is a const. Adds the libfunc declaration and adds the call in the
-    /// statements list as well.
-    pub fn add_const_if_const(&mut self, val: BasicValueEnum<'ctx>, ty: String) {
-        let val_int = val.into_int_value();
-        if val_int.is_constant_int() {
-            // Get the llvm value of the const so smth like `i32 0` if it's a const
-            let int_value = val_int
-                .print_to_string()
-                .to_string()
-                .split_whitespace()
-                .last()
-                .unwrap()
-                .to_owned();
+    /// Pushes `felt252_<op>` of `lhs` and `rhs` and stores the result, the felt operations don't take deferred
+    /// values.
+    fn push_felt_op(&mut self, op: &str, lhs: VarId, rhs: VarId) -> VarId {
+        let felt = SierraType::Felt252;
+        let libfunc_id = self.register_libfunc(&felt.libfunc_name(op), vec![]);
+        let result = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.push_simple_basic_statement(libfunc_id, &[lhs, rhs], std::slice::from_ref(&result));
+        self.push_store_temp_statement(
+            felt.to_string(),
+            std::slice::from_ref(&result),
+            std::slice::from_ref(&result),
+        );
+        result
+    }
 
-            let fn_name = format!("const_as_immediate<{}, {}>", ty, int_value);
+    /// Pushes a `dup` of `var` and returns the copy, `var` is left to the other reader.
+    fn push_dup(&mut self, ty: &str, var: VarId) -> VarId {
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        let copy = VarId {
+            id: self.next_var() as u64,
+            debug_name: var.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(
+            dup,
+            std::slice::from_ref(&var),
+            &[var.clone(), copy.clone()],
+        );
+        copy
+    }
 
-            let func = LibfuncDeclaration {
-                id: ConcreteLibfuncId::from_string(&fn_name),
-                long_id: ConcreteLibfuncLongId {
-                    generic_id: GenericLibfuncId::from_string("const"),
-                    generic_args: vec![
-                        GenericArg::Type(ConcreteTypeId::from_string(ty.clone())),
-                        GenericArg::Value(BigInt::from(int_value.parse::<i128>().unwrap())),
-                    ],
-                },
+    /// Binds the `extractvalue`s of the struct returned by `call` to the variables (with their type) of its fields,
+    /// the ones extracting an already extracted field read the variable of the first one. Fields nothing extracts are
+    /// dropped.
+    fn bind_extracted_fields(
+        &mut self,
+        call: InstructionValue<'ctx>,
+        fields: Vec<(VarId, String)>,
+    ) {
+        let returned = BasicValueEnum::try_from(call.as_any_value_enum()).unwrap();
+        // Only the fields are read.
+        self.remaining_uses.remove(&returned);
+        let extracts = call
+            .get_parent()
+            .unwrap()
+            .get_parent()
+            .unwrap()
+            .get_basic_blocks()
+            .into_iter()
+            .flat_map(|block| block.get_instructions())
+            .filter(|instr| {
+                instr.get_opcode() == InstructionOpcode::ExtractValue
+                    && instr.get_operand(0).unwrap().left() == Some(returned)
+            })
+            .collect::<Vec<_>>();
+        for (index, (var, ty)) in fields.into_iter().enumerate() {
+            let mut reads = extracts
+                .iter()
+                .filter(|extract| aggregate_indices(**extract) == [index])
+                .map(|extract| BasicValueEnum::try_from(extract.as_any_value_enum()).unwrap());
+            let Some(first) = reads.next() else {
+                let drop = self.register_libfunc(
+                    "drop",
+                    vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+                );
+                self.push_simple_basic_statement(drop, &[var], &[]);
+                continue;
             };
-            // if not declared yet declare it
-            if self.libfuncs.insert(fn_name.clone()) {
-                self.program.libfunc_declarations.push(func);
+            // An unread extract is dropped once it's lowered (see `Self::drop_if_unused`).
+            self.variables.insert(first, var);
+            for other in reads {
+                let uses = self.remaining_uses.remove(&other).unwrap_or_default();
+                if uses > 0 {
+                    *self.remaining_uses.entry(first).or_default() += uses;
+                }
+                self.aliases.insert(other, first);
             }
-            // Var id for the const.
-            let next_var = VarId {
+        }
+        self.overflow_extracts.extend(extracts);
+    }
+
+    /// Lowers a wrapping llvm `add`/`sub` with `u<N>_overflowing_<op>` as sierra has no wrapping arithmetic. Both
+    /// branches give the wrapped result, llvm `add` wraps and an overflowing `add nuw`/`add nsw` is poison, so both
+    /// of them continue with it. With `mask_narrow` the result of an operation on a narrow integer (`i3` lowered to
+    /// `u8`) is reduced to its llvm width, otherwise it only wraps at the bounds of the sierra type.
+    pub fn build_overflowing_op(
+        &mut self,
+        instr: InstructionValue<'ctx>,
+        op: &str,
+        mask_narrow: bool,
+    ) {
+        let first_val = instr.get_operand(0).unwrap().left().unwrap();
+        let scnd_val = instr.get_operand(1).unwrap().left().unwrap();
+        let sierra_ty = SierraType::from_llvm(&first_val.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        if let Some(folded) = Self::fold_binary_consts(instr, first_val, scnd_val) {
+            let result_var_id = self.bind_result_var(instr);
+            self.push_const_statement(ty, folded.to_string(), result_var_id);
+            return;
+        }
+        // The overflowing libfuncs don't accept deferred values and consts are deferred.
+        let args = [first_val, scnd_val].map(|val| self.use_stored_var(val, &ty));
+        let range_check = self.range_check_var();
+        let result = self.bind_result_var(instr);
+        let bit_width = first_val.into_int_value().get_type().get_bit_width();
+        let narrow = mask_narrow && sierra_ty.bit_width() != Some(bit_width);
+        let wrapped = if narrow {
+            VarId {
                 id: self.next_var() as u64,
-                debug_name: Some(SmolStr::from(format!("const_{}<{}>", ty, int_value))),
+                debug_name: None,
+            }
+        } else {
+            result.clone()
+        };
+        let libfunc_id = self.register_libfunc(
+            &sierra_ty.libfunc_name(&format!("overflowing_{}", op)),
+            vec![],
+        );
+        self.push_merged_branches(
+            libfunc_id,
+            vec![range_check.clone(), args[0].clone(), args[1].clone()],
+            vec![range_check, wrapped.clone()],
+        );
+        if narrow {
+            let modulus = BigInt::from(1) << bit_width;
+            self.push_remainder_by_const(&sierra_ty, wrapped, modulus, result);
+        }
+    }
+
+    /// Returns the masked value and the number of bits kept if `and` masks the low bits of a value (`and i32 %x, 255`).
+    pub fn low_bits_mask(
+        &self,
+        and: InstructionValue<'ctx>,
+    ) -> Option<(BasicValueEnum<'ctx>, u32)> {
+        let lhs = and.get_operand(0).unwrap().left().unwrap();
+        let rhs = and.get_operand(1).unwrap().left().unwrap();
+        if !lhs.is_int_value() {
+            return None;
+        }
+        let (value, mask) = if rhs.into_int_value().is_constant_int() {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        let mask = mask.into_int_value().get_zero_extended_constant()?;
+        // `0b0..01..1`: adding one leaves a single bit set (or overflows when all the bits are set).
+        let contiguous = mask != 0 && mask.checked_add(1).is_none_or(|next| next & mask == 0);
+        (contiguous && !value.into_int_value().is_constant_int())
+            .then_some((value, mask.count_ones()))
+    }
+
+    /// Lowers `and %x, 2^k - 1` to the remainder of `x` divided by `2^k` with `<ty>_safe_divmod`. Masking all the bits
+    /// is `x` itself.
+    pub fn build_low_bits_mask(&mut self, and: InstructionValue<'ctx>) {
+        let (value, bits) = self.low_bits_mask(and).unwrap();
+        let result = BasicValueEnum::try_from(and.as_any_value_enum()).unwrap();
+        let bit_width = value.into_int_value().get_type().get_bit_width();
+        if bits >= bit_width {
+            return self.alias_value(result, value);
+        }
+        self.build_remainder_by_const(and, value, BigInt::from(1) << bits);
+    }
+
+    /// Lowers `udiv`/`urem` with `<ty>_safe_divmod`, binding the result of the other one of the same operands later
+    /// in the block as well. A const divisor is passed as a `NonZero` const, otherwise it's checked with `<ty>_is_zero`
+    /// and a division by zero, undefined in llvm, gives zeros:
+    /// ```text
+    /// u32_is_zero(b) { fallthrough() 6(b) }
+    /// branch_align() -> ()
+    /// u32_const<0>() -> (q)
+    /// store_temp<u32>(q) -> (q)
+    /// jump() { 10() }
+    /// branch_align() -> ()
+    /// u32_safe_divmod(rc, a, b) -> (rc, q, r)
+    /// drop<u32>(r) -> ()
+    /// store_temp<u32>(q) -> (q)
+    /// ```
+    pub fn build_divmod(&mut self, instr: InstructionValue<'ctx>) {
+        let dividend = instr.get_operand(0).unwrap().left().unwrap();
+        let divisor = instr.get_operand(1).unwrap().left().unwrap();
+        let sierra_ty = SierraType::from_llvm(&dividend.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        let dividend = self.use_stored_var(dividend, &ty);
+        let const_divisor = divisor
+            .into_int_value()
+            .get_zero_extended_constant()
+            .filter(|divisor| *divisor != 0);
+        let divisor = match const_divisor {
+            Some(divisor) => self.push_non_zero_const(&ty, BigInt::from(divisor)),
+            None => self.use_stored_var(divisor, &ty),
+        };
+        let result = Some(self.bind_result_var(instr));
+        let sibling = self.divmod_sibling(instr).map(|sibling| {
+            self.fused_divmods.insert(sibling);
+            self.bind_result_var(sibling)
+        });
+        let (quotient, remainder) = if instr.get_opcode() == InstructionOpcode::UDiv {
+            (result, sibling)
+        } else {
+            (sibling, result)
+        };
+        self.push_checked_divmod(
+            &sierra_ty,
+            dividend,
+            divisor,
+            const_divisor.is_some(),
+            quotient,
+            remainder,
+        );
+    }
+
+    /// Pushes the `<ty>_safe_divmod` of `dividend` by `divisor` binding `quotient` and `remainder` (see
+    /// [`Self::build_divmod`]), a divisor that isn't a `NonZero` const is checked with `<ty>_is_zero` first.
+    fn push_checked_divmod(
+        &mut self,
+        sierra_ty: &SierraType,
+        dividend: VarId,
+        divisor: VarId,
+        const_divisor: bool,
+        quotient: Option<VarId>,
+        remainder: Option<VarId>,
+    ) {
+        let ty = sierra_ty.to_string();
+        if const_divisor {
+            return self.push_divmod(sierra_ty, dividend, divisor, quotient, remainder);
+        }
+        self.insert_non_zero_type(&ty);
+        let non_zero = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let is_zero = self.register_libfunc(&sierra_ty.libfunc_name("is_zero"), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: is_zero,
+                args: vec![divisor],
+                branches: vec![
+                    GenBranchInfo {
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![],
+                    },
+                    GenBranchInfo {
+                        // Set once the zero branch is pushed.
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![non_zero.clone()],
+                    },
+                ],
+            }));
+        let results: Vec<_> = [quotient.clone(), remainder.clone()]
+            .into_iter()
+            .flatten()
+            .collect();
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        for result in &results {
+            self.push_const_statement(ty.clone(), "0".to_owned(), result.clone());
+        }
+        self.push_store_temp_statement(ty.clone(), &results, &results);
+        let jump = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let non_zero_branch = self.sierra.program.statements.len();
+        if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[idx] {
+            invocation.branches[1].target =
+                GenBranchTarget::Statement(StatementIdx(non_zero_branch));
+        }
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_divmod(sierra_ty, dividend, non_zero, quotient, remainder);
+        self.push_store_temp_statement(ty, &results, &results);
+        let end = self.sierra.program.statements.len();
+        if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[jump] {
+            invocation.branches[0].target = GenBranchTarget::Statement(StatementIdx(end));
+        }
+    }
+
+    /// Lowers `sdiv`/`srem` to the unsigned division of the magnitudes of the operands (see [`Self::push_magnitude`]),
+    /// binding the result of the other one of the same operands as well. The remainder takes the sign of the
+    /// dividend and the quotient is negative when only one of the operands is. Like `udiv` a division by zero gives
+    /// zeros.
+    pub fn build_signed_divmod(&mut self, instr: InstructionValue<'ctx>) {
+        let dividend = instr.get_operand(0).unwrap().left().unwrap();
+        let divisor = instr.get_operand(1).unwrap().left().unwrap();
+        let sierra_ty = SierraType::from_llvm(&dividend.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        self.insert_type("bool".to_owned());
+        let dividend = self.use_stored_var(dividend, &ty);
+        let (dividend, dividend_negative) = self.push_magnitude(&sierra_ty, dividend);
+        let const_divisor = divisor
+            .into_int_value()
+            .get_sign_extended_constant()
+            .filter(|divisor| *divisor != 0);
+        let (divisor, divisor_negative) = match const_divisor {
+            Some(divisor) => {
+                let negative = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                };
+                self.push_bool_const(divisor < 0, negative.clone());
+                self.push_store_temp_statement(
+                    "bool".to_owned(),
+                    std::slice::from_ref(&negative),
+                    std::slice::from_ref(&negative),
+                );
+                let magnitude = self.push_non_zero_const(&ty, BigInt::from(divisor.unsigned_abs()));
+                (magnitude, negative)
+            }
+            None => {
+                let divisor = self.use_stored_var(divisor, &ty);
+                self.push_magnitude(&sierra_ty, divisor)
+            }
+        };
+        let result = Some(self.bind_result_var(instr));
+        let sibling = self.divmod_sibling(instr).map(|sibling| {
+            self.fused_divmods.insert(sibling);
+            self.bind_result_var(sibling)
+        });
+        let (quotient, remainder) = if instr.get_opcode() == InstructionOpcode::SDiv {
+            (result, sibling)
+        } else {
+            (sibling, result)
+        };
+        let [quotient_magnitude, remainder_magnitude] = [&quotient, &remainder].map(|result| {
+            result.as_ref().map(|_| VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            })
+        });
+        self.push_checked_divmod(
+            &sierra_ty,
+            dividend,
+            divisor,
+            const_divisor.is_some(),
+            quotient_magnitude.clone(),
+            remainder_magnitude.clone(),
+        );
+        let drop_bool = self.register_libfunc(
+            "drop",
+            vec![GenericArg::Type(ConcreteTypeId::from_string("bool"))],
+        );
+        match quotient.zip(quotient_magnitude) {
+            Some((quotient, magnitude)) => {
+                let dividend_negative = if remainder.is_some() {
+                    self.push_dup("bool", dividend_negative.clone())
+                } else {
+                    dividend_negative.clone()
+                };
+                let xor = self.register_libfunc("bool_xor_impl", vec![]);
+                let negative = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                };
+                self.push_simple_basic_statement(
+                    xor,
+                    &[dividend_negative, divisor_negative],
+                    std::slice::from_ref(&negative),
+                );
+                self.push_store_temp_statement(
+                    "bool".to_owned(),
+                    std::slice::from_ref(&negative),
+                    std::slice::from_ref(&negative),
+                );
+                self.push_negate_if(&sierra_ty, magnitude, negative, quotient);
+            }
+            None => self.push_simple_basic_statement(drop_bool.clone(), &[divisor_negative], &[]),
+        }
+        match remainder.zip(remainder_magnitude) {
+            Some((remainder, magnitude)) => {
+                self.push_negate_if(&sierra_ty, magnitude, dividend_negative, remainder);
+            }
+            None => self.push_simple_basic_statement(drop_bool, &[dividend_negative], &[]),
+        }
+    }
+
+    /// Magnitude of the two's complement integer `var` and whether it's negative, `0 - var` for the ones with their
+    /// sign bit set:
+    /// ```text
+    /// dup<u32>(a) -> (a, copy)
+    /// u32_lt(rc, copy, half) { fallthrough(rc) non_negative(rc) }
+    /// branch_align() -> ()
+    /// u32_overflowing_sub(rc, zero, a) -> (rc, magnitude)
+    /// enum_init<bool, 1>(unit) -> (negative)
+    /// jump() { end() }
+    /// non_negative:
+    /// branch_align() -> ()
+    /// store_temp<u32>(a) -> (magnitude)
+    /// enum_init<bool, 0>(unit) -> (negative)
+    /// end:
+    /// ```
+    fn push_magnitude(&mut self, sierra_ty: &SierraType, var: VarId) -> (VarId, VarId) {
+        let ty = sierra_ty.to_string();
+        let bit_width = sierra_ty
+            .bit_width()
+            .expect("Signed divisions are only lowered for integers");
+        let copy = self.push_dup(&ty, var.clone());
+        let half = self.push_stored_const(&ty, BigInt::from(1) << (bit_width - 1));
+        let range_check = self.range_check_var();
+        let lt = self.register_libfunc(&sierra_ty.libfunc_name("lt"), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let lt_idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: lt,
+                args: vec![range_check.clone(), copy, half],
+                branches: [(); 2]
+                    .map(|_| GenBranchInfo {
+                        // The target of the second branch is set once the first one is pushed.
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check.clone()],
+                    })
+                    .into(),
+            }));
+        let [magnitude, negative] = [(); 2].map(|_| VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        });
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.push_negation(sierra_ty, var.clone(), magnitude.clone());
+        self.push_bool_const(true, negative.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&negative),
+            std::slice::from_ref(&negative),
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let non_negative_branch = self.sierra.program.statements.len();
+        self.set_branch_target(lt_idx, 1, non_negative_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_store_temp_statement(ty, &[var], std::slice::from_ref(&magnitude));
+        self.push_bool_const(false, negative.clone());
+        self.push_store_temp_statement(
+            "bool".to_owned(),
+            std::slice::from_ref(&negative),
+            std::slice::from_ref(&negative),
+        );
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
+        (magnitude, negative)
+    }
+
+    /// Binds `result` to `0 - var` if the bool `negative` is true and to `var` otherwise.
+    fn push_negate_if(
+        &mut self,
+        sierra_ty: &SierraType,
+        var: VarId,
+        negative: VarId,
+        result: VarId,
+    ) {
+        let enum_match = self.register_libfunc(
+            "enum_match",
+            vec![GenericArg::Type(ConcreteTypeId::from_string("bool"))],
+        );
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let match_idx = self.sierra.program.statements.len();
+        let branches = [(); 2].map(|_| GenBranchInfo {
+            // The target of the true branch is set once the false one is pushed.
+            target: GenBranchTarget::Fallthrough,
+            results: vec![VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            }],
+        });
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: enum_match,
+                args: vec![negative],
+                branches: branches.into(),
+            }));
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.push_store_temp_statement(
+            sierra_ty.to_string(),
+            std::slice::from_ref(&var),
+            std::slice::from_ref(&result),
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let true_branch = self.sierra.program.statements.len();
+        self.set_branch_target(match_idx, 1, true_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_negation(sierra_ty, var, result.clone());
+        self.push_store_temp_statement(
+            sierra_ty.to_string(),
+            std::slice::from_ref(&result),
+            std::slice::from_ref(&result),
+        );
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
+    }
+
+    /// Binds `result` to the wrapping `0 - var`.
+    fn push_negation(&mut self, sierra_ty: &SierraType, var: VarId, result: VarId) {
+        let zero = self.push_stored_const(&sierra_ty.to_string(), BigInt::from(0));
+        let range_check = self.range_check_var();
+        let sub = self.register_libfunc(&sierra_ty.libfunc_name("overflowing_sub"), vec![]);
+        self.push_merged_branches(
+            sub,
+            vec![range_check.clone(), zero, var],
+            vec![range_check, result],
+        );
+    }
+
+    /// `urem` of the same operands as the `udiv` (or the other way around) later in its block, they're lowered to the
+    /// same `safe_divmod`.
+    fn divmod_sibling(&self, instr: InstructionValue<'ctx>) -> Option<InstructionValue<'ctx>> {
+        let sibling_opcode = match instr.get_opcode() {
+            InstructionOpcode::UDiv => InstructionOpcode::URem,
+            InstructionOpcode::URem => InstructionOpcode::UDiv,
+            InstructionOpcode::SDiv => InstructionOpcode::SRem,
+            _ => InstructionOpcode::SDiv,
+        };
+        let operands = |instr: InstructionValue<'ctx>| {
+            (
+                instr.get_operand(0).unwrap().left(),
+                instr.get_operand(1).unwrap().left(),
+            )
+        };
+        std::iter::successors(instr.get_next_instruction(), |next| {
+            next.get_next_instruction()
+        })
+        .find(|next| next.get_opcode() == sibling_opcode && operands(*next) == operands(instr))
+    }
+
+    /// Lowers `trunc %x to iN` to the remainder of `x` divided by `2^N` (see [`Self::build_low_bits_mask`]), downcast
+    /// to the smaller type (see [`Self::push_fitting_downcast`]).
+    pub fn build_trunc(&mut self, trunc: InstructionValue<'ctx>) {
+        let value = trunc.get_operand(0).unwrap().left().unwrap();
+        let result = BasicValueEnum::try_from(trunc.as_any_value_enum()).unwrap();
+        let from = SierraType::from_llvm(&value.get_type().to_string());
+        let to_ty = trunc.get_type().into_int_type();
+        let to = SierraType::from_llvm(&to_ty.to_string());
+        let bits = to_ty.get_bit_width();
+        if let Some(constant) = value.into_int_value().get_zero_extended_constant() {
+            self.insert_type(to.to_string());
+            let result_var = self.bind_result_var(trunc);
+            let truncated = constant & (u64::MAX >> (64 - bits));
+            return self.push_const_statement(to.to_string(), truncated.to_string(), result_var);
+        }
+        self.build_remainder_by_const(trunc, value, BigInt::from(1) << bits);
+        // Truncating to a narrow integer of the same sierra type (`i8` to `i3`) is only the masking.
+        if from == to {
+            return;
+        }
+        let reduced = self.variables[&result].clone();
+        self.insert_type(to.to_string());
+        let result_var = self.bind_result_var(trunc);
+        self.push_fitting_downcast(&from, &to, reduced, result_var);
+    }
+
+    /// Lowers `zext`/`sext` of an integer to a wider one. `zext` is `upcast<from, to>`, or nothing when both
+    /// integers are lowered to the same sierra type (`i3` to `i8`). `sext` upcasts the value biased by `2^(N-1)` (see
+    /// [`Self::push_sign_bias`]) and adds `2^M - 2^(N-1)` with a wrapping add, which removes the bias and sets the
+    /// high bits of the negative values. Extending an `i1` matches on the `bool` (see [`Self::build_bool_to_int`]).
+    pub fn build_int_extension(&mut self, ext: InstructionValue<'ctx>) {
+        let value = ext.get_operand(0).unwrap().left().unwrap();
+        let result_val = BasicValueEnum::try_from(ext.as_any_value_enum()).unwrap();
+        let signed = ext.get_opcode() == InstructionOpcode::SExt;
+        let from_width = value.into_int_value().get_type().get_bit_width();
+        let to_width = ext.get_type().into_int_type().get_bit_width();
+        let from = SierraType::from_llvm(&value.get_type().to_string());
+        let to = SierraType::from_llvm(&ext.get_type().print_to_string().to_string());
+        self.insert_type(to.to_string());
+        if let Some(constant) = value.into_int_value().get_zero_extended_constant() {
+            let mut extended = BigInt::from(constant);
+            if signed && extended >= BigInt::from(1) << (from_width - 1) {
+                extended += (BigInt::from(1) << to_width) - (BigInt::from(1) << from_width);
+            }
+            let result = self.bind_result_var(ext);
+            return self.push_const_statement(to.to_string(), extended.to_string(), result);
+        }
+        if from == SierraType::Bool {
+            let true_value = if signed {
+                (BigInt::from(1) << to_width) - 1
+            } else {
+                BigInt::from(1)
             };
-            // Add the const call to the statement.
-            self.push_simple_basic_statement(
-                ConcreteLibfuncId::from_string(fn_name),
-                &[],
-                &[next_var.clone()],
+            return self.build_bool_to_int(ext, true_value);
+        }
+        if from == to {
+            return self.alias_value(result_val, value);
+        }
+        let var = self.use_stored_var(value, &from.to_string());
+        let var = if signed {
+            self.push_sign_bias(&from, var)
+        } else {
+            var
+        };
+        let result = self.bind_result_var(ext);
+        let upcast_result = if signed {
+            VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            }
+        } else {
+            result.clone()
+        };
+        let upcast = self.register_libfunc(
+            "upcast",
+            vec![
+                GenericArg::Type(from.concrete_id()),
+                GenericArg::Type(to.concrete_id()),
+            ],
+        );
+        self.push_simple_basic_statement(upcast, &[var], std::slice::from_ref(&upcast_result));
+        if !signed {
+            return;
+        }
+        let high_bits = self.push_stored_const(
+            &to.to_string(),
+            (BigInt::from(1) << to_width) - (BigInt::from(1) << (from_width - 1)),
+        );
+        let range_check = self.range_check_var();
+        let add = self.register_libfunc(&to.libfunc_name("overflowing_add"), vec![]);
+        self.push_merged_branches(
+            add,
+            vec![range_check.clone(), upcast_result, high_bits],
+            vec![range_check, result],
+        );
+    }
+
+    /// Lowers the extension `ext` of an `i1` to an integer that is `0` for `false` and `true_value` for `true` (see
+    /// [`Self::push_bool_to_int`]).
+    fn build_bool_to_int(&mut self, ext: InstructionValue<'ctx>, true_value: BigInt) {
+        let value = ext.get_operand(0).unwrap().left().unwrap();
+        let ty = SierraType::from_llvm(&ext.get_type().print_to_string().to_string()).to_string();
+        let cond = self.use_var(value);
+        let result = self.bind_result_var(ext);
+        self.push_bool_to_int(&ty, cond, result, true_value);
+    }
+
+    /// Stores `0` in `result` if `cond` is `false` and `true_value` if it's `true`. Each branch of `enum_match<bool>`
+    /// stores its const in the result and they merge right after.
+    fn push_bool_to_int(&mut self, ty: &str, cond: VarId, result: VarId, true_value: BigInt) {
+        self.insert_type("bool".to_owned());
+        let enum_match = self.register_libfunc(
+            "enum_match",
+            vec![GenericArg::Type(ConcreteTypeId::from_string("bool"))],
+        );
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let match_idx = self.sierra.program.statements.len();
+        // Patched once both branches are emitted.
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(vec![]));
+        let mut arms = vec![];
+        let mut merge_jump_idx = 0;
+        for value in [BigInt::from(0), true_value] {
+            arms.push(self.sierra.program.statements.len());
+            self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+            self.push_const_statement(ty.to_owned(), value.to_string(), result.clone());
+            self.push_store_temp_statement(
+                ty.to_owned(),
+                std::slice::from_ref(&result),
+                std::slice::from_ref(&result),
             );
+            if arms.len() == 1 {
+                merge_jump_idx = self.sierra.program.statements.len();
+                self.sierra
+                    .program
+                    .statements
+                    .push(GenStatement::Return(vec![]));
+            }
+        }
 
-            self.variables.insert(val, next_var);
+        let merge_idx = self.sierra.program.statements.len();
+        let branches = [
+            GenBranchTarget::Fallthrough,
+            GenBranchTarget::Statement(StatementIdx(arms[1])),
+        ]
+        .map(|target| GenBranchInfo {
+            target,
+            results: vec![VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            }],
+        });
+        self.sierra.program.statements[match_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: enum_match,
+            args: vec![cond],
+            branches: branches.into(),
+        });
+        let jump = self.register_libfunc("jump", vec![]);
+        self.sierra.program.statements[merge_jump_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: jump,
+            args: vec![],
+            branches: vec![GenBranchInfo {
+                target: GenBranchTarget::Statement(StatementIdx(merge_idx)),
+                results: vec![],
+            }],
+        });
+    }
+
+    /// Downcasts `value` of type `from` that always fits in `to` to `result`. The failure branch of `downcast` is
+    /// never taken, it gives a zero to keep the merged branches well typed.
+    fn push_fitting_downcast(
+        &mut self,
+        from: &SierraType,
+        to: &SierraType,
+        value: VarId,
+        result_var: VarId,
+    ) {
+        let range_check = self.range_check_var();
+        let downcast = self.register_libfunc(
+            "downcast",
+            vec![
+                GenericArg::Type(ConcreteTypeId::from_string(from.to_string())),
+                GenericArg::Type(ConcreteTypeId::from_string(to.to_string())),
+            ],
+        );
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: downcast,
+                args: vec![range_check.clone(), value],
+                branches: vec![
+                    GenBranchInfo {
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check.clone(), result_var.clone()],
+                    },
+                    GenBranchInfo {
+                        // Set once the fitting branch is pushed.
+                        target: GenBranchTarget::Fallthrough,
+                        results: vec![range_check],
+                    },
+                ],
+            }));
+        // Both branches store the result so they end with the same ap change.
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.push_store_temp_statement(
+            to.to_string(),
+            std::slice::from_ref(&result_var),
+            std::slice::from_ref(&result_var),
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let failure_branch = self.sierra.program.statements.len();
+        self.set_branch_target(idx, 1, failure_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_const_statement(to.to_string(), "0".to_owned(), result_var.clone());
+        self.push_store_temp_statement(
+            to.to_string(),
+            std::slice::from_ref(&result_var),
+            std::slice::from_ref(&result_var),
+        );
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
+    }
+
+    /// Lowers a wrapping llvm `mul` with `<ty>_wide_mul`, which gives the full product in the type twice as wide,
+    /// reduced modulo `2^N` with `safe_divmod` and downcast back (see [`Self::push_fitting_downcast`]). Sierra has no
+    /// wrapping nor overflowing multiplication, `u128` only has `u128_guarantee_mul` whose guarantee type isn't
+    /// droppable so `i128` products aren't lowered. Narrow integers (`i3`) are always reduced to their llvm width.
+    pub fn build_wrapping_mul(&mut self, mul: InstructionValue<'ctx>) {
+        let first_val = mul.get_operand(0).unwrap().left().unwrap();
+        let scnd_val = mul.get_operand(1).unwrap().left().unwrap();
+        let sierra_ty = SierraType::from_llvm(&first_val.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        if let Some(folded) = Self::fold_binary_consts(mul, first_val, scnd_val) {
+            let result_var_id = self.bind_result_var(mul);
+            self.push_const_statement(ty, folded.to_string(), result_var_id);
+            return;
         }
+        let args = [first_val, scnd_val].map(|val| self.use_stored_var(val, &ty));
+        let bit_width = first_val.into_int_value().get_type().get_bit_width();
+        let result = self.bind_result_var(mul);
+        self.push_wrapping_mul(&sierra_ty, args, bit_width, result);
+    }
+
+    /// Pushes the product of `args` reduced modulo `2^bit_width` into `result`, see [`Self::build_wrapping_mul`].
+    fn push_wrapping_mul(
+        &mut self,
+        sierra_ty: &SierraType,
+        args: [VarId; 2],
+        bit_width: u32,
+        result: VarId,
+    ) {
+        let wide_ty = sierra_ty
+            .wide_mul_type()
+            .expect("Only the products of integers up to 64 bits are lowered");
+        self.insert_type(wide_ty.to_string());
+        let wide = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let wide_mul = self.register_libfunc(&sierra_ty.libfunc_name("wide_mul"), vec![]);
+        self.push_simple_basic_statement(wide_mul, &args, std::slice::from_ref(&wide));
+        self.push_store_temp_statement(
+            wide_ty.to_string(),
+            std::slice::from_ref(&wide),
+            std::slice::from_ref(&wide),
+        );
+        let reduced = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.push_remainder_by_const(
+            &wide_ty,
+            wide,
+            BigInt::from(1) << bit_width,
+            reduced.clone(),
+        );
+        self.push_fitting_downcast(&wide_ty, sierra_ty, reduced, result);
+    }
+
+    /// Lowers a shift by a const amount `c` smaller than the width `N`:
+    /// - `shl` is the wrapping product by `2^c` (see [`Self::build_wrapping_mul`]), so not lowered for `i128`.
+    /// - `lshr` is the quotient of the division by `2^c`.
+    /// - `ashr` biases the value by `2^(N-1)` (see [`Self::push_sign_bias`]), takes the quotient and removes the
+    ///   bias divided by `2^c`, with a wrapping sub. `floor((x + 2^(N-1)) / 2^c) - 2^(N-1-c)` is `floor(x / 2^c)`
+    ///   for the signed `x`. The sign bit has to be the one of the sierra type so `N` is a sierra integer width.
+    pub fn build_const_shift(&mut self, instr: InstructionValue<'ctx>) {
+        let value = instr.get_operand(0).unwrap().left().unwrap();
+        let amount = instr.get_operand(1).unwrap().left().unwrap();
+        let amount = amount
+            .into_int_value()
+            .get_zero_extended_constant()
+            .expect("Only shifts by a const amount are lowered");
+        let sierra_ty = SierraType::from_llvm(&value.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        let bit_width = value.into_int_value().get_type().get_bit_width();
+        let var = self.use_stored_var(value, &ty);
+        let factor = BigInt::from(1) << amount;
+        match instr.get_opcode() {
+            InstructionOpcode::Shl => {
+                let factor = self.push_stored_const(&ty, factor);
+                let result = self.bind_result_var(instr);
+                self.push_wrapping_mul(&sierra_ty, [var, factor], bit_width, result);
+            }
+            InstructionOpcode::LShr => {
+                let divisor = self.push_non_zero_const(&ty, factor);
+                let result = self.bind_result_var(instr);
+                self.push_divmod(&sierra_ty, var, divisor, Some(result), None);
+            }
+            _ => {
+                let biased = self.push_sign_bias(&sierra_ty, var);
+                let divisor = self.push_non_zero_const(&ty, factor);
+                let quotient = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                };
+                self.push_divmod(&sierra_ty, biased, divisor, Some(quotient.clone()), None);
+                let bias =
+                    self.push_stored_const(&ty, BigInt::from(1) << (bit_width - 1 - amount as u32));
+                let range_check = self.range_check_var();
+                let result = self.bind_result_var(instr);
+                let sub = self.register_libfunc(&sierra_ty.libfunc_name("overflowing_sub"), vec![]);
+                self.push_merged_branches(
+                    sub,
+                    vec![range_check.clone(), quotient, bias],
+                    vec![range_check, result],
+                );
+            }
+        }
+    }
+
+    /// Variable of the integer `val` of sierra type `ty` that can be passed to the libfuncs that don't accept deferred
+    /// values, consts and deferred values are stored first.
+    fn use_stored_var(&mut self, val: BasicValueEnum<'ctx>, ty: &str) -> VarId {
+        self.add_const_if_const(val, ty.to_owned());
+        // `null` is a const as well.
+        let is_const = match val {
+            BasicValueEnum::IntValue(int) => int.is_constant_int(),
+            BasicValueEnum::PointerValue(pointer) => pointer.is_null(),
+            _ => false,
+        };
+        let deferred = self.deferred_values.contains(&val) || is_const;
+        let var = self.use_var(val);
+        if deferred {
+            self.push_store_temp_statement(
+                ty.to_owned(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+        }
+        var
+    }
+
+    /// Divides `dividend` by the non zero const `divisor` with `<ty>_safe_divmod`, binds the remainder to `instr`
+    /// and drops the quotient.
+    fn build_remainder_by_const(
+        &mut self,
+        instr: InstructionValue<'ctx>,
+        dividend: BasicValueEnum<'ctx>,
+        divisor: BigInt,
+    ) {
+        let sierra_ty = SierraType::from_llvm(&dividend.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        self.add_const_if_const(dividend, ty.clone());
+        let deferred =
+            self.deferred_values.contains(&dividend) || dividend.into_int_value().is_constant_int();
+        let var = self.use_var(dividend);
+        if deferred {
+            self.push_store_temp_statement(
+                ty.clone(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+        }
+        let result = self.bind_result_var(instr);
+        self.push_remainder_by_const(&sierra_ty, var, divisor, result);
+    }
+
+    /// Pushes the division of `dividend` by the non zero const `divisor` with `<ty>_safe_divmod`, the remainder is
+    /// stored in `result` and the quotient is dropped.
+    fn push_remainder_by_const(
+        &mut self,
+        sierra_ty: &SierraType,
+        dividend: VarId,
+        divisor: BigInt,
+        result: VarId,
+    ) {
+        let divisor = self.push_non_zero_const(&sierra_ty.to_string(), divisor);
+        self.push_divmod(sierra_ty, dividend, divisor, None, Some(result));
+    }
+
+    /// Pushes the division of `dividend` by the `NonZero` `divisor` with `<ty>_safe_divmod` into `quotient` and
+    /// `remainder`, the `None` ones are dropped.
+    fn push_divmod(
+        &mut self,
+        sierra_ty: &SierraType,
+        dividend: VarId,
+        divisor: VarId,
+        quotient: Option<VarId>,
+        remainder: Option<VarId>,
+    ) {
+        let ty = sierra_ty.to_string();
+        let range_check = self.range_check_var();
+        let [quotient, remainder] = [quotient, remainder].map(|result| {
+            result.ok_or_else(|| VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            })
+        });
+        let var = |result: &Result<VarId, VarId>| match result {
+            Ok(var) | Err(var) => var.clone(),
+        };
+        let divmod = self.register_libfunc(&sierra_ty.libfunc_name("safe_divmod"), vec![]);
+        self.push_simple_basic_statement(
+            divmod,
+            &[range_check.clone(), dividend, divisor],
+            &[range_check, var(&quotient), var(&remainder)],
+        );
+        for dropped in [quotient, remainder].into_iter().filter_map(Result::err) {
+            let drop = self.register_libfunc(
+                "drop",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+            );
+            self.push_simple_basic_statement(drop, &[dropped], &[]);
+        }
+    }
+
+    /// Pushes the `NonZero<ty>` const `value` (`Const<NonZero<ty>, Const<ty, value>>`) and returns its variable.
+    pub fn push_non_zero_const(&mut self, ty: &str, value: BigInt) -> VarId {
+        self.insert_non_zero_type(ty);
+        let inner = format!("Const<{}, {}>", ty, value);
+        let outer = format!("Const<NonZero<{}>, {}>", ty, inner);
+        for (id, generic_args) in [
+            (
+                inner.clone(),
+                vec![
+                    GenericArg::Type(ConcreteTypeId::from_string(ty)),
+                    GenericArg::Value(value),
+                ],
+            ),
+            (
+                outer.clone(),
+                vec![
+                    GenericArg::Type(ConcreteTypeId::from_string(format!("NonZero<{}>", ty))),
+                    GenericArg::Type(ConcreteTypeId::from_string(&inner)),
+                ],
+            ),
+        ] {
+            if self.sierra.types.insert(id.clone()) {
+                self.sierra.program.type_declarations.push(TypeDeclaration {
+                    id: ConcreteTypeId::from_string(id),
+                    long_id: ConcreteTypeLongId {
+                        generic_id: GenericTypeId::from_string("Const"),
+                        generic_args,
+                    },
+                    declared_type_info: Some(DeclaredTypeInfo {
+                        storable: false,
+                        droppable: false,
+                        duplicatable: false,
+                        zero_sized: false,
+                    }),
+                })
+            }
+        }
+        let const_as_immediate = self.register_libfunc(
+            "const_as_immediate",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(outer))],
+        );
+        let var = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.push_simple_basic_statement(const_as_immediate, &[], std::slice::from_ref(&var));
+        var
+    }
+
+    /// Whether `alloca` allocates an integer and is only used as the address of loads and stores, it's then lowered
+    /// to a sierra local which outlives the temp vars and can be read from any block. `i1` slots are `bool` locals.
+    /// Struct slots can also have their fields loaded through geps.
+    pub fn is_local_alloca(&self, alloca: InstructionValue<'ctx>) -> bool {
+        let Ok(slot) = BasicValueEnum::try_from(alloca.as_any_value_enum()) else {
+            return false;
+        };
+        let Ok(slot_ty) = alloca.get_allocated_type() else {
+            return false;
+        };
+        let function = alloca.get_parent().unwrap().get_parent().unwrap();
+        let instructions = function
+            .get_basic_block_iter()
+            .flat_map(|block| block.get_instructions())
+            .collect::<Vec<_>>();
+        // Whether `address` is only used as one of the `allowed` operands (opcode and operand index).
+        let only_used_as = |address: BasicValueEnum<'ctx>,
+                            allowed: &[(InstructionOpcode, usize)]| {
+            instructions.iter().all(|instr| {
+                instr.get_operands().enumerate().all(|(idx, operand)| {
+                    operand.and_then(|operand| operand.left()) != Some(address)
+                        || allowed.contains(&(instr.get_opcode(), idx))
+                })
+            })
+        };
+        if slot_ty.is_int_type() {
+            return only_used_as(
+                slot,
+                &[(InstructionOpcode::Load, 0), (InstructionOpcode::Store, 1)],
+            );
+        }
+        // The fields of a struct are read through geps, writing one would need to rebuild the struct.
+        slot_ty.is_struct_type()
+            && only_used_as(
+                slot,
+                &[
+                    (InstructionOpcode::Load, 0),
+                    (InstructionOpcode::Store, 1),
+                    (InstructionOpcode::GetElementPtr, 0),
+                ],
+            )
+            && instructions
+                .iter()
+                .filter(|instr| {
+                    instr.get_opcode() == InstructionOpcode::GetElementPtr
+                        && instr.get_operand(0).and_then(|base| base.left()) == Some(slot)
+                })
+                .all(|gep| {
+                    let field = BasicValueEnum::try_from(gep.as_any_value_enum()).unwrap();
+                    only_used_as(field, &[(InstructionOpcode::Load, 0)])
+                })
+    }
+
+    /// Lowers `alloca` to `alloc_local`. Sierra needs `finalize_locals` once all the locals are allocated, it's
+    /// emitted after the last local alloca of the block.
+    pub fn build_alloc_local(&mut self, alloca: InstructionValue<'ctx>) {
+        let ty = self.declare_llvm_type(alloca.get_allocated_type().unwrap());
+        self.insert_uninitialized_type(&ty);
+        let alloc_local = self.register_libfunc(
+            "alloc_local",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        let slot = self.bind_result_var(alloca);
+        self.push_simple_basic_statement(alloc_local, &[], std::slice::from_ref(&slot));
+        let alloca_val = BasicValueEnum::try_from(alloca.as_any_value_enum()).unwrap();
+        self.locals.insert(alloca_val, (ty, slot, false));
+        let last_local = std::iter::successors(alloca.get_next_instruction(), |instr| {
+            instr.get_next_instruction()
+        })
+        .all(|instr| {
+            instr.get_opcode() != InstructionOpcode::Alloca || !self.is_local_alloca(instr)
+        });
+        if last_local {
+            let finalize_locals = self.register_libfunc("finalize_locals", vec![]);
+            self.push_simple_basic_statement(finalize_locals, &[], &[]);
+        }
+    }
+
+    /// Stores zero (`false` for a `bool`, zero fields for a struct) in the local `ptr` which is loaded before any store
+    /// to it, the load would read an uninitialized local. Llvm leaves the value undefined, a warning is printed. A
+    /// slot with a field that has no zero (an array) is reported once the instruction is lowered (see
+    /// [`Self::uninitialized_local`]).
+    fn store_default_local(&mut self, ptr: BasicValueEnum<'ctx>) {
+        let (ty, slot, _) = self.locals[&ptr].clone();
+        let slot_ty = ptr
+            .as_instruction_value()
+            .unwrap()
+            .get_allocated_type()
+            .unwrap();
+        let Some(default) = self.push_default_value(&SierraType::from_llvm_type(slot_ty)) else {
+            if self.uninitialized_local.is_none() {
+                self.uninitialized_local =
+                    Some(llvm_value_name(ptr).unwrap_or_else(|| ptr.print_to_string().to_string()));
+            }
+            return;
+        };
+        self.warn(&format!(
+            "{}: `{}` is loaded before being stored, it reads {}",
+            self.current_span(),
+            ptr.get_name().to_str().unwrap(),
+            if slot_ty.is_struct_type() {
+                "zeros"
+            } else if ty == "bool" {
+                "false"
+            } else {
+                "0"
+            }
+        ));
+        let store_local = self.register_libfunc(
+            "store_local",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        let local = VarId {
+            id: self.next_var() as u64,
+            debug_name: slot.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(
+            store_local,
+            &[slot, default],
+            std::slice::from_ref(&local),
+        );
+        self.locals.insert(ptr, (ty, local, true));
+    }
+
+    /// Pushes the zero value of `ty`: `false` for a `bool`, `0` for an integer and a struct of the zeros of its
+    /// fields. Returns `None` without pushing anything for the types without one (arrays, `u256`).
+    fn push_default_value(&mut self, ty: &SierraType) -> Option<VarId> {
+        let fields = match ty {
+            SierraType::Struct(fields) => fields
+                .iter()
+                .map(|field| self.push_default_value(field))
+                .collect::<Option<Vec<_>>>()?,
+            SierraType::Bool => {
+                let default = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: None,
+                };
+                self.push_bool_const(false, default.clone());
+                return Some(default);
+            }
+            SierraType::Felt252 => vec![],
+            _ if ty.bit_width().is_some_and(|width| width <= 128) => vec![],
+            _ => return None,
+        };
+        let default = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        if let SierraType::Struct(_) = ty {
+            let struct_construct =
+                self.register_libfunc("struct_construct", vec![GenericArg::Type(ty.concrete_id())]);
+            self.push_simple_basic_statement(
+                struct_construct,
+                &fields,
+                std::slice::from_ref(&default),
+            );
+        } else {
+            self.push_const_statement(ty.to_string(), "0".to_owned(), default.clone());
+        }
+        Some(default)
+    }
+
+    /// Lowers a `store` to a local with `store_local`. A local can only be written once, the later stores rebind it
+    /// to the stored value instead.
+    pub fn build_store_local(&mut self, store: InstructionValue<'ctx>) {
+        let val = store.get_operand(0).unwrap().left().unwrap();
+        let ptr = store.get_operand(1).unwrap().left().unwrap();
+        let (ty, slot, stored) = self.locals[&ptr].clone();
+        let var = if val.is_struct_value() {
+            self.build_struct_construct(val, ty.clone())
+        } else {
+            self.add_const_if_const(val, ty.clone());
+            self.use_var(val)
+        };
+        if stored {
+            self.locals.insert(ptr, (ty, var, true));
+            return;
+        }
+        let store_local = self.register_libfunc(
+            "store_local",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        let local = VarId {
+            id: self.next_var() as u64,
+            debug_name: slot.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(store_local, &[slot, var], std::slice::from_ref(&local));
+        self.locals.insert(ptr, (ty, local, true));
+    }
+
+    /// Resolves a `getelementptr` into a struct local (`gep %struct.S, ptr %s, i32 0, i32 2, i32 1`) to the path of
+    /// the field it points to, read by the loads from it. The first index has to be zero, the gep would point after
+    /// the local otherwise.
+    pub fn build_field_gep(&mut self, gep: InstructionValue<'ctx>) -> Result<(), LoweringError> {
+        let slot = gep.get_operand(0).unwrap().left().unwrap();
+        let indices = (1..gep.get_num_operands())
+            .map(|i| {
+                let index = gep.get_operand(i).unwrap().left().unwrap();
+                index.into_int_value().get_zero_extended_constant()
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|indices| indices.first() == Some(&0))
+            .ok_or_else(|| LoweringError::DynamicStructIndex {
+                instruction: gep.print_to_string().to_string(),
+                span: self.current_span(),
+            })?;
+        let path = indices[1..].iter().map(|index| *index as u32).collect();
+        let field = BasicValueEnum::try_from(gep.as_any_value_enum()).unwrap();
+        self.field_geps.insert(field, (slot, path));
+        Ok(())
+    }
+
+    /// Lowers a `load` of a field of a struct local by deconstructing a copy of the struct down to the field, the
+    /// other fields are dropped.
+    pub fn build_load_field(&mut self, load: InstructionValue<'ctx>) {
+        let ptr = load.get_operand(0).unwrap().left().unwrap();
+        let (slot, path) = self.field_geps[&ptr].clone();
+        if !self.locals[&slot].2 {
+            self.store_default_local(slot);
+        }
+        let (slot_ty, local, stored) = self.locals[&slot].clone();
+        // Its type has no default, it's reported.
+        if !stored {
+            let load_val = BasicValueEnum::try_from(load.as_any_value_enum()).unwrap();
+            self.variables.insert(load_val, local);
+            return;
+        }
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&slot_ty))],
+        );
+        let mut var = VarId {
+            id: self.next_var() as u64,
+            debug_name: local.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(
+            dup,
+            std::slice::from_ref(&local),
+            &[local.clone(), var.clone()],
+        );
+        self.locals.insert(slot, (slot_ty, local, stored));
+        let mut llvm_ty = slot
+            .as_instruction_value()
+            .unwrap()
+            .get_allocated_type()
+            .unwrap();
+        for index in path {
+            let struct_ty = llvm_ty.into_struct_type();
+            let ty = self.declare_llvm_type(llvm_ty);
+            let fields = struct_ty
+                .get_field_types()
+                .into_iter()
+                .map(|field_ty| {
+                    let field = VarId {
+                        id: self.next_var() as u64,
+                        debug_name: None,
+                    };
+                    (self.declare_llvm_type(field_ty), field)
+                })
+                .collect::<Vec<_>>();
+            let struct_deconstruct = self.register_libfunc(
+                "struct_deconstruct",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+            );
+            let results = fields
+                .iter()
+                .map(|(_, field)| field.clone())
+                .collect::<Vec<_>>();
+            self.push_simple_basic_statement(
+                struct_deconstruct,
+                std::slice::from_ref(&var),
+                &results,
+            );
+            for (idx, (field_ty, field)) in fields.into_iter().enumerate() {
+                if idx as u32 == index {
+                    var = field;
+                    continue;
+                }
+                let drop = self.register_libfunc(
+                    "drop",
+                    vec![GenericArg::Type(ConcreteTypeId::from_string(field_ty))],
+                );
+                self.push_simple_basic_statement(drop, &[field], &[]);
+            }
+            llvm_ty = struct_ty.get_field_type_at_index(index).unwrap();
+        }
+        let load_val = BasicValueEnum::try_from(load.as_any_value_enum()).unwrap();
+        self.variables.insert(load_val, var);
+    }
+
+    /// Element of a constant global table (`@tbl = constant [4 x i32] [...]`) `gep` points to, if all its indices
+    /// are consts.
+    pub fn const_table_element(&self, gep: InstructionValue<'ctx>) -> Option<BasicValueEnum<'ctx>> {
+        let base = gep.get_operand(0)?.left()?;
+        let table = *self.const_globals.get(&base)?;
+        let indices = (1..gep.get_num_operands())
+            .map(|i| {
+                let index = gep.get_operand(i)?.left()?;
+                index.into_int_value().get_zero_extended_constant()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let (first, rest) = indices.split_first()?;
+        // The first index steps over whole source elements: the table itself or one of its elements.
+        let source_ty = gep.get_gep_source_element_type().ok()?;
+        let mut element = if source_ty == table.get_type() {
+            (*first == 0).then_some(table)?
+        } else {
+            aggregate_element(table, *first)?
+        };
+        for index in rest {
+            element = aggregate_element(element, *index)?;
+        }
+        element.is_int_value().then_some(element)
+    }
+
+    /// Const a `load` from `ptr` reads: a constant global table element or the value of a constant global.
+    pub fn loaded_const(&self, ptr: BasicValueEnum<'ctx>) -> Option<BasicValueEnum<'ctx>> {
+        let ptr = self.aliases.get(&ptr).copied().unwrap_or(ptr);
+        self.table_elements
+            .get(&ptr)
+            .or_else(|| self.const_globals.get(&ptr))
+            .copied()
+            .filter(|val| val.is_int_value())
+    }
+
+    /// Lowers a `load` of a const known at compile time to that const.
+    pub fn build_const_load(&mut self, load: InstructionValue<'ctx>) {
+        let ptr = load.get_operand(0).unwrap().left().unwrap();
+        let value = self.loaded_const(ptr).unwrap();
+        let ty = llvm_int_ty_to_sierra(&value.get_type().to_string());
+        self.insert_type(ty.clone());
+        self.add_const_if_const(value, ty);
+        let load_val = BasicValueEnum::try_from(load.as_any_value_enum()).unwrap();
+        let var = self.variables[&value].clone();
+        self.variables.insert(load_val, var);
+    }
+
+    /// Lowers a `load` from a local to a copy of the variable holding its value, the local is kept for the later
+    /// loads and dropped at the return. A slot whose type can't be duplicated is reported once the instruction is
+    /// lowered (see [`Self::uncopyable_value`]).
+    pub fn build_load_local(&mut self, load: InstructionValue<'ctx>) {
+        let ptr = load.get_operand(0).unwrap().left().unwrap();
+        if !self.locals[&ptr].2 {
+            self.store_default_local(ptr);
+        }
+        let (ty, var, stored) = self.locals[&ptr].clone();
+        // A slot that is still not stored has no default, it's reported as well.
+        if !stored || !self.is_duplicatable(&ty) {
+            if stored && self.uncopyable_value.is_none() {
+                self.uncopyable_value =
+                    Some(llvm_value_name(ptr).unwrap_or_else(|| ptr.print_to_string().to_string()));
+            }
+            let load_val = BasicValueEnum::try_from(load.as_any_value_enum()).unwrap();
+            self.variables.insert(load_val, var);
+            return;
+        }
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        let copy = self.bind_result_var(load);
+        self.push_simple_basic_statement(dup, std::slice::from_ref(&var), &[var.clone(), copy]);
+    }
+
+    /// Pushes the invocation of a libfunc with two branches giving the same `results`, then merges the branches.
+    fn push_merged_branches(
+        &mut self,
+        libfunc_id: ConcreteLibfuncId,
+        args: Vec<VarId>,
+        results: Vec<VarId>,
+    ) {
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id,
+                args,
+                branches: vec![
+                    GenBranchInfo {
+                        target: GenBranchTarget::Fallthrough,
+                        results: results.clone(),
+                    },
+                    GenBranchInfo {
+                        // Set once the first branch is pushed.
+                        target: GenBranchTarget::Fallthrough,
+                        results,
+                    },
+                ],
+            }));
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let second_branch = self.sierra.program.statements.len();
+        self.set_branch_target(idx, 1, second_branch);
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        let end = self.sierra.program.statements.len();
+        self.set_branch_target(jump_idx, 0, end);
+    }
+
+    /// Pushes a `jump` to the statement `target`.
+    fn push_jump(&mut self, target: usize) {
+        let jump = self.register_libfunc("jump", vec![]);
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: jump,
+                args: vec![],
+                branches: vec![GenBranchInfo {
+                    target: GenBranchTarget::Statement(StatementIdx(target)),
+                    results: vec![],
+                }],
+            }));
+    }
+
+    /// Lowers `shl` by a non constant amount to a loop doubling the value `amount` times, withdrawing gas at each
+    /// iteration. It's a lot bigger and costlier than a shift by a constant so it's only used with
+    /// `--dynamic-shifts`. Right shifts would need a division (and the sign for `ashr`) at each iteration and aren't
+    /// lowered this way.
+    pub fn build_shift_loop(&mut self, instr: InstructionValue<'ctx>) {
+        let value = instr.get_operand(0).unwrap().left().unwrap();
+        let amount = instr.get_operand(1).unwrap().left().unwrap();
+        let sierra_ty = SierraType::from_llvm(&value.get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        let value_var = self.use_stored_var(value, &ty);
+        let amount_var = self.use_stored_var(amount, &ty);
+        let acc = self.bind_result_var(instr);
+        let counter = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("shift_counter")),
+        };
+        let range_check = self.range_check_var();
+        let gas_builtin = self.gas_builtin_var();
+        // The loop head is reached from the entry and from the end of the loop, both store the loop variables in
+        // the same order so they agree on them.
+        let loop_vars = [
+            ("RangeCheck".to_owned(), range_check.clone()),
+            ("GasBuiltin".to_owned(), gas_builtin),
+            (ty.clone(), acc.clone()),
+            (ty.clone(), counter.clone()),
+        ];
+        let entry_sources = [
+            range_check.clone(),
+            loop_vars[1].1.clone(),
+            value_var,
+            amount_var,
+        ];
+        for ((var_ty, var), source) in loop_vars.iter().zip(entry_sources) {
+            self.push_store_temp_statement(var_ty.clone(), &[source], std::slice::from_ref(var));
+        }
+        let loop_idx = self.sierra.program.statements.len();
+        self.build_withdraw_gas(instr.get_parent().unwrap().get_parent().unwrap());
+
+        self.insert_non_zero_type(&ty);
+        let counter_non_zero = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("shift_counter_non_zero")),
+        };
+        let is_zero = self.register_libfunc(&sierra_ty.libfunc_name("is_zero"), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let is_zero_idx = self.sierra.program.statements.len();
+        // Patched once the body of the loop is emitted.
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(vec![]));
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        let exit_jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let step_idx = self.sierra.program.statements.len();
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        let unwrap_non_zero = self.register_libfunc(
+            "unwrap_non_zero",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        self.push_simple_basic_statement(
+            unwrap_non_zero,
+            std::slice::from_ref(&counter_non_zero),
+            std::slice::from_ref(&counter),
+        );
+        let one = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.push_const_statement(ty.clone(), "1".to_owned(), one.clone());
+        self.push_store_temp_statement(
+            ty.clone(),
+            std::slice::from_ref(&one),
+            std::slice::from_ref(&one),
+        );
+        // The counter isn't zero so it can't underflow.
+        let sub = self.register_libfunc(&sierra_ty.libfunc_name("overflowing_sub"), vec![]);
+        self.push_merged_branches(
+            sub,
+            vec![range_check.clone(), counter.clone(), one],
+            vec![range_check.clone(), counter],
+        );
+        let acc_copy = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        self.push_simple_basic_statement(
+            dup,
+            std::slice::from_ref(&acc),
+            &[acc.clone(), acc_copy.clone()],
+        );
+        // Doubling wraps like the shift drops the high bits.
+        let add = self.register_libfunc(&sierra_ty.libfunc_name("overflowing_add"), vec![]);
+        self.push_merged_branches(
+            add,
+            vec![range_check.clone(), acc.clone(), acc_copy],
+            vec![range_check, acc],
+        );
+        for (var_ty, var) in &loop_vars {
+            self.push_store_temp_statement(
+                var_ty.clone(),
+                std::slice::from_ref(var),
+                std::slice::from_ref(var),
+            );
+        }
+        self.push_jump(loop_idx);
+
+        let exit_idx = self.sierra.program.statements.len();
+        self.sierra.program.statements[is_zero_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: is_zero,
+            args: vec![loop_vars[3].1.clone()],
+            branches: vec![
+                GenBranchInfo {
+                    target: GenBranchTarget::Fallthrough,
+                    results: vec![],
+                },
+                GenBranchInfo {
+                    target: GenBranchTarget::Statement(StatementIdx(step_idx)),
+                    results: vec![counter_non_zero],
+                },
+            ],
+        });
+        self.set_branch_target(exit_jump_idx, 0, exit_idx);
+    }
+
+    /// Variable of the `RangeCheck` builtin, declaring the type the first time it's needed.
+    pub fn range_check_var(&mut self) -> VarId {
+        if let Some(range_check) = &self.range_check {
+            return range_check.clone();
+        }
+        self.insert_type("RangeCheck".to_owned());
+        let range_check = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("range_check")),
+        };
+        self.range_check = Some(range_check.clone());
+        range_check
+    }
+
+    /// Variable of the `Bitwise` builtin, declaring the type the first time it's needed.
+    pub fn bitwise_var(&mut self) -> VarId {
+        if let Some(bitwise) = &self.bitwise {
+            return bitwise.clone();
+        }
+        self.insert_type("Bitwise".to_owned());
+        let bitwise = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("bitwise")),
+        };
+        self.bitwise = Some(bitwise.clone());
+        bitwise
+    }
+
+    /// Variable of the `GasBuiltin` builtin, declaring the type the first time it's needed.
+    pub fn gas_builtin_var(&mut self) -> VarId {
+        if let Some(gas_builtin) = &self.gas_builtin {
+            return gas_builtin.clone();
+        }
+        self.insert_type("GasBuiltin".to_owned());
+        let gas_builtin = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("gas_builtin")),
+        };
+        self.gas_builtin = Some(gas_builtin.clone());
+        gas_builtin
+    }
+
+    /// Builtins used by the function being lowered along with their type, in the order they're received and returned.
+    pub fn used_builtins(&self) -> Vec<(&'static str, VarId)> {
+        BUILTINS
+            .into_iter()
+            .zip([&self.range_check, &self.bitwise, &self.gas_builtin])
+            .filter_map(|(ty, var)| var.clone().map(|var| (ty, var)))
+            .collect()
+    }
+
+    /// Variable of the builtin `ty`, one of [`BUILTINS`].
+    fn builtin_var(&mut self, ty: &str) -> VarId {
+        match ty {
+            "RangeCheck" => self.range_check_var(),
+            "Bitwise" => self.bitwise_var(),
+            _ => self.gas_builtin_var(),
+        }
+    }
+
+    /// Declares `function` in the program before lowering any body so calls to it (including its own recursive
+    /// calls) resolve. Its entry point is set by [`Self::start_function`] and the builtins it needs are only known
+    /// once its body is lowered, see [`Self::add_builtins_to_signature`].
+    pub fn declare_function(&mut self, function: FunctionValue<'ctx>) {
+        // The params are the first variables of the function.
+        self.sierra.next_var = 0;
+        let params = function
+            .get_param_iter()
+            .map(|param| {
+                self.insert_param(param);
+                Param {
+                    id: self.variables[&param].clone(),
+                    ty: SierraType::from_llvm_type(param.get_type()).concrete_id(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let ret_types = function
+            .get_type()
+            .get_return_type()
+            .map(|ty| ConcreteTypeId::from_string(self.declare_llvm_type(ty)))
+            .into_iter()
+            .collect();
+        self.sierra.program.funcs.push(Function {
+            id: FunctionId::from_string(function.get_name().to_str().unwrap()),
+            signature: FunctionSignature {
+                param_types: params.iter().map(|param| param.ty.clone()).collect(),
+                ret_types,
+            },
+            params,
+            entry_point: StatementIdx(0),
+        });
+    }
+
+    /// Starts lowering the body of the `func_idx`th declared function at the next statement. The variables of the
+    /// builtins it takes are created up front so every `return` gives back all of them, wherever the body first
+    /// uses them.
+    pub fn start_function(&mut self, func_idx: usize) {
+        self.range_check = None;
+        self.bitwise = None;
+        self.gas_builtin = None;
+        self.sierra.program.funcs[func_idx].entry_point =
+            StatementIdx(self.sierra.program.statements.len());
+        let name = self.sierra.program.funcs[func_idx].id.to_string();
+        let builtins = self
+            .function_builtins
+            .as_ref()
+            .and_then(|builtins| builtins.get(&name))
+            .cloned()
+            .unwrap_or_default();
+        for ty in builtins {
+            self.builtin_var(ty);
+        }
+    }
+
+    /// Prepends the builtins used by the `func_idx`th function, which was just lowered, to its params and return
+    /// types.
+    pub fn add_builtins_to_signature(&mut self, func_idx: usize) {
+        let builtins = self.used_builtins();
+        let function = &mut self.sierra.program.funcs[func_idx];
+        for (ty, var) in builtins.into_iter().rev() {
+            let ty = ConcreteTypeId::from_string(ty);
+            function.signature.param_types.insert(0, ty.clone());
+            function.signature.ret_types.insert(0, ty.clone());
+            function.params.insert(0, Param { id: var, ty });
+        }
+    }
+
+    /// Withdraws the gas of a recursive call with `withdraw_gas`. Functions don't return a panic result so running
+    /// out of gas returns the builtins along with zeroes.
+    fn build_withdraw_gas(&mut self, function: FunctionValue<'ctx>) {
+        let disable_ap_tracking = self.register_libfunc("disable_ap_tracking", vec![]);
+        self.push_simple_basic_statement(disable_ap_tracking, &[], &[]);
+        let builtins = vec![self.range_check_var(), self.gas_builtin_var()];
+        let withdraw_gas = self.register_libfunc("withdraw_gas", vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let jump = self.register_libfunc("jump", vec![]);
+        let withdraw_idx = self.sierra.program.statements.len();
+        // Patched once the out of gas branch is emitted.
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(vec![]));
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        let jump_idx = self.sierra.program.statements.len();
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(vec![]));
+        let out_of_gas_idx = self.sierra.program.statements.len();
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.push_zero_return(function);
+        self.sierra.program.statements[withdraw_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: withdraw_gas,
+            args: builtins.clone(),
+            branches: vec![
+                GenBranchInfo {
+                    target: GenBranchTarget::Fallthrough,
+                    results: builtins.clone(),
+                },
+                GenBranchInfo {
+                    target: GenBranchTarget::Statement(StatementIdx(out_of_gas_idx)),
+                    results: builtins,
+                },
+            ],
+        });
+        self.sierra.program.statements[jump_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: jump,
+            args: vec![],
+            branches: vec![GenBranchInfo {
+                target: GenBranchTarget::Statement(StatementIdx(
+                    self.sierra.program.statements.len(),
+                )),
+                results: vec![],
+            }],
+        });
+    }
+
+    /// Returns the builtins along with a zero from `function`, for the paths that can't return a value.
+    fn push_zero_return(&mut self, function: FunctionValue<'ctx>) {
+        // The shared consts are kept for the path that goes on.
+        let mut shared = self.shared_const_vars.values().cloned().collect::<Vec<_>>();
+        shared.sort_by_key(|(var, _)| var.id);
+        for (var, ty) in shared {
+            let drop = self.register_libfunc(
+                "drop",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+            );
+            self.push_simple_basic_statement(drop, &[var], &[]);
+        }
+        let mut returned = vec![];
+        for (ty, var) in self.used_builtins() {
+            self.push_store_temp_statement(
+                ty.to_owned(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+            returned.push(var);
+        }
+        if let Some(ret_ty) = function.get_type().get_return_type() {
+            let ty = self.declare_llvm_type(ret_ty);
+            let zero = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            self.push_const_statement(ty.clone(), "0".to_owned(), zero.clone());
+            self.push_store_temp_statement(
+                ty,
+                std::slice::from_ref(&zero),
+                std::slice::from_ref(&zero),
+            );
+            returned.push(zero);
+        }
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(returned));
+    }
+
+    /// Lowers `unreachable`. Reaching it is undefined behavior and sierra has no trap, the block returns a zero like
+    /// running out of gas does (see [`Self::push_zero_return`]) so it terminates. The values still held on the paths
+    /// to it and the locals are dropped first, like before a `ret`.
+    pub fn build_unreachable(&mut self, unreachable: InstructionValue<'ctx>) {
+        let block = unreachable.get_parent().unwrap();
+        let function = block.get_parent().unwrap();
+        let mut held = self.held_values(block);
+        held.sort_by_key(|(var, _)| var.id);
+        for (var, ty) in held {
+            let drop = self.register_libfunc(
+                "drop",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+            );
+            self.push_simple_basic_statement(drop, &[var], &[]);
+        }
+        self.drop_locals(function, &[]);
+        self.push_zero_return(function);
+    }
+
+    /// Variables (with their type) of the values defined on every path to the end of `block` that weren't consumed
+    /// on all of them: the ones read again later and the ones whose last read is in a block that doesn't dominate
+    /// `block`.
+    fn held_values(&self, block: BasicBlock<'ctx>) -> Vec<(VarId, String)> {
+        let function = block.get_parent().unwrap();
+        let dominators = dominators(function);
+        let dominates = |other: BasicBlock<'ctx>| dominators[&block].contains(&other);
+        let blocks = function.get_basic_blocks();
+        // Block of the last read of each value in the layout, the one consuming its variable. A phi reads its
+        // incoming values in their block.
+        let mut last_reads = HashMap::new();
+        for (idx, other) in blocks.iter().enumerate() {
+            for instr in other.get_instructions() {
+                if instr.get_opcode() == InstructionOpcode::Phi {
+                    for (val, incoming) in
+                        unsafe { PhiValue::new(instr.as_value_ref()) }.get_incomings()
+                    {
+                        let incoming_idx = blocks.iter().position(|b| *b == incoming).unwrap();
+                        let last = last_reads.entry(val).or_insert(incoming_idx);
+                        *last = (*last).max(incoming_idx);
+                    }
+                    continue;
+                }
+                for val in instr.get_operands().flatten().filter_map(|op| op.left()) {
+                    last_reads.insert(val, idx);
+                }
+            }
+        }
+        self.variables
+            .iter()
+            .filter(|(val, _)| {
+                let defined_before = match val.as_instruction_value() {
+                    Some(def) => def.get_parent().is_some_and(|def_block| {
+                        def_block.get_parent() == Some(function) && dominates(def_block)
+                    }),
+                    None => function.get_param_iter().any(|param| param == **val),
+                };
+                let consumed = match self.remaining_uses.get(val) {
+                    // Never read, it's dropped right after it's defined.
+                    None => true,
+                    Some(0) => last_reads
+                        .get(val)
+                        .is_some_and(|idx| dominates(blocks[*idx])),
+                    Some(_) => false,
+                };
+                defined_before && !consumed
+            })
+            .map(|(val, var)| {
+                (
+                    var.clone(),
+                    SierraType::from_llvm_type(val.get_type()).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Declares the `name<generic_args>` libfunc if it isn't declared yet and returns its id.
+    pub fn register_libfunc(
+        &mut self,
+        name: &str,
+        generic_args: Vec<GenericArg>,
+    ) -> ConcreteLibfuncId {
+        self.sierra.register_libfunc(name, generic_args)
+    }
+
+    /// Count the uses of each non const operand of `instr`.
+    pub fn count_uses(&mut self, instr: InstructionValue<'ctx>) {
+        // A phi is stored once per predecessor block even if the block has several edges to it.
+        let operands = if instr.get_opcode() == InstructionOpcode::Phi {
+            let mut blocks = HashSet::new();
+            unsafe { PhiValue::new(instr.as_value_ref()) }
+                .get_incomings()
+                .filter(|(_, block)| blocks.insert(*block))
+                .map(|(val, _)| val)
+                .collect::<Vec<_>>()
+        } else {
+            instr
+                .get_operands()
+                .flatten()
+                .filter_map(|operand| operand.left())
+                .collect()
+        };
+        for val in operands {
+            // Consts (`null` as well) are materialized at each use.
+            let is_const = match val {
+                BasicValueEnum::IntValue(int) => int.is_constant_int(),
+                BasicValueEnum::PointerValue(pointer) => pointer.is_null(),
+                _ => false,
+            };
+            if !is_const {
+                *self.remaining_uses.entry(val).or_default() += 1;
+            }
+        }
+    }
+
+    /// Finds the `sub`s of `module` whose only use is an `icmp eq` with zero (`icmp eq (sub a, b), 0`), the
+    /// comparison reads `a` and `b` instead and the sub isn't lowered. Needs the uses to be counted.
+    pub fn find_zero_compared_subs(&mut self, module: &Module<'ctx>) {
+        let subs = module
+            .get_functions()
+            .flat_map(|function| function.get_basic_block_iter())
+            .flat_map(|block| block.get_instructions())
+            .filter_map(|instr| {
+                // A comparison fused with a select reads its operands itself.
+                if instr.get_opcode() != InstructionOpcode::ICmp
+                    || instr.get_icmp_predicate() != Some(IntPredicate::EQ)
+                    || self.fusable_select(instr).is_some()
+                {
+                    return None;
+                }
+                let operands = [0, 1].map(|i| instr.get_operand(i).unwrap().left().unwrap());
+                let is_zero = |val: &BasicValueEnum| {
+                    val.is_int_value()
+                        && val.into_int_value().get_zero_extended_constant() == Some(0)
+                };
+                let compared = match operands {
+                    [val, zero] | [zero, val] if is_zero(&zero) => val,
+                    _ => return None,
+                };
+                compared
+                    .as_instruction_value()
+                    .filter(|sub| sub.get_opcode() == InstructionOpcode::Sub)
+                    .filter(|_| self.remaining_uses.get(&compared) == Some(&1))
+            })
+            .collect::<Vec<_>>();
+        self.zero_compared_subs.extend(subs);
+    }
+
+    /// Int consts read by `instr` that can be shared with the other instructions of its block, once per read: the
+    /// single const operand of `add`, `sub` and the bitwise ops, the operands of comparisons (and of the select they
+    /// branch to), of selects, switches and calls. Bools and the reads of instructions that aren't lowered or that
+    /// pick one const without materializing the other (`select i1 true`) aren't shared.
+    pub fn shared_const_operands(
+        &self,
+        instr: InstructionValue<'ctx>,
+    ) -> Vec<BasicValueEnum<'ctx>> {
+        let operands = |instr: InstructionValue<'ctx>, range: std::ops::Range<u32>| {
+            range
+                .map(|i| instr.get_operand(i).unwrap().left().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let is_const = |val: &BasicValueEnum| {
+            val.is_int_value()
+                && val.into_int_value().is_constant_int()
+                && val.into_int_value().get_type().get_bit_width() > 1
+        };
+        let reads = match instr.get_opcode() {
+            InstructionOpcode::Add | InstructionOpcode::Or | InstructionOpcode::Xor => {
+                operands(instr, 0..2)
+            }
+            InstructionOpcode::Sub if !self.zero_compared_subs.contains(&instr) => {
+                operands(instr, 0..2)
+            }
+            InstructionOpcode::And if self.low_bits_mask(instr).is_none() => operands(instr, 0..2),
+            InstructionOpcode::ICmp if self.dead_booleans.contains(&instr) => vec![],
+            InstructionOpcode::ICmp => match self.fusable_select(instr) {
+                Some(select) => [operands(instr, 0..2), operands(select, 1..3)].concat(),
+                None if is_lowerable_comparison(instr) => {
+                    let (lhs, rhs) = self.comparison_operands(instr);
+                    vec![lhs, rhs]
+                }
+                None => vec![],
+            },
+            InstructionOpcode::Select => {
+                let cond = instr.get_operand(0).unwrap().left().unwrap();
+                let fused = cond
+                    .as_instruction_value()
+                    .filter(|icmp| icmp.get_opcode() == InstructionOpcode::ICmp)
+                    .is_some_and(|icmp| self.fusable_select(icmp) == Some(instr));
+                let const_cond = cond.is_int_value() && cond.into_int_value().is_constant_int();
+                if fused || const_cond || !cond.is_int_value() {
+                    vec![]
+                } else {
+                    operands(instr, 1..3)
+                }
+            }
+            InstructionOpcode::Switch => operands(instr, 0..1),
+            InstructionOpcode::Call
+                if !crate::is_signed_overflow_intrinsic(instr)
+                    && crate::callee_name(instr).as_deref() != Some("printf") =>
+            {
+                operands(instr, 0..instr.get_num_operands() - 1)
+            }
+            _ => vec![],
+        };
+        let arithmetic = matches!(
+            instr.get_opcode(),
+            InstructionOpcode::Add
+                | InstructionOpcode::Sub
+                | InstructionOpcode::And
+                | InstructionOpcode::Or
+                | InstructionOpcode::Xor
+        );
+        // Arithmetic on two consts is folded.
+        if arithmetic && reads.iter().all(is_const) {
+            return vec![];
+        }
+        reads.into_iter().filter(is_const).collect()
+    }
+
+    /// Counts the consts read several times by the instructions of `block` (see [`Self::shared_const_operands`]),
+    /// each one is materialized once and copied for its readers.
+    pub fn find_shared_consts(&mut self, block: BasicBlock<'ctx>) {
+        self.shared_const_uses.clear();
+        self.shared_const_vars.clear();
+        for instr in block.get_instructions() {
+            for val in self.shared_const_operands(instr) {
+                *self.shared_const_uses.entry(val).or_default() += 1;
+            }
+        }
+        self.shared_const_uses.retain(|_, uses| *uses > 1);
+    }
+
+    /// Whether `val` is a shared const read by the instruction being lowered.
+    fn is_shared_const(&self, val: BasicValueEnum<'ctx>) -> bool {
+        self.current_shared_consts.contains(&val) && self.shared_const_uses.contains_key(&val)
+    }
+
+    /// Variable for the read of the shared const `val` by the instruction being lowered, `var` if it was just
+    /// materialized. The last read takes the variable itself, the others a copy.
+    fn share_const(&mut self, val: BasicValueEnum<'ctx>, ty: &str, var: VarId) -> VarId {
+        let uses = self.shared_const_uses.get_mut(&val).unwrap();
+        *uses -= 1;
+        if *uses == 0 {
+            self.shared_const_uses.remove(&val);
+            self.shared_const_vars.remove(&val);
+            return var;
+        }
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        let copy = VarId {
+            id: self.next_var() as u64,
+            debug_name: var.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(
+            dup,
+            std::slice::from_ref(&var),
+            &[var.clone(), copy.clone()],
+        );
+        self.shared_const_vars.insert(val, (var, ty.to_owned()));
+        copy
+    }
+
+    /// The `sub` the comparison `icmp` compares to zero if they're lowered together, see
+    /// [`Self::find_zero_compared_subs`].
+    fn zero_compared_sub(&self, icmp: InstructionValue<'ctx>) -> Option<InstructionValue<'ctx>> {
+        if icmp.get_opcode() != InstructionOpcode::ICmp {
+            return None;
+        }
+        (0..2)
+            .filter_map(|i| icmp.get_operand(i)?.left()?.as_instruction_value())
+            .find(|operand| self.zero_compared_subs.contains(operand))
+    }
+
+    /// Finds the comparisons and `i1` boolean ops of `module` whose result is never used, the uses they make of their
+    /// operands are removed so an `icmp` only feeding a dead `and` is dead as well. Needs the uses to be counted.
+    pub fn find_dead_booleans(&mut self, module: &Module<'ctx>) {
+        let booleans = module
+            .get_functions()
+            .flat_map(|function| function.get_basic_block_iter())
+            .flat_map(|block| block.get_instructions())
+            .filter(|instr| match instr.get_opcode() {
+                InstructionOpcode::ICmp => true,
+                InstructionOpcode::And | InstructionOpcode::Or | InstructionOpcode::Xor => {
+                    instr.get_type().is_int_type()
+                        && instr.get_type().into_int_type().get_bit_width() == 1
+                }
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+        loop {
+            let dead = booleans.iter().copied().find(|instr| {
+                let result = BasicValueEnum::try_from(instr.as_any_value_enum()).unwrap();
+                !self.dead_booleans.contains(instr)
+                    && self
+                        .remaining_uses
+                        .get(&result)
+                        .is_none_or(|uses| *uses == 0)
+            });
+            let Some(dead) = dead else {
+                return;
+            };
+            self.dead_booleans.insert(dead);
+            for operand in dead.get_operands().flatten().filter_map(|op| op.left()) {
+                if let Some(uses) = self.remaining_uses.get_mut(&operand) {
+                    *uses = uses.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Returns the sierra variable of `val` so it can be consumed. Sierra variables can only be used once so if `val`
+    /// is used again later the consumer gets a copy made with `dup`. A snapshot of a type that can't be duplicated
+    /// would be a `Snapshot<T>` and the consumers take a `T`, so such a value used again is reported (see
+    /// [`Self::uncopyable_value`]).
+    pub fn use_var(&mut self, val: BasicValueEnum<'ctx>) -> VarId {
+        let val = self.aliases.get(&val).copied().unwrap_or(val);
+        let Some(var) = self.variables.get(&val).cloned() else {
+            // Reported once the instruction is lowered (see `Self::missing_value`), it goes on with a placeholder.
+            if self.missing_value.is_none() {
+                self.missing_value =
+                    Some(llvm_value_name(val).unwrap_or_else(|| val.print_to_string().to_string()));
+            }
+            return VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+        };
+        let Some(remaining) = self.remaining_uses.get_mut(&val) else {
+            return var;
+        };
+        *remaining = remaining.saturating_sub(1);
+        if *remaining == 0 {
+            return var;
+        }
+        let ty = SierraType::from_llvm_type(val.get_type()).to_string();
+        if !self.is_duplicatable(&ty) {
+            if self.uncopyable_value.is_none() {
+                self.uncopyable_value =
+                    Some(llvm_value_name(val).unwrap_or_else(|| val.print_to_string().to_string()));
+            }
+            return var;
+        }
+        // Sierra variables are consumed by their first use, the consumer gets a copy while others are left.
+        let dup = self.register_libfunc(
+            "dup",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+        );
+        let copy = VarId {
+            id: self.next_var() as u64,
+            debug_name: var.debug_name.clone(),
+        };
+        self.push_simple_basic_statement(
+            dup,
+            std::slice::from_ref(&var),
+            &[var.clone(), copy.clone()],
+        );
+        copy
+    }
+
+    /// Whether all the indices of the `getelementptr` instruction are zero, meaning it points to its base.
+    pub fn is_zero_offset_gep(&self, gep: InstructionValue<'ctx>) -> bool {
+        (1..gep.get_num_operands()).all(|i| {
+            gep.get_operand(i)
+                .and_then(|index| index.left())
+                .is_some_and(|index| {
+                    index.is_int_value()
+                        && index.into_int_value().get_zero_extended_constant() == Some(0)
+                })
+        })
+    }
+
+    /// Makes `alias` use the variable of `target`. The uses of `alias` are transferred to `target`, minus the one
+    /// that created the alias.
+    pub fn alias_value(&mut self, alias: BasicValueEnum<'ctx>, target: BasicValueEnum<'ctx>) {
+        let target = self.aliases.get(&target).copied().unwrap_or(target);
+        let alias_uses = self.remaining_uses.remove(&alias).unwrap_or_default();
+        if let Some(target_uses) = self.remaining_uses.get_mut(&target) {
+            *target_uses = (*target_uses + alias_uses).saturating_sub(1);
+        }
+        self.aliases.insert(alias, target);
+    }
+
+    /// Drops the variable of the result of `instr` if no instruction reads it, sierra variables have to be consumed.
+    /// Results that weren't lowered (dead booleans, aliases) have no variable to drop.
+    pub fn drop_if_unused(&mut self, instr: InstructionValue<'ctx>) {
+        let Ok(val) = BasicValueEnum::try_from(instr.as_any_value_enum()) else {
+            return;
+        };
+        if self.remaining_uses.contains_key(&val) {
+            return;
+        }
+        let Some(var) = self.variables.get(&val).cloned() else {
+            return;
+        };
+        let ty = SierraType::from_llvm_type(val.get_type()).to_string();
+        let drop = self.register_libfunc(
+            "drop",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        self.push_simple_basic_statement(drop, &[var], &[]);
+    }
+
+    /// Whether the declared type `ty` can be duplicated. Undeclared types are considered duplicatable.
+    pub fn is_duplicatable(&self, ty: &str) -> bool {
+        self.sierra
+            .program
+            .type_declarations
+            .iter()
+            .find(|decl| decl.id.to_string() == ty)
+            .and_then(|decl| decl.declared_type_info.as_ref())
+            .is_none_or(|info| info.duplicatable)
+    }
+
+    /// Type info of the declared type `ty`, the one [`SierraType::declared_type_info`] gives for undeclared types.
+    fn declared_type_info(&self, ty: &str) -> DeclaredTypeInfo {
+        self.sierra
+            .program
+            .type_declarations
+            .iter()
+            .find(|decl| decl.id.to_string() == ty)
+            .and_then(|decl| decl.declared_type_info.clone())
+            .unwrap_or_else(|| SierraType::from_name(ty).declared_type_info())
+    }
+
+    /// Declares the `Uninitialized<ty>` type of unwritten locals if needed.
+    pub fn insert_uninitialized_type(&mut self, ty: &str) {
+        let uninitialized_ty = format!("Uninitialized<{}>", ty);
+        if self.sierra.types.insert(uninitialized_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&uninitialized_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("Uninitialized"),
+                    generic_args: vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: false,
+                    droppable: true,
+                    duplicatable: false,
+                    zero_sized: false,
+                }),
+            })
+        }
+    }
+
+    /// Declares the `BoundedInt<0, max>` type if needed and returns its name.
+    pub fn insert_bounded_int_type(&mut self, max: u64) -> String {
+        let bounded_ty = format!("BoundedInt<0, {}>", max);
+        if self.sierra.types.insert(bounded_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&bounded_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("BoundedInt"),
+                    generic_args: vec![
+                        GenericArg::Value(BigInt::from(0)),
+                        GenericArg::Value(BigInt::from(max)),
+                    ],
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: true,
+                    droppable: true,
+                    duplicatable: true,
+                    zero_sized: false,
+                }),
+            })
+        }
+        bounded_ty
+    }
+
+    /// Declares the `SwitchCases<n>` enum (`Enum<ut@SwitchCases, Unit, ...>` with `n` unit variants) a dense `switch`
+    /// matches on if needed and returns its name.
+    pub fn insert_switch_cases_type(&mut self, n: usize) -> String {
+        let unit = self.insert_struct_type(vec![]);
+        let cases_ty = format!("SwitchCases<{}>", n);
+        if self.sierra.types.insert(cases_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&cases_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("Enum"),
+                    generic_args: std::iter::once(GenericArg::UserType(UserTypeId::from_string(
+                        "SwitchCases",
+                    )))
+                    .chain((0..n).map(|_| GenericArg::Type(ConcreteTypeId::from_string(&unit))))
+                    .collect(),
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: true,
+                    droppable: true,
+                    duplicatable: true,
+                    zero_sized: false,
+                }),
+            })
+        }
+        cases_ty
+    }
+
+    /// Declares the `NonZero<ty>` type if needed.
+    pub fn insert_non_zero_type(&mut self, ty: &str) {
+        let non_zero_ty = format!("NonZero<{}>", ty);
+        if self.sierra.types.insert(non_zero_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&non_zero_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("NonZero"),
+                    generic_args: vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: true,
+                    droppable: true,
+                    duplicatable: true,
+                    zero_sized: false,
+                }),
+            })
+        }
+    }
+
+    /// Lowers `select i1 true, %a, %b` to the chosen operand without branching.
+    pub fn build_const_select(&mut self, select: InstructionValue<'ctx>) {
+        let cond = select
+            .get_operand(0)
+            .unwrap()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let (chosen, dropped) = if cond.get_zero_extended_constant() == Some(1) {
+            (1, 2)
+        } else {
+            (2, 1)
+        };
+        let chosen = select.get_operand(chosen).unwrap().left().unwrap();
+        let dropped = select.get_operand(dropped).unwrap().left().unwrap();
+        // The other operand isn't read by the select anymore.
+        if let Some(uses) = self.remaining_uses.get_mut(&dropped) {
+            *uses = uses.saturating_sub(1);
+        }
+        let result = BasicValueEnum::try_from(select.as_any_value_enum()).unwrap();
+        if chosen.is_int_value() && chosen.into_int_value().is_constant_int() {
+            let ty = self.declare_llvm_type(chosen.get_type());
+            self.add_const_if_const(chosen, ty);
+            self.deferred_values.insert(result);
+        }
+        self.alias_value(result, chosen);
+    }
+
+    /// Returns the `select` consuming the result of the comparison `icmp` if both can be lowered as a single branch.
+    /// That's the case when the comparison result is only used as the condition of the select that follows it and
+    /// the predicate maps to a branching sierra comparison. Comparisons of pointers lower to a `bool` the select
+    /// matches on.
+    pub fn fusable_select(&self, icmp: InstructionValue<'ctx>) -> Option<InstructionValue<'ctx>> {
+        let select = icmp.get_next_instruction()?;
+        let condition = BasicValueEnum::try_from(icmp.as_any_value_enum()).ok()?;
+        (select.get_opcode() == InstructionOpcode::Select
+            && icmp.get_operand(0)?.left()?.is_int_value()
+            && select.get_operand(0)?.left()? == condition
+            && self.remaining_uses.get(&condition) == Some(&1)
+            && branching_comparison(icmp.get_icmp_predicate()?).is_some())
+        .then_some(select)
+    }
+
+    /// Lowers `%c = icmp pred a, b; %r = select %c, x, y` to a comparison that branches to a store of either `x` or
+    /// `y` in the result variable of the select:
+    /// ```text
+    /// u32_lt(rc, a, b) { fallthrough(rc) true_branch(rc) }
+    /// branch_align() -> ()
+    /// store_temp<u32>(y) -> (r)
+    /// jump() { merge() }
+    /// true_branch:
+    /// branch_align() -> ()
+    /// store_temp<u32>(x) -> (r)
+    /// merge:
+    /// ```
+    pub fn build_select_on_comparison(
+        &mut self,
+        icmp: InstructionValue<'ctx>,
+        select: InstructionValue<'ctx>,
+    ) {
+        let (cond, swap_operands, negate) =
+            branching_comparison(icmp.get_icmp_predicate().unwrap()).unwrap();
+        let mut lhs = icmp.get_operand(0).unwrap().left().unwrap();
+        let mut rhs = icmp.get_operand(1).unwrap().left().unwrap();
+        if swap_operands {
+            std::mem::swap(&mut lhs, &mut rhs);
+        }
+        let cmp_ty = SierraType::from_llvm(&lhs.get_type().to_string());
+        self.insert_type(cmp_ty.to_string());
+        // The comparisons don't accept deferred values as their first operand and `lt`/`le` take no const at all.
+        let args = [lhs, rhs].map(|val| self.use_stored_var(val, &cmp_ty.to_string()));
+        let mut true_val = select.get_operand(1).unwrap().left().unwrap();
+        let mut false_val = select.get_operand(2).unwrap().left().unwrap();
+        if negate {
+            std::mem::swap(&mut true_val, &mut false_val);
+        }
+        let ty = self.declare_llvm_type(BasicTypeEnum::try_from(select.get_type()).unwrap());
+        let [true_shared, false_shared] =
+            self.use_shared_selected_values([true_val, false_val], &ty);
+        let result = self.bind_result_var(select);
+        let range_check = (cond != "eq").then(|| self.range_check_var());
+        let cmp_libfunc = self.register_libfunc(&cmp_ty.libfunc_name(cond), vec![]);
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let cmp_idx = self.sierra.program.statements.len();
+        // The target of the true branch is patched once the false branch is emitted.
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: cmp_libfunc.clone(),
+                args: vec![],
+                branches: vec![],
+            }));
+
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.store_selected_value(
+            false_val,
+            ty.clone(),
+            result.clone(),
+            [false_shared.clone(), true_shared.clone()],
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let true_branch_idx = self.sierra.program.statements.len();
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.store_selected_value(true_val, ty, result, [true_shared, false_shared]);
+        let merge_idx = self.sierra.program.statements.len();
+
+        let results = range_check.clone().into_iter().collect::<Vec<_>>();
+        self.sierra.program.statements[cmp_idx] = GenStatement::Invocation(GenInvocation {
+            libfunc_id: cmp_libfunc,
+            args: range_check.into_iter().chain(args).collect(),
+            branches: vec![
+                GenBranchInfo {
+                    target: GenBranchTarget::Fallthrough,
+                    results: results.clone(),
+                },
+                GenBranchInfo {
+                    target: GenBranchTarget::Statement(StatementIdx(true_branch_idx)),
+                    results,
+                },
+            ],
+        });
+        self.set_branch_target(jump_idx, 0, merge_idx);
+    }
+
+    /// Lowers `select i1 %c, %a, %b` to an `enum_match<bool>` on the condition, each branch stores its value in the
+    /// result and they merge right after:
+    /// ```text
+    /// enum_match<bool>(c) { fallthrough(unit) 4(unit) }
+    /// branch_align() -> ()
+    /// store_temp<u32>(b) -> (result)
+    /// jump() { 6() }
+    /// branch_align() -> ()
+    /// store_temp<u32>(a) -> (result)
+    /// ```
+    pub fn build_select(&mut self, select: InstructionValue<'ctx>) {
+        let cond = select.get_operand(0).unwrap().left().unwrap();
+        let true_val = select.get_operand(1).unwrap().left().unwrap();
+        let false_val = select.get_operand(2).unwrap().left().unwrap();
+        self.insert_type("bool".to_owned());
+        let cond = self.use_var(cond);
+        let ty = self.declare_llvm_type(BasicTypeEnum::try_from(select.get_type()).unwrap());
+        let [true_shared, false_shared] =
+            self.use_shared_selected_values([true_val, false_val], &ty);
+        let result = self.bind_result_var(select);
+        let enum_match = self.register_libfunc(
+            "enum_match",
+            vec![GenericArg::Type(ConcreteTypeId::from_string("bool"))],
+        );
+        let branch_align = self.register_libfunc("branch_align", vec![]);
+        let match_idx = self.sierra.program.statements.len();
+        // The target of the true branch is patched once the false branch is emitted.
+        let branches = [(); 2].map(|_| GenBranchInfo {
+            target: GenBranchTarget::Fallthrough,
+            results: vec![VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            }],
+        });
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id: enum_match,
+                args: vec![cond],
+                branches: branches.into(),
+            }));
+        self.push_simple_basic_statement(branch_align.clone(), &[], &[]);
+        self.store_selected_value(
+            false_val,
+            ty.clone(),
+            result.clone(),
+            [false_shared.clone(), true_shared.clone()],
+        );
+        let jump_idx = self.sierra.program.statements.len();
+        self.push_jump(usize::MAX);
+        let true_branch_idx = self.sierra.program.statements.len();
+        self.push_simple_basic_statement(branch_align, &[], &[]);
+        self.store_selected_value(true_val, ty, result, [true_shared, false_shared]);
+        let merge_idx = self.sierra.program.statements.len();
+        if let GenStatement::Invocation(invocation) = &mut self.sierra.program.statements[match_idx]
+        {
+            invocation.branches[1].target =
+                GenBranchTarget::Statement(StatementIdx(true_branch_idx));
+        }
+        if let GenStatement::Invocation(jump) = &mut self.sierra.program.statements[jump_idx] {
+            jump.branches[0].target = GenBranchTarget::Statement(StatementIdx(merge_idx));
+        }
+    }
+
+    /// Variables of the shared consts among the values of a select, they're read before it branches as the copies
+    /// handed out after would only exist in one of the branches.
+    fn use_shared_selected_values(
+        &mut self,
+        vals: [BasicValueEnum<'ctx>; 2],
+        ty: &str,
+    ) -> [Option<VarId>; 2] {
+        vals.map(|val| {
+            self.is_shared_const(val).then(|| {
+                self.add_const_if_const(val, ty.to_owned());
+                self.use_var(val)
+            })
+        })
+    }
+
+    /// Stores the value chosen by one of the branches of a select in the result variable of the select. `shared`
+    /// are the variables from [`Self::use_shared_selected_values`] of the chosen value and of the other one, which
+    /// is dropped.
+    fn store_selected_value(
+        &mut self,
+        val: BasicValueEnum<'ctx>,
+        ty: String,
+        result: VarId,
+        shared: [Option<VarId>; 2],
+    ) {
+        let [chosen, other] = shared;
+        if let Some(other) = other {
+            let drop = self.register_libfunc(
+                "drop",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(&ty))],
+            );
+            self.push_simple_basic_statement(drop, &[other], &[]);
+        }
+        let source = chosen.unwrap_or_else(|| {
+            self.add_const_if_const(val, ty.clone());
+            self.use_var(val)
+        });
+        self.push_store_temp_statement(ty, &[source], &[result]);
+    }
+
+    /// Json array mapping each statement lowered from an instruction with a `!dbg` location to its source line, like
+    /// `[{"statement": 0, "line": 3}]`.
+    pub fn debug_map_json(&self) -> String {
+        let entries = self
+            .statement_lines
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                line.map(|line| format!("{{\"statement\": {}, \"line\": {}}}", idx, line))
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", entries.join(", "))
+    }
+
+    /// Json object mapping the llvm values of each function of `module` to their sierra variable, like
+    /// `{"fib": {"%n": {"id": 0, "debug_name": "n"}, "%3": {"id": 4, "debug_name": "v4"}}}`. Values lowered to
+    /// several variables have the one they were first bound to, values without any (stores, branches) are left out.
+    pub fn var_map_json(&self, module: &Module<'ctx>) -> String {
+        let names = crate::format::variable_names(&self.sierra.program);
+        let functions = module
+            .get_functions()
+            .filter(|function| function.count_basic_blocks() > 0)
+            .map(|function| {
+                let function_name = function.get_name().to_str().unwrap().to_owned();
+                let values = function.get_param_iter().chain(
+                    function
+                        .get_basic_block_iter()
+                        .flat_map(|block| block.get_instructions())
+                        .filter_map(|instr| {
+                            BasicValueEnum::try_from(instr.as_any_value_enum()).ok()
+                        }),
+                );
+                let entries = values
+                    .filter_map(|val| {
+                        let var = self
+                            .variables
+                            .get(&self.aliases.get(&val).copied().unwrap_or(val))?;
+                        let debug_name = names
+                            .get(&function_name)
+                            .and_then(|names| names.get(&var.id))
+                            .map_or("null".to_owned(), |name| format!("\"{}\"", name));
+                        Some(format!(
+                            "\"{}\": {{\"id\": {}, \"debug_name\": {}}}",
+                            llvm_value_name(val)?,
+                            var.id,
+                            debug_name
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                format!("\"{}\": {{{}}}", function_name, entries.join(", "))
+            })
+            .collect::<Vec<_>>();
+        format!("{{{}}}", functions.join(", "))
+    }
+
+    /// Formats the program like its `Display` implementation but with each statement prefixed with the llvm block and
+    /// opcode it was lowered from.
+    pub fn annotated_program(&self) -> String {
+        let mut out = String::new();
+        for declaration in &self.sierra.program.type_declarations {
+            out.push_str(&format!("{};\n", declaration));
+        }
+        out.push('\n');
+        for declaration in &self.sierra.program.libfunc_declarations {
+            out.push_str(&format!("{};\n", declaration));
+        }
+        out.push('\n');
+        for (i, statement) in self.sierra.program.statements.iter().enumerate() {
+            if let Some((block, opcode)) = self.statement_origins.get(i) {
+                out.push_str(&format!("[{} {:?}] ", block, opcode));
+            }
+            out.push_str(&format!("{}; // {}\n", statement, i));
+        }
+        out.push('\n');
+        for func in &self.sierra.program.funcs {
+            out.push_str(&format!("{};\n", func));
+        }
+        out
+    }
+
+    /// Declares the sierra type representing the llvm type `ty` and returns its name. Llvm structs become sierra
+    /// tuples of their (mapped) fields.
+    pub fn declare_llvm_type(&mut self, ty: BasicTypeEnum<'ctx>) -> String {
+        if let BasicTypeEnum::StructType(struct_ty) = ty {
+            let fields = struct_ty
+                .get_field_types()
+                .into_iter()
+                .map(|field| self.declare_llvm_type(field))
+                .collect::<Vec<_>>();
+            self.insert_struct_type(fields)
+        } else {
+            let sierra_ty = llvm_int_ty_to_sierra(&ty.print_to_string().to_string());
+            self.insert_type(sierra_ty.clone());
+            sierra_ty
+        }
+    }
+
+    /// Declares the `Struct<ut@Tuple, fields...>` type if needed and returns its name.
+    pub fn insert_struct_type(&mut self, fields: Vec<String>) -> String {
+        let struct_ty = format!("Tuple<{}>", fields.join(", "));
+        if self.sierra.types.insert(struct_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&struct_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("Struct"),
+                    generic_args: std::iter::once(GenericArg::UserType(UserTypeId::from_string(
+                        "Tuple",
+                    )))
+                    .chain(
+                        fields
+                            .iter()
+                            .map(|field| GenericArg::Type(ConcreteTypeId::from_string(field))),
+                    )
+                    .collect(),
+                },
+                declared_type_info: Some(struct_type_info(
+                    fields.iter().map(|field| self.declared_type_info(field)),
+                )),
+            })
+        }
+        struct_ty
+    }
+
+    /// Declares the sierra `bool` type (`Enum<ut@core::bool, Unit, Unit>`) if needed.
+    pub fn insert_bool_type(&mut self) {
+        let unit = self.insert_struct_type(vec![]);
+        if self.sierra.types.insert("bool".to_owned()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string("bool"),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("Enum"),
+                    generic_args: vec![
+                        GenericArg::UserType(UserTypeId::from_string("core::bool")),
+                        GenericArg::Type(ConcreteTypeId::from_string(&unit)),
+                        GenericArg::Type(ConcreteTypeId::from_string(unit)),
+                    ],
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: true,
+                    droppable: true,
+                    duplicatable: true,
+                    zero_sized: false,
+                }),
+            })
+        }
+    }
+
+    /// Lowers `and`/`or`/`xor` of two `i1` with the `bool_<op>_impl` libfunc `libfunc_name`. These libfuncs don't
+    /// accept deferred values and return one so a chain like `a && b && c` stores the intermediate result in between.
+    pub fn build_bool_op(&mut self, instr: InstructionValue<'ctx>, libfunc_name: &str) {
+        self.insert_type("bool".to_owned());
+        let libfunc_id = self.register_libfunc(libfunc_name, vec![]);
+        let args = [0, 1].map(|i| {
+            let operand = instr.get_operand(i).unwrap().left().unwrap();
+            self.add_const_if_const(operand, "bool".to_owned());
+            let deferred = self.deferred_values.contains(&operand);
+            let var = self.use_var(operand);
+            if deferred {
+                self.push_store_temp_statement(
+                    "bool".to_owned(),
+                    std::slice::from_ref(&var),
+                    std::slice::from_ref(&var),
+                );
+            }
+            var
+        });
+        let result = self.bind_result_var(instr);
+        self.push_simple_basic_statement(libfunc_id, &args, &[result]);
+        if let Ok(result_val) = BasicValueEnum::try_from(instr.as_any_value_enum()) {
+            self.deferred_values.insert(result_val);
+        }
+    }
+
+    /// Lowers an integer `and`/`or`/`xor` with the `bitwise` libfunc, which only exists for `u128`, the operands are
+    /// upcast to it and the result downcast back (see [`Self::push_fitting_downcast`]). `bitwise` gives the three
+    /// operations at once but each instruction gets its own invocation, the other results are dropped: llvm rarely
+    /// computes two of them on the same operands.
+    pub fn build_bitwise_op(&mut self, instr: InstructionValue<'ctx>) {
+        let operands = [0, 1].map(|i| instr.get_operand(i).unwrap().left().unwrap());
+        let sierra_ty = SierraType::from_llvm(&operands[0].get_type().to_string());
+        let ty = sierra_ty.to_string();
+        self.insert_type(ty.clone());
+        self.insert_type(SierraType::U128.to_string());
+        let args = operands.map(|val| {
+            let var = self.use_stored_var(val, &ty);
+            if sierra_ty == SierraType::U128 {
+                return var;
+            }
+            let upcast = self.register_libfunc(
+                "upcast",
+                vec![
+                    GenericArg::Type(sierra_ty.concrete_id()),
+                    GenericArg::Type(SierraType::U128.concrete_id()),
+                ],
+            );
+            let wide = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            self.push_simple_basic_statement(upcast, &[var], std::slice::from_ref(&wide));
+            wide
+        });
+        let bitwise = self.bitwise_var();
+        // `bitwise` returns `and`, `xor` and `or` in that order.
+        let mut results = [(); 3].map(|_| VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        });
+        let kept = match instr.get_opcode() {
+            InstructionOpcode::And => 0,
+            InstructionOpcode::Xor => 1,
+            _ => 2,
+        };
+        let result = self.bind_result_var(instr);
+        if sierra_ty == SierraType::U128 {
+            results[kept] = result.clone();
+        }
+        let bitwise_libfunc = self.register_libfunc("bitwise", vec![]);
+        self.push_simple_basic_statement(
+            bitwise_libfunc,
+            &[bitwise.clone(), args[0].clone(), args[1].clone()],
+            &[
+                bitwise,
+                results[0].clone(),
+                results[1].clone(),
+                results[2].clone(),
+            ],
+        );
+        let drop = self.register_libfunc(
+            "drop",
+            vec![GenericArg::Type(SierraType::U128.concrete_id())],
+        );
+        for (idx, var) in results.iter().enumerate() {
+            if idx != kept {
+                self.push_simple_basic_statement(drop.clone(), std::slice::from_ref(var), &[]);
+            }
+        }
+        if sierra_ty != SierraType::U128 {
+            let wide = results[kept].clone();
+            self.push_fitting_downcast(&SierraType::U128, &sierra_ty, wide, result);
+        }
+    }
+
+    /// Prints the warning `message`, unless the builder is [`Self::quiet`].
+    fn warn(&self, message: &str) {
+        if !self.quiet {
+            eprintln!("warning: {}", message);
+        }
+    }
+
+    /// Location of the instruction being lowered, for errors.
+    pub fn current_span(&self) -> Span {
+        Span {
+            function: self.current_function.clone().unwrap_or_default(),
+            line: self.current_line,
+        }
+    }
+
+    /// Lowers `ret`. Returned values have to be on top of the stack, the ones that were computed in another block
+    /// (or not stored at all like consts and deferred values) are stored in a temp var right before returning. Call
+    /// results are stored by `function_call` so a tail call (`%r = call @g(); ret %r`) returns its result as is.
+    pub fn build_return(&mut self, instr: InstructionValue<'ctx>) -> Result<(), LoweringError> {
+        let block = instr.get_parent();
+        let function_id = FunctionId::from_string(self.current_function.as_ref().unwrap());
+        let expected = self
+            .sierra
+            .program
+            .funcs
+            .iter()
+            .find(|func| func.id == function_id)
+            .map(|func| func.signature.ret_types.clone())
+            .unwrap_or_default();
+        let found = instr
+            .get_operands()
+            .flatten()
+            .filter_map(|op| op.left())
+            .map(|val| ConcreteTypeId::from_string(self.declare_llvm_type(val.get_type())))
+            .collect::<Vec<_>>();
+        // The builtins are added to the signature once the whole function is lowered.
+        if found != expected {
+            let names = |types: &[ConcreteTypeId]| {
+                types
+                    .iter()
+                    .map(|ty| ty.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            return Err(LoweringError::ReturnTypeMismatch {
+                expected: names(&expected),
+                found: names(&found),
+                span: self.current_span(),
+            });
+        }
+        // Builtins are returned first, in the order they're received.
+        let mut returned = self
+            .used_builtins()
+            .into_iter()
+            .map(|(_, var)| var)
+            .collect::<Vec<_>>();
+        for val in instr.get_operands().flatten().filter_map(|op| op.left()) {
+            let ty = self.declare_llvm_type(val.get_type());
+            // Aggregates are built from their fields (the chain of `insertvalue`) right before being returned, unless
+            // they already have a variable (params, results of calls).
+            if val.is_struct_value() && !self.variables.contains_key(&val) {
+                let var = self.build_struct_construct(val, ty.clone());
+                self.push_store_temp_statement(
+                    ty,
+                    std::slice::from_ref(&var),
+                    std::slice::from_ref(&var),
+                );
+                returned.push(var);
+                continue;
+            }
+            self.add_const_if_const(val, ty.clone());
+            let defined_here = val
+                .as_instruction_value()
+                .is_some_and(|def| def.get_parent() == block);
+            let deferred = self.deferred_values.contains(&val);
+            let var = self.use_var(val);
+            if !defined_here || deferred {
+                self.push_store_temp_statement(
+                    ty,
+                    std::slice::from_ref(&var),
+                    std::slice::from_ref(&var),
+                );
+            }
+            returned.push(var);
+        }
+        self.drop_locals(block.unwrap().get_parent().unwrap(), &returned);
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Return(returned));
+        Ok(())
+    }
+
+    /// Drops the locals of `function` before returning, except the ones whose value is `returned`. A local that was
+    /// never stored is dropped uninitialized.
+    fn drop_locals(&mut self, function: FunctionValue<'ctx>, returned: &[VarId]) {
+        let mut locals = self
+            .locals
+            .iter()
+            .filter(|(slot, _)| {
+                slot.as_instruction_value()
+                    .and_then(|alloca| alloca.get_parent())
+                    .and_then(|block| block.get_parent())
+                    == Some(function)
+            })
+            .map(|(_, local)| local.clone())
+            .filter(|(_, var, _)| !returned.contains(var))
+            .collect::<Vec<_>>();
+        locals.sort_by_key(|(_, var, _)| var.id);
+        for (ty, var, stored) in locals {
+            let ty = if stored {
+                ty
+            } else {
+                format!("Uninitialized<{}>", ty)
+            };
+            let drop = self.register_libfunc(
+                "drop",
+                vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+            );
+            self.push_simple_basic_statement(drop, &[var], &[]);
+        }
+    }
+
+    /// Lowers `call @callee(args...)` to `function_call<user@callee>`. Each argument is stored in a temp var as
+    /// required by sierra, aggregates passed by value are first constructed from their fields.
+    pub fn build_call(&mut self, instr: InstructionValue<'ctx>) {
+        let nb_args = instr.get_num_operands() - 1;
+        // The callee is the last operand of the call.
+        let callee = instr
+            .get_operand(nb_args)
+            .unwrap()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+            .get_name()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        if callee == "printf" {
+            return self.build_debug_print(instr);
+        }
+        let caller = instr.get_parent().unwrap().get_parent().unwrap();
+        // Every function is declared before the bodies are lowered so (mutually) recursive calls resolve, they
+        // withdraw gas.
+        let recursive = self.recursive_calls.contains(&(
+            caller.get_name().to_str().unwrap().to_owned(),
+            callee.clone(),
+        ));
+        if recursive {
+            self.build_withdraw_gas(caller);
+        }
+        // The callee takes its builtins and gives them back, the caller has them as well (see `function_builtins`).
+        let callee_builtins = self
+            .function_builtins
+            .as_ref()
+            .and_then(|builtins| builtins.get(&callee))
+            .cloned()
+            .unwrap_or_default();
+        let mut builtins = vec![];
+        for ty in callee_builtins {
+            let var = self.builtin_var(ty);
+            self.push_store_temp_statement(
+                ty.to_owned(),
+                std::slice::from_ref(&var),
+                std::slice::from_ref(&var),
+            );
+            builtins.push(var);
+        }
+        let args = (0..nb_args)
+            .map(|i| {
+                let arg = instr.get_operand(i).unwrap().left().unwrap();
+                let ty = self.declare_llvm_type(arg.get_type());
+                let source = if arg.is_struct_value() {
+                    self.build_struct_construct(arg, ty.clone())
+                } else {
+                    self.add_const_if_const(arg, ty.clone());
+                    self.use_var(arg)
+                };
+                let stored = VarId {
+                    id: self.next_var() as u64,
+                    debug_name: source.debug_name.clone(),
+                };
+                self.push_store_temp_statement(ty, &[source], std::slice::from_ref(&stored));
+                stored
+            })
+            .collect::<Vec<_>>();
+        let function_call = self.register_libfunc(
+            "function_call",
+            vec![GenericArg::UserFunc(FunctionId::from_string(callee))],
+        );
+        let mut results = builtins.clone();
+        if let Ok(ret_ty) = BasicTypeEnum::try_from(instr.get_type()) {
+            self.declare_llvm_type(ret_ty);
+            results.push(self.bind_result_var(instr));
+        }
+        builtins.extend(args);
+        self.push_simple_basic_statement(function_call, &builtins, &results);
+    }
+
+    /// Lowers `printf(format, args...)` to the `print` debug libfunc printing the integer arguments as felts, the text
+    /// of the format is ignored. Formats with non integer conversions (or that aren't a const string) are dropped
+    /// with a warning.
+    pub fn build_debug_print(&mut self, instr: InstructionValue<'ctx>) {
+        let nb_args = instr.get_num_operands() - 1;
+        let format = instr.get_operand(0).unwrap().left().unwrap();
+        // Globals are the only const pointers with an initializer.
+        let format = (format.is_pointer_value() && format.into_pointer_value().is_const())
+            .then(|| unsafe { GlobalValue::new(format.as_value_ref()) })
+            .and_then(|global| global.get_initializer())
+            .filter(|init| init.is_array_value())
+            .and_then(|init| {
+                init.into_array_value()
+                    .get_string_constant()
+                    .map(|format| format.to_string_lossy().into_owned())
+            });
+        let args = (1..nb_args)
+            .map(|i| instr.get_operand(i).unwrap().left().unwrap())
+            .collect::<Vec<_>>();
+        let supported = format
+            .is_some_and(|format| printed_integer_conversions(&format) == Some(args.len()))
+            && args.iter().all(|arg| arg.is_int_value());
+        if !supported {
+            self.warn(&format!(
+                "`{}` only prints integers with a const format, it's dropped",
+                instr.print_to_string().to_string().trim()
+            ));
+            return;
+        }
+        self.insert_type("felt252".to_owned());
+        let array_ty = self.insert_array_type("felt252");
+        let felt_arg = vec![GenericArg::Type(ConcreteTypeId::from_string("felt252"))];
+        let array_new = self.register_libfunc("array_new", felt_arg.clone());
+        let array_append = self.register_libfunc("array_append", felt_arg);
+        let print = self.register_libfunc("print", vec![]);
+        let array = VarId {
+            id: self.next_var() as u64,
+            debug_name: Some(SmolStr::from("print_args")),
+        };
+        self.push_simple_basic_statement(array_new, &[], std::slice::from_ref(&array));
+        for arg in args {
+            let ty = llvm_int_ty_to_sierra(&arg.get_type().to_string());
+            self.add_const_if_const(arg, ty.clone());
+            let var = self.use_var(arg);
+            let felt = VarId {
+                id: self.next_var() as u64,
+                debug_name: None,
+            };
+            let to_felt = self.register_libfunc(&format!("{}_to_felt252", ty), vec![]);
+            self.push_simple_basic_statement(to_felt, &[var], std::slice::from_ref(&felt));
+            self.push_simple_basic_statement(
+                array_append.clone(),
+                &[array.clone(), felt],
+                std::slice::from_ref(&array),
+            );
+        }
+        self.push_store_temp_statement(
+            array_ty,
+            std::slice::from_ref(&array),
+            std::slice::from_ref(&array),
+        );
+        self.push_simple_basic_statement(print, &[array], &[]);
+    }
+
+    /// Declares the `Array<ty>` type if needed and returns it.
+    pub fn insert_array_type(&mut self, ty: &str) -> String {
+        let array_ty = format!("Array<{}>", ty);
+        if self.sierra.types.insert(array_ty.clone()) {
+            self.sierra.program.type_declarations.push(TypeDeclaration {
+                id: ConcreteTypeId::from_string(&array_ty),
+                long_id: ConcreteTypeLongId {
+                    generic_id: GenericTypeId::from_string("Array"),
+                    generic_args: vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+                },
+                declared_type_info: Some(DeclaredTypeInfo {
+                    storable: true,
+                    droppable: true,
+                    duplicatable: false,
+                    zero_sized: false,
+                }),
+            })
+        }
+        array_ty
+    }
+
+    /// Builds the struct value `val` of sierra type `ty` with `struct_construct` from the variables of its fields.
+    pub fn build_struct_construct(&mut self, val: BasicValueEnum<'ctx>, ty: String) -> VarId {
+        let fields = self.struct_fields(val).into_iter().collect::<Vec<_>>();
+        // A field that was never set has no value, it's reported once the instruction is lowered (see
+        // [`Self::unset_struct`]).
+        if fields.contains(&None) && self.unset_struct.is_none() {
+            self.unset_struct =
+                Some(llvm_value_name(val).unwrap_or_else(|| val.print_to_string().to_string()));
+        }
+        let fields = fields
+            .into_iter()
+            .flatten()
+            .map(|field| {
+                let field_ty = self.declare_llvm_type(field.get_type());
+                self.add_const_if_const(field, field_ty);
+                self.use_var(field)
+            })
+            .collect::<Vec<_>>();
+        let struct_construct = self.register_libfunc(
+            "struct_construct",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(ty))],
+        );
+        let result = VarId {
+            id: self.next_var() as u64,
+            debug_name: (!val.get_name().is_empty())
+                .then(|| SmolStr::from(val.get_name().to_str().unwrap())),
+        };
+        self.push_simple_basic_statement(struct_construct, &fields, std::slice::from_ref(&result));
+        result
+    }
+
+    /// Llvm values of the fields of the struct `val`, either a const struct or the result of a chain of
+    /// `insertvalue`. Fields that were never set are `None`. An `insertvalue` into a nested aggregate is reported
+    /// once the instruction is lowered (see [`Self::unsupported_insertvalue`]) and sets nothing.
+    pub fn struct_fields(
+        &mut self,
+        val: BasicValueEnum<'ctx>,
+    ) -> Vec<Option<BasicValueEnum<'ctx>>> {
+        let struct_val = val.into_struct_value();
+        match struct_val.as_instruction_value() {
+            Some(instr) if instr.get_opcode() == InstructionOpcode::InsertValue => {
+                let mut fields = self.struct_fields(instr.get_operand(0).unwrap().left().unwrap());
+                let printed = instr.print_to_string().to_string();
+                match aggregate_indices(instr)[..] {
+                    [index] => fields[index] = Some(instr.get_operand(1).unwrap().left().unwrap()),
+                    _ if self.unsupported_insertvalue.is_none() => {
+                        self.unsupported_insertvalue = Some(printed);
+                    }
+                    _ => (),
+                }
+                fields
+            }
+            // `undef`/`poison` structs have no field set yet.
+            _ if struct_val.print_to_string().to_string().ends_with("undef")
+                || struct_val.print_to_string().to_string().ends_with("poison") =>
+            {
+                vec![None; struct_val.count_fields() as usize]
+            }
+            _ => (0..struct_val.count_fields())
+                .map(|i| struct_val.get_field_at_index(i))
+                .collect(),
+        }
+    }
+
+    /// Whether the statements of the block starting at `block_start` end with a statement that leaves the block: a
+    /// return or an invocation that can't fall through.
+    pub fn ends_with_terminator(&self, block_start: usize) -> bool {
+        match self.sierra.program.statements[block_start..].last() {
+            Some(GenStatement::Return(_)) => true,
+            Some(GenStatement::Invocation(invocation)) => invocation
+                .branches
+                .iter()
+                .all(|branch| branch.target != GenBranchTarget::Fallthrough),
+            None => false,
+        }
+    }
+
+    /// Emits an `unsupported_<opcode>` invocation standing for an instruction that can't be lowered. Its result (if
+    /// any) is bound to a placeholder variable so the instructions using it read that variable instead of failing
+    /// with [`LoweringError::MissingVariable`]. The operands aren't read, their variables are left to their other
+    /// uses. Terminators get a placeholder without branches so the block still ends.
+    pub fn build_unsupported_placeholder(&mut self, instr: InstructionValue<'ctx>) {
+        let name = format!("unsupported_{:?}", instr.get_opcode()).to_lowercase();
+        let libfunc_id = self.register_libfunc(&name, vec![]);
+        let results = if BasicValueEnum::try_from(instr.as_any_value_enum()).is_ok() {
+            vec![self.bind_result_var(instr)]
+        } else {
+            vec![]
+        };
+        let branches = if instr.is_terminator() {
+            vec![]
+        } else {
+            vec![GenBranchInfo {
+                target: GenBranchTarget::Fallthrough,
+                results,
+            }]
+        };
+        self.sierra
+            .program
+            .statements
+            .push(GenStatement::Invocation(GenInvocation {
+                libfunc_id,
+                args: vec![],
+                branches,
+            }));
+    }
+
+    /// Creates the sierra variable holding the result of `instr` and binds it to the instruction value.
+    pub fn bind_result_var(&mut self, instr: InstructionValue<'ctx>) -> VarId {
+        let mut result_var_id = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        if let Ok(basic_value_enum) = BasicValueEnum::try_from(instr.as_any_value_enum()) {
+            let res_name = basic_value_enum.get_name().to_str().unwrap();
+            result_var_id.debug_name = (!res_name.is_empty()).then_some(SmolStr::from(res_name));
+            self.variables
+                .insert(basic_value_enum, result_var_id.clone());
+        }
+        result_var_id
+    }
+
+    /// Adds a const function if the int value is a const. Adds the libfunc declaration and adds the call in the
+    /// statements list as well. Consts are materialized in the block reading them, once for the instructions sharing
+    /// one (see [`Self::find_shared_consts`]).
+    pub fn add_const_if_const(&mut self, val: BasicValueEnum<'ctx>, ty: String) {
+        // `null` is the address 0.
+        if val.is_pointer_value() && val.into_pointer_value().is_null() {
+            let next_var = VarId {
+                id: self.next_var() as u64,
+                debug_name: Some(SmolStr::from("null")),
+            };
+            self.push_const_statement(ty, "0".to_owned(), next_var.clone());
+            self.variables.insert(val, next_var);
+            return;
+        }
+        // Other pointers and aggregates are never int consts.
+        if !val.is_int_value() {
+            return;
+        }
+        let val_int = val.into_int_value();
+        if val_int.is_constant_int() {
+            // Get the llvm value of the const so smth like `i32 0` if it's a const
+            let mut int_value = val_int
+                .print_to_string()
+                .to_string()
+                .split_whitespace()
+                .last()
+                .unwrap()
+                .to_owned();
+            // Negative narrow consts (`i3 -1`) would wrap at the bounds of the wider sierra type.
+            let bit_width = val_int.get_type().get_bit_width();
+            if bit_width > 1 && SierraType::from_name(&ty).bit_width() != Some(bit_width) {
+                if let Some(value) = val_int.get_zero_extended_constant() {
+                    int_value = value.to_string();
+                }
+            }
+
+            let shared = self.is_shared_const(val);
+            if let Some((var, _)) = self.shared_const_vars.get(&val).filter(|_| shared).cloned() {
+                let var = self.share_const(val, &ty, var);
+                self.variables.insert(val, var);
+                return;
+            }
+
+            // Var id for the const.
+            let next_var = VarId {
+                id: self.next_var() as u64,
+                debug_name: Some(SmolStr::from(format!("const_{}<{}>", ty, int_value))),
+            };
+            if val_int.get_type().get_bit_width() == 1 {
+                self.push_bool_const(int_value == "true", next_var.clone());
+                self.deferred_values.insert(val);
+            } else {
+                self.push_const_statement(ty.clone(), int_value, next_var.clone());
+            }
+
+            let var = if shared {
+                self.share_const(val, &ty, next_var)
+            } else {
+                next_var
+            };
+            self.variables.insert(val, var);
+        }
+    }
+
+    /// Pushes the construction of the `bool` enum `value` (`enum_init<bool, 1>` of a unit for `true`) in `result`.
+    pub fn push_bool_const(&mut self, value: bool, result: VarId) {
+        self.insert_type("bool".to_owned());
+        let unit_ty = self.insert_struct_type(vec![]);
+        let struct_construct = self.register_libfunc(
+            "struct_construct",
+            vec![GenericArg::Type(ConcreteTypeId::from_string(unit_ty))],
+        );
+        let enum_init = self.register_libfunc(
+            "enum_init",
+            vec![
+                GenericArg::Type(ConcreteTypeId::from_string("bool")),
+                GenericArg::Value(BigInt::from(value as u8)),
+            ],
+        );
+        let unit = VarId {
+            id: self.next_var() as u64,
+            debug_name: None,
+        };
+        self.push_simple_basic_statement(struct_construct, &[], std::slice::from_ref(&unit));
+        self.push_simple_basic_statement(enum_init, &[unit], &[result]);
+    }
+
+    /// Declares the `<ty>_const<value>` libfunc if needed and pushes its call, storing the const in `result`. A value
+    /// that isn't an integer is reported once the instruction is lowered (see [`Self::unsupported_const`]), it goes
+    /// on with a zero.
+    pub fn push_const_statement(&mut self, ty: String, int_value: String, result: VarId) {
+        let value = sierra_const_value(&SierraType::from_name(&ty), &int_value);
+        let value = value.unwrap_or_else(|| {
+            self.unsupported_const.get_or_insert(int_value);
+            BigInt::from(0)
+        });
+        let fn_name = format!("{}_const<{}>", ty, value);
+
+        let func = LibfuncDeclaration {
+            id: ConcreteLibfuncId::from_string(&fn_name),
+            long_id: ConcreteLibfuncLongId {
+                generic_id: GenericLibfuncId::from_string(format!("{}_const", ty)),
+                generic_args: vec![GenericArg::Value(value)],
+            },
+        };
+        // if not declared yet declare it
+        if self.sierra.libfuncs.insert(fn_name.clone()) {
+            self.sierra.program.libfunc_declarations.push(func);
+        }
+        // Add the const call to the statement.
+        self.push_simple_basic_statement(ConcreteLibfuncId::from_string(fn_name), &[], &[result]);
+    }
+
+    /// Computes `add`/`sub`/`mul` of two constant operands at compile time. Returns `None` if the instruction isn't
+    /// foldable or if the result doesn't fit in the operands type, in which case the overflow is left to the runtime.
+    pub fn fold_binary_consts(
+        instr: InstructionValue<'ctx>,
+        first_val: BasicValueEnum<'ctx>,
+        scnd_val: BasicValueEnum<'ctx>,
+    ) -> Option<u128> {
+        if !first_val.is_int_value() || !scnd_val.is_int_value() {
+            return None;
+        }
+        let (first_int, scnd_int) = (first_val.into_int_value(), scnd_val.into_int_value());
+        if !first_int.is_constant_int() || !scnd_int.is_constant_int() {
+            return None;
+        }
+        let lhs = first_int.get_zero_extended_constant()? as u128;
+        let rhs = scnd_int.get_zero_extended_constant()? as u128;
+        let folded = match instr.get_opcode() {
+            InstructionOpcode::Add => lhs.checked_add(rhs)?,
+            InstructionOpcode::Sub => lhs.checked_sub(rhs)?,
+            InstructionOpcode::Mul => lhs.checked_mul(rhs)?,
+            _ => return None,
+        };
+        // The constants fit in a u64, an `i128` result always fits its type.
+        let bit_width = first_int.get_type().get_bit_width();
+        (bit_width >= 128 || folded >> bit_width == 0).then_some(folded)
+    }
+}
+
+/// Unsigned predicate comparing the operands of the signed `predicate` once they're biased by `2^(N-1)`, `None` for
+/// the predicates that aren't signed.
+pub fn unsigned_predicate(predicate: IntPredicate) -> Option<IntPredicate> {
+    match predicate {
+        IntPredicate::SLT => Some(IntPredicate::ULT),
+        IntPredicate::SLE => Some(IntPredicate::ULE),
+        IntPredicate::SGT => Some(IntPredicate::UGT),
+        IntPredicate::SGE => Some(IntPredicate::UGE),
+        _ => None,
+    }
+}
+
+/// Indices of the field `insertvalue`/`extractvalue` reads, one per level of nesting.
+fn aggregate_indices(instr: InstructionValue) -> Vec<usize> {
+    // `%s = insertvalue { i32, i32 } %agg, i32 %val, 1, !dbg !7` the indices are the last tokens before the metadata.
+    let printed = instr.print_to_string().to_string();
+    let mut indices = printed
+        .rsplit(',')
+        .map(str::trim)
+        .skip_while(|token| token.starts_with('!'))
+        .map_while(|token| token.parse::<usize>().ok())
+        .collect::<Vec<_>>();
+    indices.reverse();
+    indices
+}
+
+/// Whether [`SierraBuilder::build_signed_overflow`] lowers the signed overflow intrinsic `call`. Like the signed
+/// comparisons it needs the llvm integer to fill its sierra type, and the product of the signed operands has to fit
+/// in a felt.
+pub fn is_lowerable_signed_overflow(call: InstructionValue) -> bool {
+    let Some(lhs) = call.get_operand(0).and_then(|op| op.left()) else {
+        return false;
+    };
+    if !lhs.is_int_value() {
+        return false;
+    }
+    let bit_width = lhs.into_int_value().get_type().get_bit_width();
+    bit_width <= 64
+        && SierraType::from_llvm(&lhs.get_type().to_string()).bit_width() == Some(bit_width)
+}
+
+/// Whether [`SierraBuilder::build_signed_divmod`] lowers `sdiv`/`srem`, the llvm integer has to fill its sierra type
+/// for its sign bit to be the one of the sierra integer.
+pub fn is_lowerable_signed_division(instr: InstructionValue) -> bool {
+    instr.get_type().is_int_type()
+        && SierraType::from_llvm(&instr.get_type().print_to_string().to_string()).bit_width()
+            == Some(instr.get_type().into_int_type().get_bit_width())
+}
+
+/// Whether [`SierraBuilder::build_comparison`] lowers `icmp`. Pointers are compared on their address, a
+/// [`POINTER_TY`]. The signed predicates need the llvm integer to fill its sierra type, an `i3` lowered to `u8` has
+/// its sign bit in the middle of it.
+pub fn is_lowerable_comparison(icmp: InstructionValue) -> bool {
+    let Some(lhs) = icmp.get_operand(0).and_then(|op| op.left()) else {
+        return false;
+    };
+    let bit_width = match lhs {
+        BasicValueEnum::IntValue(int) => int.get_type().get_bit_width(),
+        BasicValueEnum::PointerValue(_) => POINTER_TY.bit_width().unwrap(),
+        _ => return false,
+    };
+    let predicate = icmp.get_icmp_predicate().unwrap();
+    if unsigned_predicate(predicate).is_some() {
+        SierraType::from_llvm(&lhs.get_type().to_string()).bit_width() == Some(bit_width)
+    } else {
+        branching_comparison(predicate).is_some()
+    }
+}
+
+/// Whether [`SierraBuilder::build_const_shift`] lowers `shift`. Shifting by at least the width is poison, `i128`
+/// has no wide product for `shl` and `ashr` needs the llvm integer to fill its sierra type.
+pub fn is_lowerable_const_shift(shift: InstructionValue) -> bool {
+    let Some(BasicValueEnum::IntValue(value)) = shift.get_operand(0).and_then(|op| op.left())
+    else {
+        return false;
+    };
+    let Some(amount) = shift
+        .get_operand(1)
+        .and_then(|op| op.left())
+        .and_then(|amount| amount.into_int_value().get_zero_extended_constant())
+    else {
+        return false;
+    };
+    let bit_width = value.get_type().get_bit_width();
+    let sierra_ty = SierraType::from_llvm(&value.get_type().to_string());
+    amount < bit_width as u64
+        && match shift.get_opcode() {
+            InstructionOpcode::Shl => sierra_ty.wide_mul_type().is_some(),
+            InstructionOpcode::AShr => sierra_ty.bit_width() == Some(bit_width),
+            _ => true,
+        }
+}
+
+/// Whether [`SierraBuilder::build_int_extension`] lowers `ext`. Integers wider than 128 bits have no sierra type and
+/// `sext` needs the source and destination integers (other than `i1`) to fill their sierra type.
+pub fn is_lowerable_extension(ext: InstructionValue) -> bool {
+    let Some(BasicValueEnum::IntValue(value)) = ext.get_operand(0).and_then(|op| op.left()) else {
+        return false;
+    };
+    let fills_type = |ty: IntType| {
+        let bit_width = ty.get_bit_width();
+        bit_width == 1 || SierraType::from_llvm(&ty.to_string()).bit_width() == Some(bit_width)
+    };
+    let to = ext.get_type().into_int_type();
+    to.get_bit_width() <= 128
+        && (ext.get_opcode() == InstructionOpcode::ZExt
+            || (fills_type(value.get_type()) && fills_type(to)))
+}
+
+/// Sierra comparison implementing the llvm `predicate` as a branch, along with whether the operands and the branches
+/// have to be swapped (`a > b` is `b < a` and `a != b` is `a == b` with the branches swapped). The sierra comparisons
+/// fall through when the comparison is false and jump when it's true.
+pub fn branching_comparison(predicate: IntPredicate) -> Option<(&'static str, bool, bool)> {
+    match predicate {
+        IntPredicate::EQ => Some(("eq", false, false)),
+        IntPredicate::NE => Some(("eq", false, true)),
+        IntPredicate::ULT => Some(("lt", false, false)),
+        IntPredicate::ULE => Some(("le", false, false)),
+        IntPredicate::UGT => Some(("lt", true, false)),
+        IntPredicate::UGE => Some(("le", true, false)),
+        _ => None,
+    }
+}
+
+/// Number of conversions of the printf `format` if they all print an integer (`%d`, `%lu`, `%x`...), `%%` prints a
+/// `%` and isn't a conversion.
+pub fn printed_integer_conversions(format: &str) -> Option<usize> {
+    let mut conversions = 0;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        let conversion = chars
+            .by_ref()
+            .find(|c| !matches!(c, 'l' | 'h' | 'z' | 'j' | '0'..='9' | '-' | '+' | ' ' | '#'))?;
+        match conversion {
+            '%' => (),
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => conversions += 1,
+            _ => return None,
+        }
+    }
+    Some(conversions)
+}
+
+/// Smallest case and number of values of the range of `cases` if they're dense enough to be dispatched with a table
+/// (see [`SierraBuilder::build_switch`]). The cases can be as wide as the integer (`i128`, `i256`) and so can their
+/// range.
+fn switch_table_range(cases: &[BigInt]) -> Option<(BigInt, usize)> {
+    let min = cases.iter().min()?;
+    let max = cases.iter().max()?;
+    let len = usize::try_from(max - min + 1).ok()?;
+    (cases.len() >= 3 && len <= 2 * cases.len()).then(|| (min.clone(), len))
+}
+
+/// Value of the const `int` zero extended, LLVM prints constants as signed (`i8 -1` is `255`).
+fn zero_extended_const(int: IntValue) -> BigInt {
+    let printed = int.print_to_string().to_string();
+    let value = match printed.split_whitespace().last().unwrap() {
+        "true" => BigInt::from(1),
+        "false" => BigInt::from(0),
+        value => value
+            .parse::<BigInt>()
+            .expect("LLVM prints integer constants in decimal"),
+    };
+    if value < BigInt::from(0) {
+        value + (BigInt::from(1) << int.get_type().get_bit_width())
+    } else {
+        value
+    }
+}
+
+/// `index`th element of the const array or struct `aggregate`.
+fn aggregate_element<'ctx>(
+    aggregate: BasicValueEnum<'ctx>,
+    index: u64,
+) -> Option<BasicValueEnum<'ctx>> {
+    match aggregate {
+        BasicValueEnum::ArrayValue(array) => array.get_element_as_constant(index as u32),
+        BasicValueEnum::StructValue(structure) => structure.get_field_at_index(index as u32),
+        _ => None,
+    }
+}
+
+/// Blocks dominating each block of `function` (the block itself included): the ones on every path from the entry
+/// block to it.
+fn dominators<'ctx>(
+    function: FunctionValue<'ctx>,
+) -> HashMap<BasicBlock<'ctx>, HashSet<BasicBlock<'ctx>>> {
+    let blocks = function.get_basic_blocks();
+    let mut predecessors: HashMap<_, Vec<_>> = HashMap::new();
+    for block in &blocks {
+        let successors = block.get_terminator().into_iter().flat_map(|terminator| {
+            terminator
+                .get_operands()
+                .flatten()
+                .filter_map(|operand| operand.right())
+                .collect::<Vec<_>>()
+        });
+        for successor in successors {
+            predecessors.entry(successor).or_default().push(*block);
+        }
+    }
+    let all = blocks.iter().copied().collect::<HashSet<_>>();
+    let mut dominators = blocks
+        .iter()
+        .enumerate()
+        .map(|(idx, block)| {
+            let dominators = if idx == 0 {
+                HashSet::from([*block])
+            } else {
+                all.clone()
+            };
+            (*block, dominators)
+        })
+        .collect::<HashMap<_, _>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in blocks.iter().skip(1) {
+            let mut new = predecessors
+                .get(block)
+                .into_iter()
+                .flatten()
+                .map(|predecessor| dominators[predecessor].clone())
+                .reduce(|common, other| common.intersection(&other).copied().collect())
+                .unwrap_or_default();
+            new.insert(*block);
+            if new != dominators[block] {
+                dominators.insert(*block, new);
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+/// Name of `val` as printed in the llvm ir (`%n`, `%3`), `None` for an unnamed param.
+fn llvm_value_name(val: BasicValueEnum) -> Option<String> {
+    let name = val.get_name().to_str().unwrap();
+    if !name.is_empty() {
+        return Some(format!("%{}", name));
+    }
+    // Unnamed instructions get a number when printed: `%3 = add i32 %1, %2`.
+    let printed = val.print_to_string().to_string();
+    let (name, _) = printed.trim().split_once(" = ")?;
+    Some(name.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use cairo_lang_sierra::program::Program;
+
+    use super::*;
+    use crate::config::Config;
+
+    /// Lowers the llvm ir `source` with the default options, the program is type checked.
+    fn lower(source: &str) -> Result<Program, LoweringError> {
+        SierraBuilder::compile_source(source.as_bytes(), Path::new("test.ll"), &Config::default())
+    }
+
+    /// Libfunc invoked by each statement, `return` for the returns.
+    fn libfuncs(program: &Program) -> Vec<String> {
+        program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                GenStatement::Invocation(invocation) => invocation.libfunc_id.to_string(),
+                GenStatement::Return(_) => "return".to_owned(),
+            })
+            .collect()
+    }
+
+    /// Targets of the branches of the `idx`th statement, `None` for a fallthrough.
+    fn targets(program: &Program, idx: usize) -> Vec<Option<usize>> {
+        let GenStatement::Invocation(invocation) = &program.statements[idx] else {
+            panic!("statement {} isn't an invocation", idx);
+        };
+        invocation
+            .branches
+            .iter()
+            .map(|branch| match branch.target {
+                GenBranchTarget::Fallthrough => None,
+                GenBranchTarget::Statement(StatementIdx(target)) => Some(target),
+            })
+            .collect()
+    }
+
+    fn var(id: u64) -> VarId {
+        VarId {
+            id,
+            debug_name: None,
+        }
+    }
+
+    /// Runs the function `name` of `program` on the integer arguments `args`, see [`crate::interpret::run`].
+    fn run(program: &Program, name: &str, args: &[u128]) -> Result<Vec<BigInt>, String> {
+        crate::interpret::run(program, name, &ints(args))
+    }
+
+    fn ints(values: &[u128]) -> Vec<BigInt> {
+        values.iter().copied().map(BigInt::from).collect()
+    }
+
+    #[test]
+    fn bool_to_int_branches_merge_after_the_true_arm() {
+        let mut builder = SierraBuilder::default();
+        builder.push_bool_to_int("u32", var(0), var(1), BigInt::from(1));
+        let program = &builder.sierra.program;
+        assert_eq!(
+            libfuncs(program),
+            [
+                "enum_match<bool>",
+                "branch_align",
+                "u32_const<0>",
+                "store_temp<u32>",
+                "jump",
+                "branch_align",
+                "u32_const<1>",
+                "store_temp<u32>",
+            ]
+        );
+        assert_eq!(targets(program, 0), [None, Some(5)]);
+        assert_eq!(targets(program, 4), [Some(program.statements.len())]);
+    }
+
+    #[test]
+    fn zext_of_a_runtime_bool() {
+        let program = lower(
+            "define i32 @f(i1 %c) {\n\
+             \x20 %r = zext i1 %c to i32\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        let jump = libfuncs
+            .iter()
+            .position(|libfunc| libfunc == "jump")
+            .unwrap();
+        assert_eq!(
+            libfuncs[jump + 1..jump + 4],
+            ["branch_align", "u32_const<1>", "store_temp<u32>"]
+        );
+        assert_eq!(targets(&program, jump), [Some(jump + 4)]);
+    }
+
+    #[test]
+    fn pointer_comparisons_compare_addresses() {
+        let program = lower(
+            "define i1 @is_null(ptr %p) {\n\
+             \x20 %c = icmp eq ptr %p, null\n\
+             \x20 ret i1 %c\n\
+             }\n\
+             define ptr @min(ptr %a, ptr %b) {\n\
+             \x20 %c = icmp ult ptr %a, %b\n\
+             \x20 %r = select i1 %c, ptr %a, ptr %b\n\
+             \x20 ret ptr %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u64_const<0>".to_owned()));
+        assert!(libfuncs.contains(&"u64_eq".to_owned()));
+        assert!(libfuncs.contains(&"u64_lt".to_owned()));
+        // The comparison of pointers isn't fused with the select, it matches on the `bool`.
+        assert!(libfuncs.contains(&"enum_match<bool>".to_owned()));
+        assert_eq!(run(&program, "is_null", &[0]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "is_null", &[16]), Ok(ints(&[0])));
+        assert_eq!(run(&program, "min", &[32, 16]), Ok(ints(&[16])));
+        assert_eq!(run(&program, "min", &[8, 16]), Ok(ints(&[8])));
+    }
+
+    #[test]
+    fn switch_table_range_of_wide_cases() {
+        let cases = |values: &[i128]| values.iter().map(|&v| BigInt::from(v)).collect::<Vec<_>>();
+        assert_eq!(
+            switch_table_range(&cases(&[3, 4, 6])),
+            Some((BigInt::from(3), 4))
+        );
+        assert_eq!(switch_table_range(&cases(&[0, 1])), None);
+        assert_eq!(switch_table_range(&cases(&[0, 1, 100])), None);
+        // `i64 -1` is `u64::MAX`, the range of `[0, u64::MAX]` doesn't fit in a `u64`.
+        assert_eq!(switch_table_range(&cases(&[0, 1, u64::MAX as i128])), None);
+        let top = BigInt::from(u128::MAX);
+        let wide = [&top - 2, &top - 1, top.clone()];
+        assert_eq!(switch_table_range(&wide), Some((&top - 2, 3)));
+        let huge = [BigInt::from(0), BigInt::from(1), BigInt::from(1) << 200];
+        assert_eq!(switch_table_range(&huge), None);
+    }
+
+    #[test]
+    fn switch_on_cases_wider_than_64_bits() {
+        let program = lower(
+            "define i32 @f(i128 %x) {\n\
+             start:\n\
+             \x20 switch i128 %x, label %other [\n\
+             \x20   i128 0, label %zero\n\
+             \x20   i128 -1, label %max\n\
+             \x20   i128 1267650600228229401496703205376, label %big\n\
+             \x20 ]\n\
+             zero:\n\
+             \x20 ret i32 1\n\
+             max:\n\
+             \x20 ret i32 2\n\
+             big:\n\
+             \x20 ret i32 3\n\
+             other:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&format!("u128_const<{}>", u128::MAX)));
+        assert!(libfuncs.contains(&"u128_const<1267650600228229401496703205376>".to_owned()));
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "u128_eq")
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn dense_switch_at_the_top_of_u128() {
+        let program = lower(
+            "define i32 @f(i128 %x) {\n\
+             start:\n\
+             \x20 switch i128 %x, label %other [\n\
+             \x20   i128 -1, label %a\n\
+             \x20   i128 -2, label %b\n\
+             \x20   i128 -3, label %a\n\
+             \x20 ]\n\
+             a:\n\
+             \x20 ret i32 1\n\
+             b:\n\
+             \x20 ret i32 2\n\
+             other:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&format!("u128_const<{}>", u128::MAX - 2)));
+        assert!(libfuncs.contains(&"enum_match<SwitchCases<3>>".to_owned()));
+    }
+
+    #[test]
+    fn integer_params_declare_core_types() {
+        let program = lower(
+            "define i32 @f(i32 %a, i256 %b) {\n\
+             \x20 ret i32 %a\n\
+             }\n",
+        )
+        .unwrap();
+        let types = program
+            .type_declarations
+            .iter()
+            .map(|declaration| declaration.id.to_string())
+            .collect::<Vec<_>>();
+        assert!(types.contains(&"u32".to_owned()));
+        assert!(types.contains(&"felt252".to_owned()));
+        assert!(!types.iter().any(|ty| ty.starts_with('i')));
+    }
+
+    #[test]
+    fn struct_declarations_take_the_type_info_of_their_fields() {
+        let mut builder = SierraBuilder::default();
+        builder.range_check_var();
+        builder.insert_type("u32".to_owned());
+        let info = builder.declared_type_info("RangeCheck");
+        assert!(!info.droppable && !info.duplicatable);
+        let plain = builder.insert_struct_type(vec!["u32".to_owned()]);
+        let info = builder.declared_type_info(&plain);
+        assert!(info.droppable && info.duplicatable && !info.zero_sized);
+        let with_builtin =
+            builder.insert_struct_type(vec!["u32".to_owned(), "RangeCheck".to_owned()]);
+        let info = builder.declared_type_info(&with_builtin);
+        assert!(!info.droppable && !info.duplicatable);
+    }
+
+    #[test]
+    fn range_check_of_a_division_is_not_droppable() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %q = udiv i32 %a, %b\n\
+             \x20 ret i32 %q\n\
+             }\n",
+        )
+        .unwrap();
+        let range_check = program
+            .type_declarations
+            .iter()
+            .find(|declaration| declaration.id.to_string() == "RangeCheck")
+            .unwrap();
+        let info = range_check.declared_type_info.as_ref().unwrap();
+        assert!(!info.droppable && !info.duplicatable);
+    }
+
+    #[test]
+    fn struct_read_twice_is_duplicated() {
+        let program = lower(
+            "define i32 @first({ i32, i32 } %s) {\n\
+             \x20 ret i32 7\n\
+             }\n\
+             define i32 @twice({ i32, i32 } %s) {\n\
+             \x20 %a = call i32 @first({ i32, i32 } %s)\n\
+             \x20 %b = call i32 @first({ i32, i32 } %s)\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"dup<Tuple<u32, u32>>".to_owned()));
+        assert!(!libfuncs
+            .iter()
+            .any(|libfunc| libfunc.starts_with("snapshot_take")));
+    }
+
+    #[test]
+    fn uncopyable_value_used_twice_is_reported() {
+        let err = lower(
+            "define i32 @first([2 x i32] %a) {\n\
+             \x20 ret i32 7\n\
+             }\n\
+             define i32 @twice([2 x i32] %a) {\n\
+             \x20 %x = call i32 @first([2 x i32] %a)\n\
+             \x20 %y = call i32 @first([2 x i32] %a)\n\
+             \x20 %r = add i32 %x, %y\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UncopyableValue { value, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(value, "%a");
+    }
+
+    #[test]
+    fn non_integer_const_is_reported() {
+        let mut builder = SierraBuilder::default();
+        builder.push_const_statement("u64".to_owned(), "0x3FF0000000000000".to_owned(), var(0));
+        assert_eq!(
+            builder.unsupported_const.as_deref(),
+            Some("0x3FF0000000000000")
+        );
+    }
+
+    #[test]
+    fn negative_const_operand_wraps() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %r = add i32 %x, -1\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&format!("u32_const<{}>", u32::MAX)));
+    }
+
+    #[test]
+    fn const_read_by_several_instructions_is_materialized_once() {
+        let program = lower(
+            "define i32 @f(i32 %x, i32 %y) {\n\
+             \x20 %a = add i32 %x, 5\n\
+             \x20 %b = add i32 %y, 5\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let consts = libfuncs(&program)
+            .into_iter()
+            .filter(|libfunc| libfunc == "u32_const<5>")
+            .count();
+        assert_eq!(consts, 1);
+        let args = [3, 4].map(BigInt::from);
+        assert_eq!(
+            crate::interpret::run(&program, "f", &args),
+            Ok(vec![BigInt::from(17)])
+        );
+    }
+
+    #[test]
+    fn const_read_by_a_comparison_its_select_and_a_call_is_materialized_once() {
+        let program = lower(
+            "define i32 @g(i32 %a, i32 %b) {\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @f(i32 %x, i32 %y) {\n\
+             \x20 %c = icmp ult i32 %x, 5\n\
+             \x20 %m = select i1 %c, i32 5, i32 %y\n\
+             \x20 %s = call i32 @g(i32 %m, i32 5)\n\
+             \x20 %r = add i32 %s, 5\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let consts = libfuncs(&program)
+            .into_iter()
+            .filter(|libfunc| libfunc == "u32_const<5>")
+            .count();
+        assert_eq!(consts, 1);
+        for (x, expected) in [(3, 15), (7, 19)] {
+            let args = [x, 9].map(BigInt::from);
+            assert_eq!(
+                crate::interpret::run(&program, "f", &args),
+                Ok(vec![BigInt::from(expected)])
+            );
+        }
+    }
+
+    #[test]
+    fn const_read_by_a_select_is_materialized_before_it_branches() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %c = icmp ult i32 %x, 3\n\
+             \x20 %a = add i32 %x, 7\n\
+             \x20 %m = select i1 %c, i32 7, i32 %a\n\
+             \x20 %r = add i32 %m, 7\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let consts = libfuncs(&program)
+            .into_iter()
+            .filter(|libfunc| libfunc == "u32_const<7>")
+            .count();
+        assert_eq!(consts, 1);
+        for (x, expected) in [(1, 14), (5, 19)] {
+            assert_eq!(
+                crate::interpret::run(&program, "f", &[BigInt::from(x)]),
+                Ok(vec![BigInt::from(expected)])
+            );
+        }
+    }
+
+    #[test]
+    fn const_switched_on_is_shared() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %a = add i32 %x, 2\n\
+             \x20 switch i32 2, label %other [ i32 2, label %two ]\n\
+             two:\n\
+             \x20 ret i32 %a\n\
+             other:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let consts = libfuncs(&program)
+            .into_iter()
+            .filter(|libfunc| libfunc == "u32_const<2>")
+            .count();
+        assert_eq!(consts, 1);
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(1)]),
+            Ok(vec![BigInt::from(3)])
+        );
+    }
+
+    #[test]
+    fn add_of_two_consts_is_a_single_const() {
+        let program = lower(
+            "define i32 @f() {\n\
+             \x20 %r = add i32 3, 4\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        let consts = libfuncs
+            .iter()
+            .filter(|libfunc| libfunc.starts_with("u32_const"))
+            .collect::<Vec<_>>();
+        assert_eq!(consts, ["u32_const<7>"]);
+        assert!(!libfuncs.contains(&"u32_overflowing_add".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[]),
+            Ok(vec![BigInt::from(7)])
+        );
+    }
+
+    #[test]
+    fn consts_whose_result_overflows_are_computed_at_runtime() {
+        let program = lower(
+            "define i8 @add() {\n\
+             \x20 %r = add i8 200, 100\n\
+             \x20 ret i8 %r\n\
+             }\n\
+             define i8 @sub() {\n\
+             \x20 %r = sub i8 1, 2\n\
+             \x20 ret i8 %r\n\
+             }\n\
+             define i128 @wide() {\n\
+             \x20 %r = add i128 1, 2\n\
+             \x20 ret i128 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u8_overflowing_add".to_owned()));
+        assert!(libfuncs.contains(&"u8_overflowing_sub".to_owned()));
+        assert!(libfuncs.contains(&"u128_const<3>".to_owned()));
+        let run = |function: &str| crate::interpret::run(&program, function, &[]);
+        assert_eq!(run("add"), Ok(vec![BigInt::from(44)]));
+        assert_eq!(run("sub"), Ok(vec![BigInt::from(255)]));
+        assert_eq!(run("wide"), Ok(vec![BigInt::from(3)]));
+    }
+
+    #[test]
+    fn const_above_i128_max_is_a_felt252() {
+        let program = lower(
+            "define i256 @f() {\n\
+             \x20 ret i256 340282366920938463463374607431768211457\n\
+             }\n",
+        )
+        .unwrap();
+        let value = (BigInt::from(1) << 128) + 1;
+        assert!(libfuncs(&program).contains(&format!("felt252_const<{}>", value)));
+    }
+
+    #[test]
+    fn hexadecimal_int_literal() {
+        let program = lower(
+            "define i32 @f() {\n\
+             \x20 ret i32 u0x10\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_const<16>".to_owned()));
+    }
+
+    #[test]
+    fn sub_wraps_below_zero() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %d = sub i32 %a, %b\n\
+             \x20 ret i32 %d\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_overflowing_sub".to_owned()));
+        let run = |a: u32, b: u32| crate::interpret::run(&program, "f", &[a, b].map(BigInt::from));
+        assert_eq!(run(10, 3), Ok(vec![BigInt::from(7)]));
+        assert_eq!(run(3, 10), Ok(vec![BigInt::from(u32::MAX - 6)]));
+    }
+
+    #[test]
+    fn function_signature_has_its_params() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let [function] = &program.funcs[..] else {
+            panic!("expected a single function, got {}", program.funcs.len());
+        };
+        assert_eq!(function.id.to_string(), "f");
+        assert_eq!(function.entry_point, StatementIdx(0));
+        let param_types = function
+            .signature
+            .param_types
+            .iter()
+            .map(|ty| ty.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(param_types, ["RangeCheck", "u32", "u32"]);
+        assert_eq!(function.params.len(), 3);
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[2, 3].map(BigInt::from)),
+            Ok(vec![BigInt::from(5)])
+        );
+    }
+
+    #[test]
+    fn functions_lower_independently() {
+        let program = lower(
+            "define i32 @inc(i32 %x) {\n\
+             \x20 %r = add i32 %x, 1\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @dec(i32 %y) {\n\
+             \x20 %r = sub i32 %y, 1\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let entries = program
+            .funcs
+            .iter()
+            .map(|function| function.entry_point.0)
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], 0);
+        // Each body ends with its own return, right before the next one starts.
+        let libfuncs = libfuncs(&program);
+        assert_eq!(libfuncs[entries[1] - 1], "return");
+        assert_eq!(libfuncs.last().unwrap(), "return");
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "return")
+                .count(),
+            2
+        );
+        let five = [BigInt::from(5)];
+        assert_eq!(
+            crate::interpret::run(&program, "inc", &five),
+            Ok(vec![BigInt::from(6)])
+        );
+        assert_eq!(
+            crate::interpret::run(&program, "dec", &five),
+            Ok(vec![BigInt::from(4)])
+        );
+    }
+
+    #[test]
+    fn call_returns_the_result_of_the_callee() {
+        let program = lower(
+            "define void @nop() {\n\
+             \x20 ret void\n\
+             }\n\
+             define i32 @helper(i32 %x) {\n\
+             \x20 %r = add i32 %x, 1\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @main(i32 %a) {\n\
+             \x20 call void @nop()\n\
+             \x20 %c = call i32 @helper(i32 %a)\n\
+             \x20 ret i32 %c\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"function_call<user@nop>".to_owned()));
+        assert!(libfuncs.contains(&"function_call<user@helper>".to_owned()));
+        // The range check of the helper is passed through `main`.
+        let main = program
+            .funcs
+            .iter()
+            .find(|function| function.id.to_string() == "main");
+        assert_eq!(
+            main.unwrap().signature.param_types[0].to_string(),
+            "RangeCheck"
+        );
+        assert_eq!(
+            crate::interpret::run(&program, "main", &[BigInt::from(41)]),
+            Ok(vec![BigInt::from(42)])
+        );
+    }
+
+    #[test]
+    fn phi_merge_jumps_land_on_the_merge_block() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 %a = add i32 %x, 100\n\
+             \x20 br label %merge\n\
+             big:\n\
+             \x20 %b = sub i32 %x, 10\n\
+             \x20 br label %merge\n\
+             merge:\n\
+             \x20 %r = phi i32 [ %a, %small ], [ %b, %big ]\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        let jumps = (0..libfuncs.len())
+            .filter(|idx| libfuncs[*idx] == "jump")
+            .collect::<Vec<_>>();
+        let jump_targets = jumps
+            .iter()
+            .map(|idx| targets(&program, *idx)[0].unwrap())
+            .collect::<Vec<_>>();
+        // Both arms jump to the merge block, the last one, past the stores of the phi.
+        let merge = jump_targets[0];
+        assert!(jump_targets.iter().all(|target| *target == merge));
+        assert!(jumps.iter().all(|idx| *idx < merge));
+        assert!(!libfuncs[merge..].contains(&"jump".to_owned()));
+        assert_eq!(libfuncs.last().unwrap(), "return");
+        let run = |x: u32| crate::interpret::run(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(103)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(10)]));
+    }
+
+    #[test]
+    fn variable_named_jump_target_keeps_its_branches() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %jump_target = add i32 %x, 1\n\
+             \x20 %c = icmp ult i32 %jump_target, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 ret i32 %jump_target\n\
+             big:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let named = program
+            .statements
+            .iter()
+            .enumerate()
+            .filter(|(_, statement)| statement.to_string().contains("jump_target"))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        assert!(!named.is_empty());
+        let libfuncs = libfuncs(&program);
+        assert!(named.iter().all(|idx| libfuncs[*idx] != "jump"));
+        let run = |x: u32| crate::interpret::run(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(4)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn truncated_value_is_compared() {
+        let program = lower(
+            "define i32 @f(i64 %x) {\n\
+             \x20 %t = trunc i64 %x to i32\n\
+             \x20 %c = icmp ult i32 %t, 10\n\
+             \x20 %r = zext i1 %c to i32\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"downcast<u64, u32>".to_owned()));
+        let run = |x: u64| crate::interpret::run(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(1)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(0)]));
+        // The high bits are dropped before the comparison.
+        assert_eq!(run((1 << 32) + 3), Ok(vec![BigInt::from(1)]));
+    }
+
+    #[test]
+    fn sparse_switch_compares_each_case() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 switch i32 %x, label %other [ i32 1, label %one\n\
+             \x20                               i32 10, label %ten\n\
+             \x20                               i32 100, label %hundred ]\n\
+             one:\n\
+             \x20 ret i32 11\n\
+             ten:\n\
+             \x20 ret i32 22\n\
+             hundred:\n\
+             \x20 ret i32 33\n\
+             other:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "u32_eq")
+                .count(),
+            3
+        );
+        for (x, expected) in [(1, 11), (10, 22), (100, 33), (5, 0)] {
+            assert_eq!(
+                crate::interpret::run_linear(&program, "f", &[BigInt::from(x)]),
+                Ok(vec![BigInt::from(expected)])
+            );
+        }
+    }
+
+    #[test]
+    fn udiv_by_a_runtime_zero_gives_zero() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %q = udiv i32 %a, %b\n\
+             \x20 ret i32 %q\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_is_zero".to_owned()));
+        let run = |a: u32, b: u32| crate::interpret::run(&program, "f", &[a, b].map(BigInt::from));
+        assert_eq!(run(7, 2), Ok(vec![BigInt::from(3)]));
+        assert_eq!(run(7, 0), Ok(vec![BigInt::from(0)]));
+    }
+
+    /// Module with `@value` and `@overflows` returning the fields of the result of the signed overflow intrinsic
+    /// `llvm.<op>.with.overflow.<ty>`, the overflow flag as `1` or `0`.
+    fn signed_overflow_module(op: &str, ty: &str) -> String {
+        let intrinsic = format!("@llvm.{}.with.overflow.{}", op, ty);
+        format!(
+            "declare {{ {ty}, i1 }} {intrinsic}({ty}, {ty})\n\
+             define {ty} @value({ty} %a, {ty} %b) {{\n\
+             \x20 %r = call {{ {ty}, i1 }} {intrinsic}({ty} %a, {ty} %b)\n\
+             \x20 %v = extractvalue {{ {ty}, i1 }} %r, 0\n\
+             \x20 ret {ty} %v\n\
+             }}\n\
+             define {ty} @overflows({ty} %a, {ty} %b) {{\n\
+             \x20 %r = call {{ {ty}, i1 }} {intrinsic}({ty} %a, {ty} %b)\n\
+             \x20 %o = extractvalue {{ {ty}, i1 }} %r, 1\n\
+             \x20 %f = select i1 %o, {ty} 1, {ty} 0\n\
+             \x20 ret {ty} %f\n\
+             }}\n",
+        )
+    }
+
+    #[test]
+    fn signed_mul_with_overflow_gives_the_wrapped_product_and_the_flag() {
+        let program = lower(&signed_overflow_module("smul", "i32")).unwrap();
+        let minus = |value: i64| BigInt::from(value) + (BigInt::from(1) << 32);
+        let run = |function: &str, a: BigInt, b: BigInt| {
+            crate::interpret::run(&program, function, &[a, b]).map(|results| results[0].clone())
+        };
+        // (a, b, wrapped product, overflows)
+        let cases = [
+            (BigInt::from(3), minus(-4), minus(-12), 0),
+            (BigInt::from(65536), BigInt::from(65536), BigInt::from(0), 1),
+            (
+                minus(-65536),
+                BigInt::from(32768),
+                BigInt::from(1u64 << 31),
+                0,
+            ),
+            (minus(-65536), BigInt::from(32769), minus(-65536 * 32769), 1),
+        ];
+        for (a, b, product, overflows) in cases {
+            assert_eq!(run("value", a.clone(), b.clone()), Ok(product));
+            assert_eq!(run("overflows", a, b), Ok(BigInt::from(overflows)));
+        }
+    }
+
+    #[test]
+    fn signed_add_and_sub_with_overflow_flag_the_results_out_of_the_signed_range() {
+        let program = lower(&signed_overflow_module("sadd", "i8")).unwrap();
+        let run = |function: &str, a: u8, b: u8| {
+            crate::interpret::run(&program, function, &[a, b].map(BigInt::from))
+        };
+        // 100 + 27 = 127, 100 + 28 = -128 and -128 + -1 = 127.
+        assert_eq!(run("value", 100, 27), Ok(vec![BigInt::from(127)]));
+        assert_eq!(run("overflows", 100, 27), Ok(vec![BigInt::from(0)]));
+        assert_eq!(run("value", 100, 28), Ok(vec![BigInt::from(128)]));
+        assert_eq!(run("overflows", 100, 28), Ok(vec![BigInt::from(1)]));
+        assert_eq!(run("overflows", 128, 255), Ok(vec![BigInt::from(1)]));
+        assert_eq!(run("overflows", 255, 255), Ok(vec![BigInt::from(0)]));
+
+        let program = lower(&signed_overflow_module("ssub", "i8")).unwrap();
+        let run = |function: &str, a: u8, b: u8| {
+            crate::interpret::run(&program, function, &[a, b].map(BigInt::from))
+        };
+        // -128 - 1 = 127 and -1 - 127 = -128.
+        assert_eq!(run("value", 128, 1), Ok(vec![BigInt::from(127)]));
+        assert_eq!(run("overflows", 128, 1), Ok(vec![BigInt::from(1)]));
+        assert_eq!(run("value", 255, 127), Ok(vec![BigInt::from(128)]));
+        assert_eq!(run("overflows", 255, 127), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn signed_mul_with_overflow_of_i128_is_unsupported() {
+        let err = lower(&signed_overflow_module("smul", "i128")).unwrap_err();
+        let LoweringError::UnsupportedOpcode { opcode, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(opcode, InstructionOpcode::Call);
+    }
+
+    #[test]
+    fn sdiv_and_srem_round_toward_zero() {
+        let program = lower(
+            "define i32 @div(i32 %a, i32 %b) {\n\
+             \x20 %q = sdiv i32 %a, %b\n\
+             \x20 ret i32 %q\n\
+             }\n\
+             define i32 @rem(i32 %a, i32 %b) {\n\
+             \x20 %r = srem i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let signed = |value: i32| BigInt::from(value as u32);
+        let run = |function: &str, a: i32, b: i32| {
+            crate::interpret::run(&program, function, &[signed(a), signed(b)])
+        };
+        // (a, b, a / b, a % b)
+        for (a, b, quotient, remainder) in [
+            (7, 2, 3, 1),
+            (-7, 2, -3, -1),
+            (7, -2, -3, 1),
+            (-7, -2, 3, -1),
+            (i32::MIN, 1, i32::MIN, 0),
+            (7, 0, 0, 0),
+        ] {
+            assert_eq!(run("div", a, b), Ok(vec![signed(quotient)]));
+            assert_eq!(run("rem", a, b), Ok(vec![signed(remainder)]));
+        }
+    }
+
+    #[test]
+    fn sdiv_and_srem_of_the_same_operands_share_the_division() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %q = sdiv i32 %a, %b\n\
+             \x20 %r = srem i32 %a, %b\n\
+             \x20 %s = add i32 %q, %r\n\
+             \x20 ret i32 %s\n\
+             }\n",
+        )
+        .unwrap();
+        let divisions = libfuncs(&program)
+            .into_iter()
+            .filter(|libfunc| libfunc == "u32_safe_divmod")
+            .count();
+        assert_eq!(divisions, 1);
+        let minus = |value: i64| BigInt::from(value) + (BigInt::from(1) << 32);
+        // -7 / 2 + -7 % 2 = -3 + -1
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[minus(-7), BigInt::from(2)]),
+            Ok(vec![minus(-4)])
+        );
+    }
+
+    #[test]
+    fn sdiv_by_a_const_has_no_zero_check() {
+        let program = lower(
+            "define i32 @f(i32 %a) {\n\
+             \x20 %q = sdiv i32 %a, -4\n\
+             \x20 ret i32 %q\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(!libfuncs(&program).contains(&"u32_is_zero".to_owned()));
+        let minus = |value: i64| BigInt::from(value) + (BigInt::from(1) << 32);
+        let run = |a: BigInt| crate::interpret::run(&program, "f", &[a]);
+        assert_eq!(run(minus(-9)), Ok(vec![BigInt::from(2)]));
+        assert_eq!(run(BigInt::from(9)), Ok(vec![minus(-2)]));
+    }
+
+    #[test]
+    fn sdiv_of_a_narrow_integer_is_unsupported() {
+        let err = lower(
+            "define i3 @f(i3 %a, i3 %b) {\n\
+             \x20 %q = sdiv i3 %a, %b\n\
+             \x20 ret i3 %q\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UnsupportedOpcode { opcode, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(opcode, InstructionOpcode::SDiv);
+    }
+
+    #[test]
+    fn unsupported_instruction_is_kept_as_a_placeholder_read_by_its_users() {
+        let config = Config {
+            keep_unsupported_as_nop: true,
+            ..Config::default()
+        };
+        let source = "define i3 @f(i3 %a, i3 %b) {\n\
+                      \x20 %q = sdiv i3 %a, %b\n\
+                      \x20 %r = add i3 %q, %b\n\
+                      \x20 ret i3 %r\n\
+                      }\n";
+        let program =
+            SierraBuilder::compile_source(source.as_bytes(), Path::new("test.ll"), &config)
+                .unwrap();
+        let invocations = program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                GenStatement::Invocation(invocation) => Some(invocation),
+                GenStatement::Return(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let placeholder = invocations
+            .iter()
+            .find(|invocation| invocation.libfunc_id.to_string() == "unsupported_sdiv")
+            .expect("no placeholder for the sdiv");
+        let quotient = &placeholder.branches[0].results[0];
+        assert!(invocations.iter().any(|invocation| {
+            invocation.libfunc_id.to_string() == "u8_overflowing_add"
+                && invocation.args.contains(quotient)
+        }));
+    }
+
+    #[test]
+    fn mul_wraps_at_the_operand_width() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %p = mul i32 %a, %b\n\
+             \x20 ret i32 %p\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_wide_mul".to_owned()));
+        let run = |a: u32, b: u32| crate::interpret::run(&program, "f", &[a, b].map(BigInt::from));
+        assert_eq!(run(6, 7), Ok(vec![BigInt::from(42)]));
+        assert_eq!(run(1 << 31, 2), Ok(vec![BigInt::from(0)]));
+        assert_eq!(run(u32::MAX, 3), Ok(vec![BigInt::from(u32::MAX - 2)]));
+    }
+
+    #[test]
+    fn bitwise_ops_of_narrow_integers() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %and = and i32 %a, %b\n\
+             \x20 %or = or i32 %a, %b\n\
+             \x20 %r = xor i32 %and, %or\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "bitwise")
+                .count(),
+            3
+        );
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[12, 10].map(BigInt::from)),
+            Ok(vec![BigInt::from(6)])
+        );
+    }
+
+    #[test]
+    fn shift_by_a_runtime_amount_loops_over_it() {
+        let config = Config {
+            dynamic_shifts: true,
+            ..Config::default()
+        };
+        let source = "define i32 @f(i32 %x, i32 %n) {\n\
+                      \x20 %r = shl i32 %x, %n\n\
+                      \x20 ret i32 %r\n\
+                      }\n\
+                      define i32 @g(i32 %n) {\n\
+                      \x20 %r = shl i32 3, %n\n\
+                      \x20 ret i32 %r\n\
+                      }\n";
+        let program =
+            SierraBuilder::compile_source(source.as_bytes(), Path::new("test.ll"), &config)
+                .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_is_zero".to_owned()));
+        let run = |function: &str, args: &[u32]| {
+            let args = args.iter().copied().map(BigInt::from).collect::<Vec<_>>();
+            crate::interpret::run(&program, function, &args)
+        };
+        assert_eq!(run("f", &[3, 4]), Ok(vec![BigInt::from(48)]));
+        assert_eq!(run("f", &[5, 0]), Ok(vec![BigInt::from(5)]));
+        assert_eq!(run("f", &[3, 31]), Ok(vec![BigInt::from(1u64 << 31)]));
+        assert_eq!(run("g", &[2]), Ok(vec![BigInt::from(12)]));
+    }
+
+    #[test]
+    fn shifts_by_a_const_amount() {
+        let program = lower(
+            "define i32 @shl(i32 %x) {\n\
+             \x20 %r = shl i32 %x, 3\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @lshr(i32 %x) {\n\
+             \x20 %r = lshr i32 %x, 2\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @ashr(i32 %x) {\n\
+             \x20 %r = ashr i32 %x, 1\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let run = |name: &str, x: u32| crate::interpret::run(&program, name, &[BigInt::from(x)]);
+        assert_eq!(run("shl", 5), Ok(vec![BigInt::from(40)]));
+        assert_eq!(run("shl", 1 << 31), Ok(vec![BigInt::from(0)]));
+        assert_eq!(run("lshr", 40), Ok(vec![BigInt::from(10)]));
+        assert_eq!(run("ashr", 40), Ok(vec![BigInt::from(20)]));
+        // `-8 >> 1` is `-4`, the sign bit is kept.
+        assert_eq!(
+            run("ashr", 0u32.wrapping_sub(8)),
+            Ok(vec![BigInt::from(0u32.wrapping_sub(4))])
+        );
+    }
+
+    #[test]
+    fn unconditional_branch_jumps_over_the_next_block() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 br label %end\n\
+             skipped:\n\
+             \x20 ret i32 1\n\
+             end:\n\
+             \x20 ret i32 %x\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(libfuncs[0], "jump");
+        let [Some(end)] = targets(&program, 0)[..] else {
+            panic!("the jump has no target");
+        };
+        assert!(!libfuncs[end..].contains(&"u32_const<1>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(5)]),
+            Ok(vec![BigInt::from(5)])
+        );
+    }
+
+    #[test]
+    fn instruction_without_a_lowering_is_reported() {
+        let err = lower(
+            "define i32 @f() {\n\
+             \x20 %i = fptoui double 1.5 to i32\n\
+             \x20 ret i32 %i\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UnsupportedOpcode { opcode, span, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(opcode, InstructionOpcode::FPToUI);
+        assert_eq!(span.function, "f");
+    }
+
+    #[test]
+    fn struct_with_an_unset_field_is_reported() {
+        let err = lower(
+            "define { i32, i32 } @f(i32 %x) {\n\
+             \x20 %s = insertvalue { i32, i32 } undef, i32 %x, 0\n\
+             \x20 ret { i32, i32 } %s\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UnsetStructField { value, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(value, "%s");
+    }
+
+    #[test]
+    fn nested_insertvalue_is_reported() {
+        let err = lower(
+            "define { { i32 }, i32 } @f(i32 %x) {\n\
+             \x20 %a = insertvalue { { i32 }, i32 } undef, i32 %x, 0, 0\n\
+             \x20 %s = insertvalue { { i32 }, i32 } %a, i32 %x, 1\n\
+             \x20 ret { { i32 }, i32 } %s\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UnsupportedOpcode {
+            opcode,
+            instruction,
+            ..
+        } = err
+        else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(opcode, InstructionOpcode::InsertValue);
+        assert!(instruction.contains("%a = insertvalue"));
+    }
+
+    #[test]
+    fn unused_result_is_dropped() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %unused = add i32 %x, 1\n\
+             \x20 ret i32 %x\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"drop<u32>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(5)]),
+            Ok(vec![BigInt::from(5)])
+        );
+    }
+
+    #[test]
+    fn value_read_twice_is_duplicated() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %r = add i32 %x, %x\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"dup<u32>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(3)]),
+            Ok(vec![BigInt::from(6)])
+        );
+    }
+
+    #[test]
+    fn select_on_a_comparison_with_a_const_clamps() {
+        let program = lower(
+            "define i64 @f(i64 %x) {\n\
+             \x20 %c = icmp ugt i64 %x, 100\n\
+             \x20 %r = select i1 %c, i64 100, i64 %x\n\
+             \x20 ret i64 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u64_lt".to_owned()));
+        assert!(!libfuncs.contains(&"enum_match<bool>".to_owned()));
+        let run = |x: u64| crate::interpret::run(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(7), Ok(vec![BigInt::from(7)]));
+        assert_eq!(run(100), Ok(vec![BigInt::from(100)]));
+        assert_eq!(run(u64::MAX), Ok(vec![BigInt::from(100)]));
+    }
+
+    #[test]
+    fn select_on_a_runtime_bool() {
+        // The comparison is read again by the `zext`, it isn't fused with the select.
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %c = icmp ult i32 %a, %b\n\
+             \x20 %s = select i1 %c, i32 %a, i32 %b\n\
+             \x20 %z = zext i1 %c to i32\n\
+             \x20 %r = add i32 %s, %z\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"enum_match<bool>".to_owned()));
+        let run = |a: u32, b: u32| crate::interpret::run(&program, "f", &[a, b].map(BigInt::from));
+        assert_eq!(run(1, 5), Ok(vec![BigInt::from(2)]));
+        assert_eq!(run(7, 5), Ok(vec![BigInt::from(5)]));
+    }
+
+    #[test]
+    fn unreachable_returns_a_zero() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 ret i32 %x\n\
+             big:\n\
+             \x20 unreachable\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "return")
+                .count(),
+            2
+        );
+        let run = |x: u32| crate::interpret::run_linear(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(3)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn unreachable_drops_the_values_read_later() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %y = add i32 %x, 1\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             big:\n\
+             \x20 unreachable\n\
+             small:\n\
+             \x20 ret i32 %y\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"drop<u32>".to_owned()));
+        let run = |x: u32| crate::interpret::run_linear(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(4)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn comparison_branches_land_on_their_branch_align() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %c = icmp slt i32 %a, %b\n\
+             \x20 %d = icmp ne i32 %a, 7\n\
+             \x20 %both = and i1 %c, %d\n\
+             \x20 %r = zext i1 %both to i32\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        for statement in &program.statements {
+            let GenStatement::Invocation(invocation) = statement else {
+                continue;
+            };
+            if invocation.branches.len() < 2 {
+                continue;
+            }
+            for branch in &invocation.branches {
+                let GenBranchTarget::Statement(StatementIdx(target)) = branch.target else {
+                    continue;
+                };
+                let GenStatement::Invocation(landing) = &program.statements[target] else {
+                    panic!("statement {} is a return", target);
+                };
+                assert_eq!(landing.libfunc_id.to_string(), "branch_align");
+            }
+        }
+        let run = |a: u32, b: u32| crate::interpret::run(&program, "f", &[a, b].map(BigInt::from));
+        assert_eq!(run(1, 2), Ok(vec![BigInt::from(1)]));
+        assert_eq!(run(7, 8), Ok(vec![BigInt::from(0)]));
+        assert_eq!(run(3, 2), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn every_icmp_predicate() {
+        let expected = |predicate: &str, a: u32, b: u32| match predicate {
+            "eq" => a == b,
+            "ne" => a != b,
+            "ult" => a < b,
+            "ule" => a <= b,
+            "ugt" => a > b,
+            "uge" => a >= b,
+            "slt" => (a as i32) < (b as i32),
+            "sle" => (a as i32) <= (b as i32),
+            "sgt" => (a as i32) > (b as i32),
+            _ => (a as i32) >= (b as i32),
+        };
+        let predicates = [
+            "eq", "ne", "ult", "ule", "ugt", "uge", "slt", "sle", "sgt", "sge",
+        ];
+        for predicate in predicates {
+            let program = lower(&format!(
+                "define i32 @f(i32 %a, i32 %b) {{\n\
+                 \x20 %c = icmp {} i32 %a, %b\n\
+                 \x20 %r = zext i1 %c to i32\n\
+                 \x20 ret i32 %r\n\
+                 }}\n",
+                predicate
+            ))
+            .unwrap();
+            for (a, b) in [(1, 2), (2, 2), (2, 1), (u32::MAX, 1)] {
+                assert_eq!(
+                    crate::interpret::run(&program, "f", &[a, b].map(BigInt::from)),
+                    Ok(vec![BigInt::from(expected(predicate, a, b) as u8)]),
+                    "icmp {} {} {}",
+                    predicate,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn local_loaded_twice_gets_a_copy_per_load() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %p = alloca i32\n\
+             \x20 store i32 %x, ptr %p\n\
+             \x20 %a = load i32, ptr %p\n\
+             \x20 %b = load i32, ptr %p\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "dup<u32>")
+                .count(),
+            2
+        );
+        assert!(libfuncs.contains(&"drop<u32>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[BigInt::from(4)]),
+            Ok(vec![BigInt::from(8)])
+        );
+    }
+
+    #[test]
+    fn bool_local_loaded_twice() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %p = alloca i1\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 store i1 %c, ptr %p\n\
+             \x20 %a = load i1, ptr %p\n\
+             \x20 %b = load i1, ptr %p\n\
+             \x20 %za = zext i1 %a to i32\n\
+             \x20 %zb = zext i1 %b to i32\n\
+             \x20 %r = add i32 %za, %zb\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let run = |x: u32| crate::interpret::run(&program, "f", &[BigInt::from(x)]);
+        assert_eq!(run(3), Ok(vec![BigInt::from(2)]));
+        assert_eq!(run(20), Ok(vec![BigInt::from(0)]));
+    }
+
+    #[test]
+    fn load_of_an_uncopyable_local_is_reported() {
+        let err = lower(
+            "define i32 @f([2 x i32] %a) {\n\
+             \x20 %p = alloca { [2 x i32] }\n\
+             \x20 %s = insertvalue { [2 x i32] } undef, [2 x i32] %a, 0\n\
+             \x20 store { [2 x i32] } %s, ptr %p\n\
+             \x20 %l = load { [2 x i32] }, ptr %p\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UncopyableValue { value, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(value, "%p");
+    }
+
+    #[test]
+    fn local_loaded_before_being_stored_reads_zero() {
+        let program = lower(
+            "define i32 @f() {\n\
+             \x20 %x = alloca i32\n\
+             \x20 %v = load i32, ptr %x\n\
+             \x20 ret i32 %v\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_const<0>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[]),
+            Ok(vec![BigInt::from(0)])
+        );
+    }
+
+    #[test]
+    fn struct_local_loaded_before_being_stored_reads_zeros() {
+        let program = lower(
+            "define i32 @f() {\n\
+             \x20 %s = alloca { i32, i1 }\n\
+             \x20 %p = getelementptr { i32, i1 }, ptr %s, i32 0, i32 0\n\
+             \x20 %v = load i32, ptr %p\n\
+             \x20 ret i32 %v\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"struct_construct<Tuple<u32, bool>>".to_owned()));
+        assert!(libfuncs.contains(&"u32_const<0>".to_owned()));
+        assert_eq!(
+            crate::interpret::run(&program, "f", &[]),
+            Ok(vec![BigInt::from(0)])
+        );
+    }
+
+    #[test]
+    fn local_without_a_default_loaded_before_being_stored_is_reported() {
+        let err = lower(
+            "define { [2 x i32] } @f() {\n\
+             \x20 %s = alloca { [2 x i32] }\n\
+             \x20 %v = load { [2 x i32] }, ptr %s\n\
+             \x20 ret { [2 x i32] } %v\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UninitializedLocal { local, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(local, "%s");
+    }
+
+    #[test]
+    fn vector_element_ops_are_unsupported() {
+        let err = lower(
+            "define i32 @f(<4 x i32> %v) {\n\
+             \x20 %e = extractelement <4 x i32> %v, i32 1\n\
+             \x20 ret i32 %e\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::UnsupportedOpcode { opcode, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(opcode, InstructionOpcode::ExtractElement);
+    }
+
+    #[test]
+    fn u128_const_above_i128_max_keeps_its_value() {
+        let program = lower(
+            "define i128 @f(i128 %a) {\n\
+             \x20 %r = add i128 %a, -1\n\
+             \x20 ret i128 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&format!("u128_const<{}>", u128::MAX)));
+        assert_eq!(run(&program, "f", &[0]), Ok(ints(&[u128::MAX])));
+        assert_eq!(run(&program, "f", &[5]), Ok(ints(&[4])));
+    }
+
+    #[test]
+    fn struct_passed_by_value_is_constructed_before_the_call() {
+        let program = lower(
+            "define { i32, i32 } @id({ i32, i32 } %s) {\n\
+             \x20 ret { i32, i32 } %s\n\
+             }\n\
+             define { i32, i32 } @f(i32 %x, i32 %y) {\n\
+             \x20 %z = add i32 %y, 1\n\
+             \x20 %a = insertvalue { i32, i32 } undef, i32 %x, 0\n\
+             \x20 %s = insertvalue { i32, i32 } %a, i32 %z, 1\n\
+             \x20 %r = call { i32, i32 } @id({ i32, i32 } %s)\n\
+             \x20 ret { i32, i32 } %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        let construct = libfuncs
+            .iter()
+            .position(|libfunc| libfunc == "struct_construct<Tuple<u32, u32>>")
+            .unwrap();
+        let call = libfuncs
+            .iter()
+            .position(|libfunc| libfunc == "function_call<user@id>")
+            .unwrap();
+        assert!(construct < call);
+        assert_eq!(run(&program, "f", &[3, 4]), Ok(ints(&[3, 5])));
+    }
+
+    #[test]
+    fn block_ends_with_a_terminator_once_it_leaves() {
+        let mut builder = SierraBuilder::default();
+        builder.push_bool_to_int("u32", var(0), var(1), BigInt::from(1));
+        assert!(!builder.ends_with_terminator(0));
+        let block_start = builder.sierra.program.statements.len();
+        assert!(!builder.ends_with_terminator(block_start));
+        builder.push_jump(0);
+        assert!(builder.ends_with_terminator(0));
+        assert!(builder.ends_with_terminator(block_start));
+    }
+
+    #[test]
+    fn every_block_of_a_loop_ends_with_a_terminator() {
+        let program = lower(
+            "define i32 @sum(i32 %n) {\n\
+             entry:\n\
+             \x20 br label %header\n\
+             header:\n\
+             \x20 %i = phi i32 [ %n, %entry ], [ %next, %body ]\n\
+             \x20 %acc = phi i32 [ 0, %entry ], [ %added, %body ]\n\
+             \x20 %done = icmp eq i32 %i, 0\n\
+             \x20 br i1 %done, label %exit, label %body\n\
+             body:\n\
+             \x20 %added = add i32 %acc, %i\n\
+             \x20 %next = sub i32 %i, 1\n\
+             \x20 br label %header\n\
+             exit:\n\
+             \x20 ret i32 %acc\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(run(&program, "sum", &[0]), Ok(ints(&[0])));
+        assert_eq!(run(&program, "sum", &[4]), Ok(ints(&[10])));
+    }
+
+    #[test]
+    fn zero_offset_gep_aliases_its_base() {
+        let program = lower(
+            "define ptr @f(ptr %p) {\n\
+             \x20 %q = getelementptr i32, ptr %p, i32 0\n\
+             \x20 %r = getelementptr { i32, i32 }, ptr %q, i64 0, i32 0\n\
+             \x20 ret ptr %r\n\
+             }\n",
+        )
+        .unwrap();
+        let forwarded = lower(
+            "define ptr @f(ptr %p) {\n\
+             \x20 ret ptr %p\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(libfuncs(&program), libfuncs(&forwarded));
+        assert_eq!(run(&program, "f", &[1234]), Ok(ints(&[1234])));
+    }
+
+    #[test]
+    fn pointer_param_is_forwarded_to_a_call() {
+        let program = lower(
+            "define i1 @is_null(ptr %p) {\n\
+             \x20 %c = icmp eq ptr %p, null\n\
+             \x20 ret i1 %c\n\
+             }\n\
+             define i1 @wrapper(ptr %p) {\n\
+             \x20 %r = call i1 @is_null(ptr %p)\n\
+             \x20 ret i1 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let wrapper = program
+            .funcs
+            .iter()
+            .find(|func| func.id.to_string() == "wrapper")
+            .unwrap();
+        assert!(wrapper
+            .signature
+            .param_types
+            .contains(&ConcreteTypeId::from_string("u64")));
+        assert_eq!(run(&program, "wrapper", &[0]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "wrapper", &[8]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn chained_boolean_ands_and_ors() {
+        let program = lower(
+            "define i1 @all_small(i32 %a, i32 %b, i32 %c) {\n\
+             \x20 %x = icmp ult i32 %a, 10\n\
+             \x20 %y = icmp ult i32 %b, 10\n\
+             \x20 %z = icmp ult i32 %c, 10\n\
+             \x20 %xy = and i1 %x, %y\n\
+             \x20 %r = and i1 %xy, %z\n\
+             \x20 ret i1 %r\n\
+             }\n\
+             define i1 @any_small(i32 %a, i32 %b, i32 %c) {\n\
+             \x20 %x = icmp ult i32 %a, 10\n\
+             \x20 %y = icmp ult i32 %b, 10\n\
+             \x20 %z = icmp ult i32 %c, 10\n\
+             \x20 %xy = or i1 %x, %y\n\
+             \x20 %r = or i1 %xy, %z\n\
+             \x20 ret i1 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            libfuncs(&program)
+                .iter()
+                .filter(|libfunc| *libfunc == "bool_and_impl")
+                .count(),
+            2
+        );
+        assert_eq!(run(&program, "all_small", &[1, 2, 3]), Ok(ints(&[1])));
+        for args in [[10, 2, 3], [1, 20, 3], [1, 2, 30]] {
+            assert_eq!(run(&program, "all_small", &args), Ok(ints(&[0])));
+            assert_eq!(run(&program, "any_small", &args), Ok(ints(&[1])));
+        }
+        assert_eq!(run(&program, "any_small", &[10, 20, 30]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn value_of_the_entry_block_returned_by_a_successor() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             entry:\n\
+             \x20 %s = add i32 %a, %b\n\
+             \x20 %c = icmp ult i32 %a, %b\n\
+             \x20 br i1 %c, label %lt, label %ge\n\
+             lt:\n\
+             \x20 ret i32 %s\n\
+             ge:\n\
+             \x20 %m = mul i32 %s, 2\n\
+             \x20 ret i32 %m\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(run(&program, "f", &[1, 2]), Ok(ints(&[3])));
+        assert_eq!(run(&program, "f", &[5, 2]), Ok(ints(&[14])));
+    }
+
+    #[test]
+    fn add_of_i8_and_i16_wraps_at_their_width() {
+        let program = lower(
+            "define i8 @add8(i8 %a, i8 %b) {\n\
+             \x20 %r = add i8 %a, %b\n\
+             \x20 ret i8 %r\n\
+             }\n\
+             define i16 @add16(i16 %a, i16 %b) {\n\
+             \x20 %r = add i16 %a, %b\n\
+             \x20 ret i16 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u8_overflowing_add".to_owned()));
+        assert!(libfuncs.contains(&"u16_overflowing_add".to_owned()));
+        assert_eq!(run(&program, "add8", &[20, 30]), Ok(ints(&[50])));
+        assert_eq!(run(&program, "add8", &[200, 100]), Ok(ints(&[44])));
+        assert_eq!(run(&program, "add16", &[60000, 6000]), Ok(ints(&[464])));
+    }
+
+    #[test]
+    fn recursive_factorial_withdraws_gas() {
+        let program = lower(
+            "define i32 @fact(i32 %n) {\n\
+             entry:\n\
+             \x20 %z = icmp eq i32 %n, 0\n\
+             \x20 br i1 %z, label %base, label %rec\n\
+             base:\n\
+             \x20 ret i32 1\n\
+             rec:\n\
+             \x20 %m = sub i32 %n, 1\n\
+             \x20 %f = call i32 @fact(i32 %m)\n\
+             \x20 %r = mul i32 %n, %f\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"withdraw_gas".to_owned()));
+        assert!(libfuncs.contains(&"function_call<user@fact>".to_owned()));
+        assert_eq!(run(&program, "fact", &[0]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "fact", &[5]), Ok(ints(&[120])));
+    }
+
+    #[test]
+    fn call_to_a_function_defined_later() {
+        let program = lower(
+            "define i32 @caller(i32 %x) {\n\
+             \x20 %r = call i32 @triple(i32 %x)\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @triple(i32 %x) {\n\
+             \x20 %r = mul i32 %x, 3\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            program
+                .funcs
+                .iter()
+                .map(|func| func.id.to_string())
+                .collect::<Vec<_>>(),
+            ["caller", "triple"]
+        );
+        assert_eq!(run(&program, "caller", &[7]), Ok(ints(&[21])));
+    }
+
+    #[test]
+    fn phi_of_pointers_stores_their_address() {
+        let program = lower(
+            "define ptr @pick(ptr %a, ptr %b, i32 %x) {\n\
+             entry:\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 br label %done\n\
+             big:\n\
+             \x20 br label %done\n\
+             done:\n\
+             \x20 %p = phi ptr [ %a, %small ], [ %b, %big ]\n\
+             \x20 ret ptr %p\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"store_temp<u64>".to_owned()));
+        assert_eq!(run(&program, "pick", &[100, 200, 3]), Ok(ints(&[100])));
+        assert_eq!(run(&program, "pick", &[100, 200, 30]), Ok(ints(&[200])));
+    }
+
+    #[test]
+    fn call_result_is_returned_as_is() {
+        let program = lower(
+            "define i32 @g(i32 %x) {\n\
+             \x20 %r = add i32 %x, 1\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @f(i32 %x) {\n\
+             \x20 %r = call i32 @g(i32 %x)\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let call = program
+            .statements
+            .iter()
+            .find_map(|statement| match statement {
+                GenStatement::Invocation(invocation)
+                    if invocation.libfunc_id.to_string() == "function_call<user@g>" =>
+                {
+                    Some(invocation)
+                }
+                _ => None,
+            })
+            .unwrap();
+        let Some(GenStatement::Return(returned)) = program.statements.last() else {
+            panic!("`f` doesn't end with a return");
+        };
+        assert_eq!(returned.last(), call.branches[0].results.last());
+        assert!(!libfuncs(&program).contains(&"dup<u32>".to_owned()));
+        assert_eq!(run(&program, "f", &[41]), Ok(ints(&[42])));
+    }
+
+    #[test]
+    fn printf_of_integers_prints_them_as_felts() {
+        let program = lower(
+            "@fmt = private constant [7 x i8] c\"%d %u\\0A\\00\"\n\
+             declare i32 @printf(ptr, ...)\n\
+             define i32 @f(i32 %x) {\n\
+             \x20 %c = call i32 (ptr, ...) @printf(ptr @fmt, i32 %x, i32 7)\n\
+             \x20 %r = add i32 %x, 1\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert_eq!(
+            libfuncs
+                .iter()
+                .filter(|libfunc| *libfunc == "u32_to_felt252")
+                .count(),
+            2
+        );
+        assert!(libfuncs.contains(&"print".to_owned()));
+        assert_eq!(run(&program, "f", &[41]), Ok(ints(&[42])));
+    }
+
+    #[test]
+    fn blocks_reached_by_falling_through() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             entry:\n\
+             \x20 %a = add i32 %x, 1\n\
+             \x20 br label %next\n\
+             next:\n\
+             \x20 %b = mul i32 %a, 2\n\
+             \x20 br label %last\n\
+             last:\n\
+             \x20 %c = add i32 %b, 3\n\
+             \x20 ret i32 %c\n\
+             }\n",
+        )
+        .unwrap();
+        // The branches falling through emit nothing, the blocks lower like a single one.
+        let single_block = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %a = add i32 %x, 1\n\
+             \x20 %b = mul i32 %a, 2\n\
+             \x20 %c = add i32 %b, 3\n\
+             \x20 ret i32 %c\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(libfuncs(&program), libfuncs(&single_block));
+        assert_eq!(run(&program, "f", &[4]), Ok(ints(&[13])));
+    }
+
+    #[test]
+    fn u64_ops_use_the_libfuncs_of_their_type() {
+        let program = lower(
+            "define i64 @f(i64 %a, i64 %b) {\n\
+             \x20 %d = sub i64 %a, %b\n\
+             \x20 %c = icmp eq i64 %d, 3\n\
+             \x20 %r = select i1 %c, i64 %a, i64 %d\n\
+             \x20 ret i64 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u64_overflowing_sub".to_owned()));
+        assert!(libfuncs.contains(&"u64_eq".to_owned()));
+        assert_eq!(run(&program, "f", &[10, 7]), Ok(ints(&[10])));
+        assert_eq!(run(&program, "f", &[10, 2]), Ok(ints(&[8])));
+        assert_eq!(run(&program, "f", &[0, 1]), Ok(ints(&[u64::MAX as u128])));
+    }
+
+    #[test]
+    fn and_with_a_low_bits_mask_keeps_the_remainder() {
+        let program = lower(
+            "define i32 @low_byte(i32 %x) {\n\
+             \x20 %r = and i32 %x, 255\n\
+             \x20 ret i32 %r\n\
+             }\n\
+             define i32 @all_bits(i32 %x) {\n\
+             \x20 %r = and i32 %x, -1\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u32_safe_divmod".to_owned()));
+        assert!(!libfuncs.contains(&"bitwise".to_owned()));
+        assert_eq!(run(&program, "low_byte", &[0x1234]), Ok(ints(&[0x34])));
+        assert_eq!(run(&program, "low_byte", &[0xff]), Ok(ints(&[0xff])));
+        assert_eq!(
+            run(&program, "all_bits", &[0xdead_beef]),
+            Ok(ints(&[0xdead_beef]))
+        );
+    }
+
+    #[test]
+    fn allow_partial_skips_the_unsupported_functions_and_their_callers() {
+        let source = "define i32 @ok(i32 %x) {\n\
+                      \x20 %r = add i32 %x, 1\n\
+                      \x20 ret i32 %r\n\
+                      }\n\
+                      define i32 @bad(i32 %x) {\n\
+                      \x20 %f = uitofp i32 %x to float\n\
+                      \x20 %r = fptoui float %f to i32\n\
+                      \x20 ret i32 %r\n\
+                      }\n\
+                      define i32 @caller(i32 %x) {\n\
+                      \x20 %r = call i32 @bad(i32 %x)\n\
+                      \x20 ret i32 %r\n\
+                      }\n";
+        assert!(lower(source).is_err());
+        let config = Config {
+            allow_partial: true,
+            ..Config::default()
+        };
+        let program =
+            SierraBuilder::compile_source(source.as_bytes(), Path::new("test.ll"), &config)
+                .unwrap();
+        assert_eq!(
+            program
+                .funcs
+                .iter()
+                .map(|func| func.id.to_string())
+                .collect::<Vec<_>>(),
+            ["ok"]
+        );
+        assert_eq!(run(&program, "ok", &[1]), Ok(ints(&[2])));
+    }
+
+    #[test]
+    fn comparison_branched_on_and_extended_later() {
+        let program = lower(
+            "define i32 @f(i32 %a) {\n\
+             entry:\n\
+             \x20 %c = icmp ult i32 %a, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 %z = zext i1 %c to i32\n\
+             \x20 %r = add i32 %a, %z\n\
+             \x20 ret i32 %r\n\
+             big:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"dup<bool>".to_owned()));
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[4])));
+        assert_eq!(run(&program, "f", &[20]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn local_stored_in_the_entry_block_and_loaded_in_its_successors() {
+        let program = lower(
+            "define i32 @f(i32 %a) {\n\
+             entry:\n\
+             \x20 %p = alloca i32\n\
+             \x20 %d = mul i32 %a, 3\n\
+             \x20 store i32 %d, ptr %p\n\
+             \x20 %c = icmp ult i32 %a, 10\n\
+             \x20 br i1 %c, label %small, label %big\n\
+             small:\n\
+             \x20 %v = load i32, ptr %p\n\
+             \x20 ret i32 %v\n\
+             big:\n\
+             \x20 %w = load i32, ptr %p\n\
+             \x20 %r = sub i32 %w, %a\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        for libfunc in ["alloc_local<u32>", "finalize_locals", "store_local<u32>"] {
+            assert!(libfuncs.contains(&libfunc.to_owned()), "no {}", libfunc);
+        }
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[9])));
+        assert_eq!(run(&program, "f", &[12]), Ok(ints(&[24])));
+    }
+
+    #[test]
+    fn udiv_exact_by_a_const_drops_the_remainder_without_a_zero_check() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %q = udiv exact i32 %x, 4\n\
+             \x20 ret i32 %q\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(!libfuncs.contains(&"u32_is_zero".to_owned()));
+        assert!(libfuncs.contains(&"u32_safe_divmod".to_owned()));
+        assert!(libfuncs.contains(&"drop<u32>".to_owned()));
+        assert_eq!(run(&program, "f", &[12]), Ok(ints(&[3])));
+        assert_eq!(run(&program, "f", &[0]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn phi_receiving_the_same_value_from_two_blocks() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             entry:\n\
+             \x20 %c = icmp ult i32 %a, %b\n\
+             \x20 br i1 %c, label %lt, label %ge\n\
+             lt:\n\
+             \x20 br label %merge\n\
+             ge:\n\
+             \x20 br label %merge\n\
+             merge:\n\
+             \x20 %p = phi i32 [ %a, %lt ], [ %a, %ge ]\n\
+             \x20 %s = add i32 %p, %b\n\
+             \x20 ret i32 %s\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(run(&program, "f", &[1, 2]), Ok(ints(&[3])));
+        assert_eq!(run(&program, "f", &[5, 2]), Ok(ints(&[7])));
+    }
+
+    #[test]
+    fn i1_consts_are_bool_variants() {
+        let program = lower(
+            "define i1 @yes() {\n\
+             \x20 ret i1 true\n\
+             }\n\
+             define i1 @small_and_true(i32 %a) {\n\
+             \x20 %c = icmp ult i32 %a, 10\n\
+             \x20 %r = and i1 %c, true\n\
+             \x20 ret i1 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"enum_init<bool, 1>".to_owned()));
+        assert!(!libfuncs
+            .iter()
+            .any(|libfunc| libfunc.starts_with("u8_const")));
+        assert_eq!(run(&program, "yes", &[]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "small_and_true", &[3]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "small_and_true", &[30]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn return_of_a_type_other_than_the_signature_is_reported() {
+        let source = b"define i32 @f(i32 %a) {\n\
+                       \x20 %r = add i32 %a, 1\n\
+                       \x20 ret i32 %r\n\
+                       }\n";
+        let context = inkwell::context::Context::create();
+        let module = crate::parse_module(&context, source, Path::new("test.ll")).unwrap();
+        let mut builder = SierraBuilder::default();
+        SierraBuilder::lower_module(
+            &mut builder,
+            &context,
+            &module,
+            &Config::default(),
+            &HashSet::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(run(&builder.sierra.program, "f", &[41]), Ok(ints(&[42])));
+        // A lowering returning a `u32` from a function declared to return a `u64`, before the builtins are added to
+        // its signature.
+        let function = module.get_function("f").unwrap();
+        let ret = function
+            .get_last_basic_block()
+            .unwrap()
+            .get_terminator()
+            .unwrap();
+        builder.current_function = Some("f".to_owned());
+        builder.sierra.program.funcs[0].signature.ret_types =
+            vec![ConcreteTypeId::from_string("u64")];
+        let err = builder.build_return(ret).unwrap_err();
+        let LoweringError::ReturnTypeMismatch {
+            expected, found, ..
+        } = err
+        else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!((expected.as_str(), found.as_str()), ("u64", "u32"));
+    }
+
+    #[test]
+    fn load_from_a_const_table_at_a_const_index() {
+        let program = lower(
+            "@tbl = private constant [4 x i32] [i32 10, i32 20, i32 30, i32 40]\n\
+             define i32 @f(i32 %x) {\n\
+             \x20 %p = getelementptr [4 x i32], ptr @tbl, i64 0, i64 2\n\
+             \x20 %v = load i32, ptr %p\n\
+             \x20 %r = add i32 %v, %x\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(libfuncs(&program).contains(&"u32_const<30>".to_owned()));
+        assert_eq!(run(&program, "f", &[5]), Ok(ints(&[35])));
+    }
+
+    #[test]
+    fn truncated_call_result() {
+        let program = lower(
+            "define i64 @wide(i64 %x) {\n\
+             \x20 %r = mul i64 %x, 65536\n\
+             \x20 ret i64 %r\n\
+             }\n\
+             define i32 @f(i64 %x) {\n\
+             \x20 %r = call i64 @wide(i64 %x)\n\
+             \x20 %t = trunc i64 %r to i32\n\
+             \x20 ret i32 %t\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[3 << 16])));
+        assert_eq!(run(&program, "f", &[0x1_0003]), Ok(ints(&[3 << 16])));
+    }
+
+    #[test]
+    fn arithmetic_of_i3_is_masked_with_mask_narrow_ints() {
+        let source = "define i3 @f(i3 %a, i3 %b) {\n\
+                      \x20 %r = add i3 %a, %b\n\
+                      \x20 ret i3 %r\n\
+                      }\n";
+        let config = Config {
+            mask_narrow_ints: true,
+            ..Config::default()
+        };
+        let masked =
+            SierraBuilder::compile_source(source.as_bytes(), Path::new("test.ll"), &config)
+                .unwrap();
+        assert_eq!(run(&masked, "f", &[5, 6]), Ok(ints(&[3])));
+        assert_eq!(run(&masked, "f", &[1, 2]), Ok(ints(&[3])));
+        // Without the flag the result only wraps at the bounds of the `u8` it's lowered to.
+        let unmasked = lower(source).unwrap();
+        assert_eq!(run(&unmasked, "f", &[5, 6]), Ok(ints(&[11])));
+    }
+
+    #[test]
+    fn mutually_recursive_functions_withdraw_gas() {
+        let program = lower(
+            "define i1 @is_even(i32 %n) {\n\
+             entry:\n\
+             \x20 %z = icmp eq i32 %n, 0\n\
+             \x20 br i1 %z, label %zero, label %rec\n\
+             zero:\n\
+             \x20 ret i1 true\n\
+             rec:\n\
+             \x20 %m = sub i32 %n, 1\n\
+             \x20 %r = call i1 @is_odd(i32 %m)\n\
+             \x20 ret i1 %r\n\
+             }\n\
+             define i1 @is_odd(i32 %n) {\n\
+             entry:\n\
+             \x20 %z = icmp eq i32 %n, 0\n\
+             \x20 br i1 %z, label %zero, label %rec\n\
+             zero:\n\
+             \x20 ret i1 false\n\
+             rec:\n\
+             \x20 %m = sub i32 %n, 1\n\
+             \x20 %r = call i1 @is_even(i32 %m)\n\
+             \x20 ret i1 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            libfuncs(&program)
+                .iter()
+                .filter(|libfunc| *libfunc == "withdraw_gas")
+                .count(),
+            2
+        );
+        assert_eq!(run(&program, "is_even", &[4]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "is_even", &[7]), Ok(ints(&[0])));
+        assert_eq!(run(&program, "is_odd", &[7]), Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn bool_local_loaded_into_a_branch_condition() {
+        let program = lower(
+            "define i32 @f(i32 %x) {\n\
+             \x20 %p = alloca i1\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 store i1 %c, ptr %p\n\
+             \x20 %l = load i1, ptr %p\n\
+             \x20 br i1 %l, label %small, label %big\n\
+             small:\n\
+             \x20 ret i32 1\n\
+             big:\n\
+             \x20 ret i32 2\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(program
+            .type_declarations
+            .iter()
+            .any(|declaration| declaration.id.to_string() == "bool"));
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[1])));
+        assert_eq!(run(&program, "f", &[30]), Ok(ints(&[2])));
+    }
+
+    #[test]
+    fn named_struct_is_declared_with_its_fields() {
+        let program = lower(
+            "%struct.Foo = type { i32, i64 }\n\
+             define i64 @f(%struct.Foo %s) {\n\
+             \x20 %a = extractvalue %struct.Foo %s, 0\n\
+             \x20 %b = extractvalue %struct.Foo %s, 1\n\
+             \x20 %z = zext i32 %a to i64\n\
+             \x20 %r = add i64 %z, %b\n\
+             \x20 ret i64 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let declared = program
+            .type_declarations
+            .iter()
+            .map(|declaration| declaration.id.to_string())
+            .collect::<Vec<_>>();
+        assert!(declared.contains(&"Tuple<u32, u64>".to_owned()));
+        assert!(declared.contains(&"u32".to_owned()));
+        assert!(declared.contains(&"u64".to_owned()));
+        assert!(!declared.iter().any(|ty| ty.contains("Foo")));
+        assert_eq!(
+            program.funcs[0].signature.param_types[0].to_string(),
+            "Tuple<u32, u64>"
+        );
+    }
+
+    #[test]
+    fn phi_keeping_its_value_on_a_back_edge_is_not_stored_again() {
+        let program = lower(
+            "define i32 @f(i32 %n) {\n\
+             entry:\n\
+             \x20 br label %loop\n\
+             loop:\n\
+             \x20 %i = phi i32 [ 0, %entry ], [ %j, %body ]\n\
+             \x20 %k = phi i32 [ 5, %entry ], [ %k, %body ]\n\
+             \x20 %c = icmp ult i32 %i, %n\n\
+             \x20 br i1 %c, label %body, label %exit\n\
+             body:\n\
+             \x20 %j = add i32 %i, 1\n\
+             \x20 br label %loop\n\
+             exit:\n\
+             \x20 %r = add i32 %i, %k\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(!program.statements.iter().any(|statement| matches!(
+            statement,
+            GenStatement::Invocation(invocation)
+                if invocation.libfunc_id.to_string().starts_with("store_temp")
+                    && invocation.args == invocation.branches[0].results
+        )));
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[8])));
+    }
+
+    #[test]
+    fn comparisons_only_feeding_an_unused_and_are_elided() {
+        let program = lower(
+            "define i32 @f(i32 %x, i32 %y) {\n\
+             \x20 %a = icmp ult i32 %x, %y\n\
+             \x20 %b = icmp eq i32 %x, 3\n\
+             \x20 %c = and i1 %a, %b\n\
+             \x20 %r = add i32 %x, %y\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert!(!libfuncs(&program)
+            .iter()
+            .any(|libfunc| ["u32_lt", "u32_eq", "bool_and_impl"].contains(&libfunc.as_str())));
+        assert_eq!(run(&program, "f", &[3, 4]), Ok(ints(&[7])));
+    }
+
+    #[test]
+    fn field_of_a_nested_struct_local_is_loaded_through_a_gep() {
+        let program = lower(
+            "%struct.Inner = type { i32, i32 }\n\
+             %struct.Outer = type { i32, %struct.Inner }\n\
+             define i32 @f(i32 %x, i32 %y) {\n\
+             \x20 %p = alloca %struct.Outer\n\
+             \x20 %i0 = insertvalue %struct.Inner undef, i32 %x, 0\n\
+             \x20 %i1 = insertvalue %struct.Inner %i0, i32 %y, 1\n\
+             \x20 %o0 = insertvalue %struct.Outer undef, i32 7, 0\n\
+             \x20 %o1 = insertvalue %struct.Outer %o0, %struct.Inner %i1, 1\n\
+             \x20 store %struct.Outer %o1, ptr %p\n\
+             \x20 %q = getelementptr %struct.Outer, ptr %p, i32 0, i32 1, i32 1\n\
+             \x20 %r = load i32, ptr %q\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"struct_deconstruct<Tuple<u32, Tuple<u32, u32>>>".to_owned()));
+        assert!(libfuncs.contains(&"struct_deconstruct<Tuple<u32, u32>>".to_owned()));
+        assert_eq!(run(&program, "f", &[3, 4]), Ok(ints(&[4])));
+    }
+
+    #[test]
+    fn loop_header_phis_swapped_by_the_back_edge() {
+        let program = lower(
+            "define i32 @f(i32 %x, i32 %y, i32 %n) {\n\
+             entry:\n\
+             \x20 br label %loop\n\
+             loop:\n\
+             \x20 %i = phi i32 [ 0, %entry ], [ %j, %loop ]\n\
+             \x20 %a = phi i32 [ %x, %entry ], [ %b, %loop ]\n\
+             \x20 %b = phi i32 [ %y, %entry ], [ %a, %loop ]\n\
+             \x20 %j = add i32 %i, 1\n\
+             \x20 %c = icmp ult i32 %i, %n\n\
+             \x20 br i1 %c, label %loop, label %exit\n\
+             exit:\n\
+             \x20 ret i32 %a\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(run(&program, "f", &[3, 5, 0]), Ok(ints(&[3])));
+        assert_eq!(run(&program, "f", &[3, 5, 1]), Ok(ints(&[5])));
+        assert_eq!(run(&program, "f", &[3, 5, 2]), Ok(ints(&[3])));
+    }
+
+    #[test]
+    fn select_on_a_const_condition_is_its_chosen_operand() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %s = select i1 true, i32 %a, i32 %b\n\
+             \x20 %t = select i1 false, i32 %a, i32 %b\n\
+             \x20 %r = sub i32 %s, %t\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let direct = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             \x20 %r = sub i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(libfuncs(&program), libfuncs(&direct));
+        assert_eq!(run(&program, "f", &[7, 3]), Ok(ints(&[4])));
+    }
+
+    #[test]
+    fn locals_are_dropped_before_returning_a_scalar() {
+        let program = lower(
+            "define i64 @f(i64 %x) {\n\
+             \x20 %p = alloca i64\n\
+             \x20 %q = alloca i64\n\
+             \x20 store i64 %x, ptr %p\n\
+             \x20 %l = load i64, ptr %p\n\
+             \x20 %r = mul i64 %l, 3\n\
+             \x20 ret i64 %r\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        let ret = libfuncs.iter().rposition(|libfunc| libfunc == "return");
+        let drop = libfuncs.iter().rposition(|libfunc| libfunc == "drop<u64>");
+        let drop_uninitialized = libfuncs
+            .iter()
+            .rposition(|libfunc| libfunc == "drop<Uninitialized<u64>>");
+        assert!(drop.is_some() && drop < ret);
+        assert!(drop_uninitialized.is_some() && drop_uninitialized < ret);
+        assert_eq!(run(&program, "f", &[5]), Ok(ints(&[15])));
+    }
+
+    #[test]
+    fn call_to_a_user_variadic_function_is_reported() {
+        let err = lower(
+            "define i32 @sum(i32 %n, ...) {\n\
+             \x20 ret i32 %n\n\
+             }\n\
+             define i32 @f(i32 %x) {\n\
+             \x20 %r = call i32 (i32, ...) @sum(i32 1, i32 %x)\n\
+             \x20 ret i32 %r\n\
+             }\n",
+        )
+        .unwrap_err();
+        let LoweringError::VariadicCall { callee, span } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(callee, "sum");
+        assert_eq!(span.function, "f");
+    }
+
+    #[test]
+    fn add_of_operands_defined_in_two_earlier_blocks() {
+        let program = lower(
+            "define i32 @f(i32 %x, i32 %y) {\n\
+             entry:\n\
+             \x20 %a = mul i32 %x, 2\n\
+             \x20 br label %next\n\
+             next:\n\
+             \x20 %b = add i32 %y, 1\n\
+             \x20 %c = icmp ult i32 %a, %b\n\
+             \x20 br i1 %c, label %less, label %more\n\
+             less:\n\
+             \x20 %r = add i32 %a, %b\n\
+             \x20 ret i32 %r\n\
+             more:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        crate::validate::validate_program(&program).unwrap();
+        assert_eq!(run(&program, "f", &[2, 9]), Ok(ints(&[14])));
+        assert_eq!(run(&program, "f", &[9, 2]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn sub_compared_to_zero_is_an_equality() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b) {\n\
+             entry:\n\
+             \x20 %d = sub i32 %a, %b\n\
+             \x20 %c = icmp eq i32 %d, 0\n\
+             \x20 br i1 %c, label %same, label %other\n\
+             same:\n\
+             \x20 ret i32 1\n\
+             other:\n\
+             \x20 ret i32 2\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        assert!(libfuncs.contains(&"u32_eq".to_owned()));
+        assert!(!libfuncs
+            .iter()
+            .any(|libfunc| libfunc == "u32_overflowing_sub"));
+        assert_eq!(run(&program, "f", &[5, 5]), Ok(ints(&[1])));
+        // `3 - 5` wraps but isn't zero either.
+        assert_eq!(run(&program, "f", &[3, 5]), Ok(ints(&[2])));
+    }
+
+    #[test]
+    fn or_of_two_predicates_drives_a_branch() {
+        let program = lower(
+            "define i32 @f(i32 %a, i32 %b, i32 %c, i32 %d) {\n\
+             entry:\n\
+             \x20 %lt = icmp ult i32 %a, %b\n\
+             \x20 %eq = icmp eq i32 %c, %d\n\
+             \x20 %or = or i1 %lt, %eq\n\
+             \x20 br i1 %or, label %yes, label %no\n\
+             yes:\n\
+             \x20 %r = add i32 %a, %c\n\
+             \x20 ret i32 %r\n\
+             no:\n\
+             \x20 ret i32 0\n\
+             }\n",
+        )
+        .unwrap();
+        let libfuncs = libfuncs(&program);
+        for libfunc in ["u32_lt", "u32_eq", "bool_or_impl"] {
+            assert!(libfuncs.contains(&libfunc.to_owned()), "{}", libfunc);
+        }
+        assert_eq!(run(&program, "f", &[1, 2, 3, 4]), Ok(ints(&[4])));
+        assert_eq!(run(&program, "f", &[2, 1, 3, 3]), Ok(ints(&[5])));
+        assert_eq!(run(&program, "f", &[2, 1, 3, 4]), Ok(ints(&[0])));
+    }
+
+    #[test]
+    fn tuple_built_by_insertvalues_is_returned() {
+        let program = lower(
+            "define { i32, i1 } @f(i32 %x) {\n\
+             \x20 %y = add i32 %x, 1\n\
+             \x20 %c = icmp ult i32 %x, 10\n\
+             \x20 %a = insertvalue { i32, i1 } undef, i32 %y, 0\n\
+             \x20 %s = insertvalue { i32, i1 } %a, i1 %c, 1\n\
+             \x20 ret { i32, i1 } %s\n\
+             }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            program.funcs[0].signature.ret_types[0].to_string(),
+            "Tuple<u32, bool>"
+        );
+        assert!(libfuncs(&program).contains(&"struct_construct<Tuple<u32, bool>>".to_owned()));
+        assert_eq!(run(&program, "f", &[3]), Ok(ints(&[4, 1])));
+        assert_eq!(run(&program, "f", &[20]), Ok(ints(&[21, 0])));
     }
 }
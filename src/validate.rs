@@ -0,0 +1,74 @@
+use cairo_lang_sierra::extensions::core::{CoreLibfunc, CoreType};
+use cairo_lang_sierra::program::Program;
+use cairo_lang_sierra::program_registry::ProgramRegistry;
+use cairo_lang_sierra::ProgramParser;
+
+/// Type checks `program` against the sierra core library: every type and libfunc has to specialize and every
+/// statement has to match the signature of the libfunc it invokes.
+pub fn validate_program(program: &Program) -> Result<(), String> {
+    ProgramRegistry::<CoreType, CoreLibfunc>::new(program)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Parses the sierra program in the file at `path` and type checks it with [`validate_program`].
+pub fn validate_sierra_file(path: &str) -> Result<(), String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read `{}`: {}", path, err))?;
+    let program = ProgramParser::new()
+        .parse(&text)
+        .map_err(|err| format!("can't parse `{}`: {}", path, err))?;
+    validate_program(&program)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use num_bigint::BigInt;
+
+    use super::*;
+
+    /// Path of the file `name` at the root of the repository.
+    fn fixture(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(name)
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn valid_file_is_accepted_and_runs() {
+        let path = fixture("fib.sierra");
+        validate_sierra_file(&path).unwrap();
+        let program = ProgramParser::new()
+            .parse(&std::fs::read_to_string(path).unwrap())
+            .unwrap();
+        let args = [0, 1, 5].map(BigInt::from);
+        assert_eq!(
+            crate::interpret::run(&program, "fib", &args),
+            Ok(vec![BigInt::from(8)])
+        );
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let text = std::fs::read_to_string(fixture("fib.sierra")).unwrap();
+        let dir = std::env::temp_dir();
+        // The statements invoke `dup<u32>` without its declaration.
+        let undeclared = dir.join("llvm-to-sierra-undeclared-libfunc.sierra");
+        std::fs::write(
+            &undeclared,
+            text.replace("libfunc dup<u32> = dup<u32>;\n", ""),
+        )
+        .unwrap();
+        assert!(validate_sierra_file(undeclared.to_str().unwrap()).is_err());
+        let unparsable = dir.join("llvm-to-sierra-unparsable.sierra");
+        std::fs::write(&unparsable, "type u32 = ;\n").unwrap();
+        let err = validate_sierra_file(unparsable.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("can't parse"), "{}", err);
+        let err = validate_sierra_file(&fixture("missing.sierra")).unwrap_err();
+        assert!(err.starts_with("can't read"), "{}", err);
+    }
+}